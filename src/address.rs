@@ -1,11 +1,36 @@
 const CHARSET: &'static [u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const BASE58_ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
 const DEFAULT_PREFIX: &'static str = "bitcoincash";
 
+use crate::network::Network;
+
 #[derive(Clone, Debug)]
 pub enum AddressError {
     InvalidChecksum,
     InvalidBase32Letter(usize, u8),
     InvalidAddressType(u8),
+    InvalidBase58Letter(usize, u8),
+    InvalidLength(usize),
+    InvalidLegacyVersion(u8),
+    MixedCase,
+    EmptyPrefix,
+    InvalidPadding,
+    /// The reserved most significant bit of the version byte is set.
+    InvalidVersion(u8),
+    /// The hash has a length not supported by CashAddr.
+    InvalidHashSize(usize),
+    /// The version byte's size bits declare a different hash length (expected, actual).
+    HashSizeMismatch(usize, usize),
+}
+
+impl std::fmt::Display for AddressError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for AddressError {
+
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -14,6 +39,9 @@ pub enum AddressType {
     P2SH = 8,
 }
 
+/// Hash lengths in bytes, indexed by the size bits of the version byte.
+const HASH_SIZES: [usize; 8] = [20, 24, 28, 32, 40, 48, 56, 64];
+
 fn convert_bits(data: impl Iterator<Item=u8>, from_bits: u32, to_bits: u32, pad: bool) -> Option<Vec<u8>> {
     let mut acc = 0;
     let mut bits = 0;
@@ -95,8 +123,108 @@ fn b32_decode(string: &str) -> Result<Vec<u8>, AddressError> {
         .collect()
 }
 
-pub fn to_cash_addr(prefix: &str, addr_type: AddressType, addr_bytes: &[u8; 20]) -> String {
-    let version = addr_type as u8;
+fn b58_encode(data: &[u8]) -> String {
+    let n_zeros = data.iter().take_while(|x| **x == 0).count();
+    // base 58 digits, least significant first
+    let mut digits: Vec<u8> = Vec::new();
+    for byte in data[n_zeros..].iter() {
+        let mut carry = *byte as u32;
+        for digit in digits.iter_mut() {
+            carry += (*digit as u32) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+    let mut encoded = vec![BASE58_ALPHABET[0]; n_zeros];
+    encoded.extend(digits.iter().rev().map(|digit| BASE58_ALPHABET[*digit as usize]));
+    String::from_utf8(encoded).unwrap()
+}
+
+fn b58_decode(string: &str) -> Result<Vec<u8>, AddressError> {
+    let n_zeros = string.bytes().take_while(|x| *x == BASE58_ALPHABET[0]).count();
+    // bytes, least significant first
+    let mut bytes: Vec<u8> = Vec::new();
+    for (i, letter) in string.bytes().enumerate().skip(n_zeros) {
+        let mut carry = BASE58_ALPHABET.iter()
+            .position(|c| *c == letter)
+            .ok_or(AddressError::InvalidBase58Letter(i, letter))? as u32;
+        for byte in bytes.iter_mut() {
+            carry += *byte as u32 * 58;
+            *byte = (carry & 0xff) as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.push((carry & 0xff) as u8);
+            carry >>= 8;
+        }
+    }
+    let mut decoded = vec![0; n_zeros];
+    decoded.extend(bytes.into_iter().rev());
+    Ok(decoded)
+}
+
+fn legacy_version(prefix: &str, addr_type: AddressType) -> u8 {
+    let network = Network::from_prefix(prefix).unwrap_or(Network::Mainnet);
+    match addr_type {
+        AddressType::P2PKH => network.legacy_p2pkh_version(),
+        AddressType::P2SH => network.legacy_p2sh_version(),
+    }
+}
+
+/// Encodes the payload with a 4 byte double SHA256 checksum in base58, as used by legacy
+/// addresses and WIF.
+pub fn to_base58check(payload: &[u8]) -> String {
+    let mut data = payload.to_vec();
+    data.extend_from_slice(&crate::hash::double_sha256(payload)[..4]);
+    b58_encode(&data)
+}
+
+pub fn from_base58check(string: &str) -> Result<Vec<u8>, AddressError> {
+    let mut decoded = b58_decode(string)?;
+    if decoded.len() < 4 {
+        return Err(AddressError::InvalidLength(decoded.len()));
+    }
+    let checksum = decoded.split_off(decoded.len() - 4);
+    if crate::hash::double_sha256(&decoded)[..4] != checksum[..] {
+        return Err(AddressError::InvalidChecksum);
+    }
+    Ok(decoded)
+}
+
+pub fn to_legacy_addr(prefix: &str, addr_type: AddressType, addr_bytes: &[u8; 20]) -> String {
+    let mut payload = vec![legacy_version(prefix, addr_type)];
+    payload.extend_from_slice(addr_bytes);
+    to_base58check(&payload)
+}
+
+pub fn from_legacy_addr(addr_string: &str) -> Result<([u8; 20], AddressType, String), AddressError> {
+    let payload = from_base58check(addr_string)?;
+    if payload.len() != 21 {
+        return Err(AddressError::InvalidLength(payload.len() + 4));
+    }
+    // testnet and regtest share their versions, legacy addresses always decode as testnet
+    let (addr_type, prefix) = match payload[0] {
+        0x00 => (AddressType::P2PKH, Network::Mainnet.bch_prefix()),
+        0x05 => (AddressType::P2SH, Network::Mainnet.bch_prefix()),
+        0x6f => (AddressType::P2PKH, Network::Testnet.bch_prefix()),
+        0xc4 => (AddressType::P2SH, Network::Testnet.bch_prefix()),
+        version => return Err(AddressError::InvalidLegacyVersion(version)),
+    };
+    let mut addr = [0; 20];
+    addr.copy_from_slice(&payload[1..]);
+    Ok((addr, addr_type, prefix.to_string()))
+}
+
+pub fn to_cash_addr(prefix: &str, addr_type: AddressType, addr_bytes: &[u8])
+        -> Result<String, AddressError> {
+    let size_bits = HASH_SIZES.iter()
+        .position(|size| *size == addr_bytes.len())
+        .ok_or(AddressError::InvalidHashSize(addr_bytes.len()))?;
+    let version = addr_type as u8 | size_bits as u8;
     let payload = convert_bits(
         [version].iter().chain(addr_bytes.iter()).cloned(),
         8,
@@ -104,65 +232,113 @@ pub fn to_cash_addr(prefix: &str, addr_type: AddressType, addr_bytes: &[u8; 20])
         true,
     ).unwrap();
     let checksum = calculate_checksum(prefix, payload.iter().cloned());
-    String::from(prefix) + ":" + &b32_encode(payload.iter().cloned().chain(checksum.iter().cloned()))
+    Ok(String::from(prefix) + ":" +
+       &b32_encode(payload.iter().cloned().chain(checksum.iter().cloned())))
 }
 
-pub fn from_cash_addr(addr_string: &str) -> Result<([u8; 20], AddressType, String), AddressError> {
+pub fn from_cash_addr(addr_string: &str) -> Result<(Vec<u8>, AddressType, String), AddressError> {
+    from_cash_addr_default_prefix(addr_string, DEFAULT_PREFIX)
+}
+
+/// Decodes a CashAddr, using `default_prefix` if the address doesn't include a prefix.
+pub fn from_cash_addr_default_prefix(addr_string: &str, default_prefix: &str)
+        -> Result<(Vec<u8>, AddressType, String), AddressError> {
+    if addr_string.bytes().any(|c| c.is_ascii_lowercase()) &&
+            addr_string.bytes().any(|c| c.is_ascii_uppercase()) {
+        return Err(AddressError::MixedCase);
+    }
     let addr_string = addr_string.to_ascii_lowercase();
-    let (prefix, payload_base32) = if let Some(pos) = addr_string.find(":") {
-        let (prefix, payload_base32) = addr_string.split_at(pos + 1);
-        (&prefix[..prefix.len() - 1], payload_base32)
-    } else {
-        (&addr_string[..], DEFAULT_PREFIX)
+    let (prefix, payload_base32) = match addr_string.rfind(':') {
+        Some(pos) => (&addr_string[..pos], &addr_string[pos + 1..]),
+        None => (default_prefix, &addr_string[..]),
     };
+    if prefix.is_empty() {
+        return Err(AddressError::EmptyPrefix);
+    }
     let decoded = b32_decode(payload_base32)?;
+    if decoded.len() < 8 + 1 {
+        return Err(AddressError::InvalidLength(decoded.len()));
+    }
     if !verify_checksum(prefix, decoded.iter().cloned()) {
         return Err(AddressError::InvalidChecksum);
     }
-    let converted = convert_bits(decoded.iter().cloned(), 5, 8, true).unwrap();
-    let mut addr = [0; 20];
-    addr.copy_from_slice(&converted[1 .. converted.len()-6]);
-    Ok((
-        addr,
-        match converted[0] {
-            0 => AddressType::P2PKH,
-            8 => AddressType::P2SH,
-            x => return Err(AddressError::InvalidAddressType(x)),
-        },
-        prefix.to_string(),
-    ))
+    let converted = convert_bits(decoded[..decoded.len() - 8].iter().cloned(), 5, 8, false)
+        .ok_or(AddressError::InvalidPadding)?;
+    let (version, hash) = converted.split_first().ok_or(AddressError::InvalidLength(0))?;
+    if version & 0x80 != 0 {
+        return Err(AddressError::InvalidVersion(*version));
+    }
+    let addr_type = match version & 0x78 {
+        0 => AddressType::P2PKH,
+        8 => AddressType::P2SH,
+        _ => return Err(AddressError::InvalidAddressType(*version)),
+    };
+    let expected_size = HASH_SIZES[(version & 0x07) as usize];
+    if hash.len() != expected_size {
+        return Err(AddressError::HashSizeMismatch(expected_size, hash.len()));
+    }
+    Ok((hash.to_vec(), addr_type, prefix.to_string()))
 }
 
 #[derive(Clone, Debug)]
 pub struct Address {
     addr_type: AddressType,
-    bytes: [u8; 20],
+    bytes: Vec<u8>,
     cash_addr: String,
     prefix: String,
 }
 
 impl Address {
     pub fn from_bytes(addr_type: AddressType, bytes: [u8; 20]) -> Self {
-        Address {
-            cash_addr: to_cash_addr(DEFAULT_PREFIX, addr_type, &bytes),
-            addr_type,
-            prefix: DEFAULT_PREFIX.to_string(),
-            bytes,
-        }
+        Address::from_bytes_prefix(DEFAULT_PREFIX, addr_type, bytes)
     }
 
     pub fn from_bytes_prefix(prefix: &str, addr_type: AddressType, bytes: [u8; 20]) -> Self {
-        Address {
-            cash_addr: to_cash_addr(prefix, addr_type, &bytes),
+        Address::from_hash_prefix(prefix, addr_type, bytes.to_vec()).unwrap()
+    }
+
+    /// Creates an address from a hash of any size supported by CashAddr (160 to 512 bits).
+    pub fn from_hash_prefix(prefix: &str, addr_type: AddressType, bytes: Vec<u8>)
+            -> Result<Self, AddressError> {
+        Ok(Address {
+            cash_addr: to_cash_addr(prefix, addr_type, &bytes)?,
             addr_type,
             prefix: prefix.to_string(),
             bytes,
-        }
+        })
     }
 
     pub fn from_cash_addr(cash_addr: String) -> Result<Self, AddressError> {
         let (bytes, addr_type, prefix) = from_cash_addr(&cash_addr)?;
-        Ok(Address { bytes, addr_type, cash_addr, prefix })
+        Address::from_hash_prefix(&prefix, addr_type, bytes)
+    }
+
+    /// Parses a CashAddr. If it lacks a prefix, the BCH and then the SLP prefix of `network`
+    /// are tried.
+    pub fn from_cash_addr_network(cash_addr: &str, network: Network) -> Result<Self, AddressError> {
+        let (bytes, addr_type, prefix) =
+            from_cash_addr_default_prefix(cash_addr, network.bch_prefix())
+                .or_else(|err| match err {
+                    AddressError::InvalidChecksum if !cash_addr.contains(':') =>
+                        from_cash_addr_default_prefix(cash_addr, network.slp_prefix()),
+                    err => Err(err),
+                })?;
+        Address::from_hash_prefix(&prefix, addr_type, bytes)
+    }
+
+    pub fn from_legacy(legacy_addr: &str) -> Result<Self, AddressError> {
+        let (bytes, addr_type, prefix) = from_legacy_addr(legacy_addr)?;
+        Ok(Address::from_bytes_prefix(&prefix, addr_type, bytes))
+    }
+
+    /// Parses either a legacy base58check address (starting with 1, 3, m, n or 2) or a CashAddr,
+    /// which may omit its prefix if it belongs to `network`.
+    pub fn from_any(addr_string: &str, network: Network) -> Result<Self, AddressError> {
+        match addr_string.chars().next() {
+            Some('1') | Some('3') | Some('m') | Some('n') | Some('2') =>
+                Address::from_legacy(addr_string),
+            _ => Address::from_cash_addr_network(addr_string, network),
+        }
     }
 
     pub fn from_pub_key(prefix: &str, pub_key: &secp256k1::PublicKey) -> Self {
@@ -170,7 +346,7 @@ impl Address {
                                    crate::hash::hash160(&pub_key.serialize()))
     }
 
-    pub fn bytes(&self) -> &[u8; 20] {
+    pub fn bytes(&self) -> &[u8] {
         &self.bytes
     }
 
@@ -178,6 +354,16 @@ impl Address {
         &self.cash_addr
     }
 
+    /// Legacy addresses only exist for 160 bit hashes, for other sizes this returns None.
+    pub fn legacy_addr(&self) -> Option<String> {
+        if self.bytes.len() != 20 {
+            return None;
+        }
+        let mut bytes = [0; 20];
+        bytes.copy_from_slice(&self.bytes);
+        Some(to_legacy_addr(&self.prefix, self.addr_type, &bytes))
+    }
+
     pub fn addr_type(&self) -> AddressType {
         self.addr_type
     }
@@ -185,4 +371,229 @@ impl Address {
     pub fn prefix(&self) -> &str {
         &self.prefix
     }
+
+    /// Returns the network of the address' prefix, or None for unknown prefixes.
+    pub fn network(&self) -> Option<Network> {
+        Network::from_prefix(&self.prefix)
+    }
+
+    /// Whether the address has the prefix for SLP tokens on its network, e.g. 'simpleledger'.
+    pub fn is_slp(&self) -> bool {
+        self.network().map(|network| network.slp_prefix() == self.prefix).unwrap_or(false)
+    }
+
+    /// Returns the same address with a different prefix.
+    pub fn with_prefix(&self, prefix: &str) -> Address {
+        Address::from_hash_prefix(prefix, self.addr_type, self.bytes.clone()).unwrap()
+    }
+
+    /// Converts the address to its SLP form, e.g. 'bitcoincash:' to 'simpleledger:'.
+    /// Returns None if the address' prefix doesn't belong to a known network.
+    pub fn to_slp(&self) -> Option<Address> {
+        Some(self.with_prefix(self.network()?.slp_prefix()))
+    }
+
+    /// Converts the address to its BCH form, e.g. 'simpleledger:' to 'bitcoincash:'.
+    /// Returns None if the address' prefix doesn't belong to a known network.
+    pub fn to_bch(&self) -> Option<Address> {
+        Some(self.with_prefix(self.network()?.bch_prefix()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test vectors from the CashAddr specification: the hash, its P2PKH address with the
+    /// 'bitcoincash' prefix and its P2SH address with the 'bchtest' prefix.
+    const SPEC_VECTORS: [(&str, &str, &str); 8] = [
+        ("F5BF48B397DAE70BE82B3CCA4793F8EB2B6CDAC9",
+         "bitcoincash:qr6m7j9njldwwzlg9v7v53unlr4jkmx6eylep8ekg2",
+         "bchtest:pr6m7j9njldwwzlg9v7v53unlr4jkmx6eyvwc0uz5t"),
+        ("7ADBF6C17084BC86C1706827B41A56F5CA32865925E946EA",
+         "bitcoincash:q9adhakpwzztepkpwp5z0dq62m6u5v5xtyj7j3h2ws4mr9g0",
+         "bchtest:p9adhakpwzztepkpwp5z0dq62m6u5v5xtyj7j3h2u94tsynr"),
+        ("3A84F9CF51AAE98A3BB3A78BF16A6183790B18719126325BFC0C075B",
+         "bitcoincash:qgagf7w02x4wnz3mkwnchut2vxphjzccwxgjvvjmlsxqwkcw59jxxuz",
+         "bchtest:pgagf7w02x4wnz3mkwnchut2vxphjzccwxgjvvjmlsxqwkcvs7md7wt"),
+        ("3173EF6623C6B48FFD1A3DCC0CC6489B0A07BB47A37F47CFEF4FE69DE825C060",
+         "bitcoincash:qvch8mmxy0rtfrlarg7ucrxxfzds5pamg73h7370aa87d80gyhqxq5nlegake",
+         "bchtest:pvch8mmxy0rtfrlarg7ucrxxfzds5pamg73h7370aa87d80gyhqxq7fqng6m6"),
+        ("C07138323E00FA4FC122D3B85B9628EA810B3F381706385E289B0B25631197D194B5C238BEB136FB",
+         "bitcoincash:qnq8zwpj8cq05n7pytfmskuk9r4gzzel8qtsvwz79zdskftrzxtar994cgutavfklv39gr3uvz",
+         "bchtest:pnq8zwpj8cq05n7pytfmskuk9r4gzzel8qtsvwz79zdskftrzxtar994cgutavfklvmgm6ynej"),
+        ("E361CA9A7F99107C17A622E047E3745D3E19CF804ED63C5C40C6BA763696B98241223D8CE62AD48D863F4C\
+          B18C930E4C",
+         "bitcoincash:qh3krj5607v3qlqh5c3wq3lrw3wnuxw0sp8dv0zugrrt5a3kj6ucysfz8kxwv2k53krr7n933jf\
+          sunqex2w82sl",
+         "bchtest:ph3krj5607v3qlqh5c3wq3lrw3wnuxw0sp8dv0zugrrt5a3kj6ucysfz8kxwv2k53krr7n933jfsunq\
+          nzf7mt6x"),
+        ("D9FA7C4C6EF56DC4FF423BAAE6D495DBFF663D034A72D1DC7D52CBFE7D1E6858F9D523AC0A7A5C34077638\
+          E4DD1A701BD017842789982041",
+         "bitcoincash:qmvl5lzvdm6km38lgga64ek5jhdl7e3aqd9895wu04fvhlnare5937w4ywkq57juxsrhvw8ym5d8\
+          qx7sz7zz0zvcypqscw8jd03f",
+         "bchtest:pmvl5lzvdm6km38lgga64ek5jhdl7e3aqd9895wu04fvhlnare5937w4ywkq57juxsrhvw8ym5d8qx7s\
+          z7zz0zvcypqs6kgdsg2g"),
+        ("D0F346310D5513D9E01E299978624BA883E6BDA8F4C60883C10F28C2967E67EC77ECC7EEEAEAFC6DA89FAD\
+          72D11AC961E164678B868AEEEC5F2C1DA08884175B",
+         "bitcoincash:qlg0x333p4238k0qrc5ej7rzfw5g8e4a4r6vvzyrcy8j3s5k0en7calvclhw46hudk5flttj6yd\
+          vjc0pv3nchp52amk97tqa5zygg96mtky5sv5w",
+         "bchtest:plg0x333p4238k0qrc5ej7rzfw5g8e4a4r6vvzyrcy8j3s5k0en7calvclhw46hudk5flttj6ydvjc0\
+          pv3nchp52amk97tqa5zygg96mc773cwez"),
+    ];
+
+    /// Encodes a payload with an arbitrary version byte, bypassing the checks of to_cash_addr.
+    fn encode_raw(prefix: &str, version: u8, hash: &[u8]) -> String {
+        let payload = convert_bits(
+            std::iter::once(version).chain(hash.iter().cloned()),
+            8,
+            5,
+            true,
+        ).unwrap();
+        let checksum = calculate_checksum(prefix, payload.iter().cloned());
+        format!("{}:{}", prefix, b32_encode(payload.into_iter().chain(checksum)))
+    }
+
+    #[test]
+    fn spec_vectors_encode() {
+        for (hash_hex, p2pkh_addr, p2sh_addr) in SPEC_VECTORS.iter() {
+            let hash = hex::decode(hash_hex).unwrap();
+            assert_eq!(to_cash_addr("bitcoincash", AddressType::P2PKH, &hash).unwrap(),
+                       *p2pkh_addr);
+            assert_eq!(to_cash_addr("bchtest", AddressType::P2SH, &hash).unwrap(), *p2sh_addr);
+        }
+    }
+
+    #[test]
+    fn spec_vectors_decode() {
+        let mut sizes = Vec::new();
+        for (hash_hex, p2pkh_addr, p2sh_addr) in SPEC_VECTORS.iter() {
+            let hash = hex::decode(hash_hex).unwrap();
+            let (bytes, addr_type, prefix) = from_cash_addr(p2pkh_addr).unwrap();
+            assert_eq!(bytes, hash);
+            assert!(matches!(addr_type, AddressType::P2PKH));
+            assert_eq!(prefix, "bitcoincash");
+            let (bytes, addr_type, prefix) = from_cash_addr(p2sh_addr).unwrap();
+            assert_eq!(bytes, hash);
+            assert!(matches!(addr_type, AddressType::P2SH));
+            assert_eq!(prefix, "bchtest");
+            let (bytes, _, _) = from_cash_addr(&p2sh_addr.to_ascii_uppercase()).unwrap();
+            assert_eq!(bytes, hash);
+            sizes.push(hash.len());
+        }
+        assert_eq!(sizes, HASH_SIZES);
+    }
+
+    #[test]
+    fn rejects_mixed_case() {
+        let addr = "bitcoincash:qr6m7j9njldwwzlg9v7v53unlr4jkmx6eylep8ekG2";
+        assert!(matches!(from_cash_addr(addr), Err(AddressError::MixedCase)));
+        let addr = "BitcoinCash:qr6m7j9njldwwzlg9v7v53unlr4jkmx6eylep8ekg2";
+        assert!(matches!(from_cash_addr(addr), Err(AddressError::MixedCase)));
+    }
+
+    #[test]
+    fn rejects_wrong_size_bits() {
+        let hash = hex::decode(SPEC_VECTORS[0].0).unwrap();
+        // size bits of a 192 bit hash
+        let addr = encode_raw("bitcoincash", 0x01, &hash);
+        assert!(matches!(from_cash_addr(&addr), Err(AddressError::HashSizeMismatch(24, 20))));
+        let hash = hex::decode(SPEC_VECTORS[7].0).unwrap();
+        // size bits of a 160 bit hash
+        let addr = encode_raw("bitcoincash", 0x08, &hash);
+        assert!(matches!(from_cash_addr(&addr), Err(AddressError::HashSizeMismatch(20, 64))));
+    }
+
+    #[test]
+    fn rejects_reserved_version_bit() {
+        let hash = hex::decode(SPEC_VECTORS[0].0).unwrap();
+        let addr = encode_raw("bitcoincash", 0x80, &hash);
+        assert!(matches!(from_cash_addr(&addr), Err(AddressError::InvalidVersion(0x80))));
+    }
+
+    #[test]
+    fn rejects_bad_checksum() {
+        let addr = "bitcoincash:qr6m7j9njldwwzlg9v7v53unlr4jkmx6eylep8ekg3";
+        assert!(matches!(from_cash_addr(addr), Err(AddressError::InvalidChecksum)));
+        // valid checksum for a different prefix
+        let addr = "bchtest:qr6m7j9njldwwzlg9v7v53unlr4jkmx6eylep8ekg2";
+        assert!(matches!(from_cash_addr(addr), Err(AddressError::InvalidChecksum)));
+    }
+
+    #[test]
+    fn missing_prefix_uses_hint() {
+        let hash = hex::decode(SPEC_VECTORS[0].0).unwrap();
+        let addr = "qr6m7j9njldwwzlg9v7v53unlr4jkmx6eylep8ekg2";
+        let (bytes, _, prefix) = from_cash_addr_default_prefix(addr, "bitcoincash").unwrap();
+        assert_eq!(bytes, hash);
+        assert_eq!(prefix, "bitcoincash");
+        let addr = "pr6m7j9njldwwzlg9v7v53unlr4jkmx6eyvwc0uz5t";
+        let (_, addr_type, prefix) = from_cash_addr_default_prefix(addr, "bchtest").unwrap();
+        assert!(matches!(addr_type, AddressType::P2SH));
+        assert_eq!(prefix, "bchtest");
+        assert!(matches!(from_cash_addr_default_prefix(addr, "bitcoincash"),
+                         Err(AddressError::InvalidChecksum)));
+        assert!(matches!(from_cash_addr(":qr6m7j9njldwwzlg9v7v53unlr4jkmx6eylep8ekg2"),
+                         Err(AddressError::EmptyPrefix)));
+    }
+
+    /// Legacy addresses of the hash from the CashAddr specification's translation examples.
+    const LEGACY_HASH: &str = "76a04053bda0a88bda5177b86a15c3b29f559873";
+    const LEGACY_VECTORS: [(&str, AddressType, &str); 4] = [
+        ("1BpEi6DfDAUFd7GtittLSdBeYJvcoaVggu", AddressType::P2PKH, "bitcoincash"),
+        ("3CWFddi6m4ndiGyKqzYvsFYagqDLPVMTzC", AddressType::P2SH, "bitcoincash"),
+        ("mrLC19Je2BuWQDkWSTriGYPyQJXKkkBmCx", AddressType::P2PKH, "bchtest"),
+        ("2N44ThNe8NXHyv4bsX8AoVCXquBRW94Ls7W", AddressType::P2SH, "bchtest"),
+    ];
+
+    #[test]
+    fn legacy_vectors() {
+        let mut hash = [0; 20];
+        hash.copy_from_slice(&hex::decode(LEGACY_HASH).unwrap());
+        for (legacy_addr, addr_type, prefix) in LEGACY_VECTORS.iter() {
+            assert_eq!(to_legacy_addr(prefix, *addr_type, &hash), *legacy_addr);
+            let (bytes, decoded_type, decoded_prefix) = from_legacy_addr(legacy_addr).unwrap();
+            assert_eq!(bytes, hash);
+            assert_eq!(decoded_type as u8, *addr_type as u8);
+            assert_eq!(decoded_prefix, *prefix);
+            let addr = Address::from_any(legacy_addr, Network::Mainnet).unwrap();
+            assert_eq!(addr.legacy_addr().as_deref(), Some(*legacy_addr));
+        }
+        assert_eq!(Address::from_legacy(LEGACY_VECTORS[0].0).unwrap().cash_addr(),
+                   "bitcoincash:qpm2qsznhks23z7629mms6s4cwef74vcwvy22gdx6a");
+        assert_eq!(Address::from_legacy(LEGACY_VECTORS[1].0).unwrap().cash_addr(),
+                   "bitcoincash:ppm2qsznhks23z7629mms6s4cwef74vcwvn0h829pq");
+    }
+
+    #[test]
+    fn legacy_leading_zeros() {
+        // each leading zero byte is encoded as a '1'
+        assert_eq!(to_legacy_addr("bitcoincash", AddressType::P2PKH, &[0; 20]),
+                   "1111111111111111111114oLvT2");
+        assert_eq!(from_base58check("1111111111111111111114oLvT2").unwrap(), vec![0; 21]);
+        let mut hash = [0; 20];
+        hash[19] = 1;
+        assert_eq!(to_legacy_addr("bitcoincash", AddressType::P2PKH, &hash),
+                   "11111111111111111111BZbvjr");
+        assert_eq!(from_legacy_addr("11111111111111111111BZbvjr").unwrap().0, hash);
+        assert_eq!(to_base58check(&[0]), "1Wh4bh");
+        assert_eq!(from_base58check("1Wh4bh").unwrap(), vec![0]);
+    }
+
+    #[test]
+    fn legacy_rejects_invalid() {
+        assert!(matches!(from_legacy_addr("1BpEi6DfDAUFd7GtittLSdBeYJvcoaVggv"),
+                         Err(AddressError::InvalidChecksum)));
+        assert!(matches!(from_legacy_addr("1BpEi6DfDAUFd7GtittLSdBeYJvcoaVgg0"),
+                         Err(AddressError::InvalidBase58Letter(33, b'0'))));
+        let mut payload = hex::decode(LEGACY_HASH).unwrap();
+        payload.insert(0, 0x30);
+        assert!(matches!(from_legacy_addr(&to_base58check(&payload)),
+                         Err(AddressError::InvalidLegacyVersion(0x30))));
+        payload.push(0);
+        assert!(matches!(from_legacy_addr(&to_base58check(&payload)),
+                         Err(AddressError::InvalidLength(26))));
+        assert!(matches!(from_base58check("1Wh4b"), Err(AddressError::InvalidChecksum)));
+        assert!(matches!(from_base58check("111"), Err(AddressError::InvalidLength(3))));
+    }
 }
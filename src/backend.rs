@@ -0,0 +1,359 @@
+use crate::address::{Address, AddressType};
+use crate::merkle_block::{MerkleBlock, TxProof};
+use crate::network::Network;
+use crate::outputs::TradeOfferOutput;
+use crate::script::{Op, OpCodeType, Script};
+use crate::tx::Tx;
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::time::Duration;
+use byteorder::{BigEndian, ReadBytesExt};
+
+
+/// Maximum number of items per bulk request to the REST API.
+const MAX_ITEMS_PER_REQUEST: usize = 20;
+/// The node's error message for proofs of transactions which aren't in a block yet.
+pub(crate) const TX_NOT_IN_BLOCK_ERROR: &str = "Transaction not yet in block";
+/// How often backends without notifications are asked for changes of an address.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+pub enum BackendError {
+    HttpError(reqwest::Error),
+    /// The backend refused to broadcast a transaction, with its response.
+    BroadcastRejected(String),
+    InvalidResponse(String),
+    /// A JSON-RPC call failed with the given error code and message.
+    RpcError(i64, String),
+    /// The cookie file for JSON-RPC authentication couldn't be read.
+    CookieError(std::io::Error),
+    /// The connection to the backend's server failed or was closed.
+    ConnectionError(std::io::Error),
+    TlsError(String),
+    PeerError(crate::message_error::MessageError),
+}
+
+impl From<reqwest::Error> for BackendError {
+    fn from(err: reqwest::Error) -> Self {
+        BackendError::HttpError(err)
+    }
+}
+
+impl std::fmt::Display for BackendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BackendError::HttpError(err) => write!(f, "HTTP error: {}", err),
+            BackendError::BroadcastRejected(response) =>
+                write!(f, "Broadcasting transaction failed: {}", response),
+            BackendError::InvalidResponse(response) =>
+                write!(f, "Invalid response from backend: {}", response),
+            BackendError::RpcError(code, message) =>
+                write!(f, "RPC error {}: {}", code, message),
+            BackendError::CookieError(err) => write!(f, "Couldn't read RPC cookie file: {}", err),
+            BackendError::ConnectionError(err) => write!(f, "Connection error: {}", err),
+            BackendError::TlsError(err) => write!(f, "TLS error: {}", err),
+            BackendError::PeerError(err) => write!(f, "P2P error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for BackendError {
+
+}
+
+/// Whether a UTXO's transaction is proven to be in a block of our header chain.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum UtxoVerification {
+    /// Not checked, e.g. because there are no stored headers.
+    #[default]
+    Unverified,
+    /// The backend has no merkle proof, usually because the transaction isn't in a block yet.
+    Unconfirmed,
+    /// The proof's block isn't in our header chain, which might not be synced far enough.
+    UnknownBlock,
+    Verified,
+    /// The backend sent a proof which doesn't prove the transaction's inclusion.
+    Invalid,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct UtxoEntry {
+    pub txid: String,
+    pub vout: u32,
+    pub amount: f64,
+    pub satoshis: u64,
+    /// Height of the block the transaction is in, only set if it's verified.
+    #[serde(skip)]
+    pub confirmation_height: Option<u32>,
+    #[serde(skip)]
+    pub verification: UtxoVerification,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct TokenEntry {
+    pub id: String,
+    pub timestamp: String,
+    pub symbol: Option<String>,
+    pub name: Option<String>,
+    #[serde(alias = "documentUri")]
+    pub document_uri: Option<String>,
+    #[serde(alias = "documentHash")]
+    pub document_hash: Option<String>,
+    pub decimals: u64,
+    #[serde(alias = "initialTokenQty")]
+    pub initial_token_qty: f64,
+}
+
+/// Source of blockchain data and sink for transactions, e.g. an indexer's API or a node.
+pub trait Backend {
+    /// Returns the UTXOs of each of the addresses, in the same order.
+    fn get_utxos(&self, addresses: &[Address]) -> Result<Vec<Vec<UtxoEntry>>, BackendError>;
+
+    /// Returns for each of the addresses whether it ever received a transaction.
+    fn are_addresses_used(&self, addresses: &[Address]) -> Result<Vec<bool>, BackendError>;
+
+    /// Broadcasts the transaction and returns its txid.
+    fn broadcast(&self, tx: &Tx) -> Result<String, BackendError>;
+
+    /// Returns the transactions with the given txids, skipping unknown ones.
+    fn get_txs(&self, tx_ids: &[String]) -> Result<Vec<Tx>, BackendError>;
+
+    fn is_output_unspent(&self, tx_id: &str, output_idx: u32) -> Result<bool, BackendError>;
+
+    /// Returns a merkle proof of the block including the transaction, or None if the
+    /// transaction isn't in a block yet.
+    fn get_tx_proof(&self, tx_id: &str) -> Result<Option<TxProof>, BackendError>;
+
+    /// Returns the txids of the given transactions which are valid SLP transactions.
+    fn validate_slp_txids(&self, tx_ids: &[String]) -> Result<Vec<String>, BackendError>;
+
+    /// Returns the details of the tokens with the given token ids, skipping unknown ones.
+    fn token_info(&self, token_ids: &[String]) -> Result<Vec<TokenEntry>, BackendError>;
+
+    fn list_tokens(&self) -> Result<Vec<TokenEntry>, BackendError>;
+
+    /// Returns all trade offers (EXCH SELL outputs) on the network, without validating them.
+    fn find_trade_offers(&self) -> Result<Vec<TradeOfferOutput>, BackendError>;
+
+    /// Blocks until the address might have received a transaction or one of its transactions
+    /// might have been confirmed, but at most for `timeout`. Returns false if nothing happened.
+    /// Backends without notifications wait for up to a second and return true, so callers have
+    /// to check the address again either way.
+    fn wait_for_address_change(&self, _address: &Address, timeout: Duration)
+            -> Result<bool, BackendError> {
+        std::thread::sleep(timeout.min(POLL_INTERVAL));
+        Ok(true)
+    }
+}
+
+/// The output script paying to the address.
+pub fn address_script(address: &Address) -> Script {
+    match address.addr_type() {
+        AddressType::P2PKH => Script::new(vec![
+            Op::Code(OpCodeType::OpDup),
+            Op::Code(OpCodeType::OpHash160),
+            Op::Push(address.bytes().to_vec()),
+            Op::Code(OpCodeType::OpEqualVerify),
+            Op::Code(OpCodeType::OpCheckSig),
+        ]),
+        AddressType::P2SH => Script::new(vec![
+            Op::Code(OpCodeType::OpHash160),
+            Op::Push(address.bytes().to_vec()),
+            Op::Code(OpCodeType::OpEqual),
+        ]),
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+struct UtxoResult {
+    utxos: Vec<UtxoEntry>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+struct AddressDetails {
+    transactions: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+struct SlpTxValidity {
+    txid: String,
+    valid: bool,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+struct TxOutEntry {
+    value: f64,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+struct TradeEntryTx {
+    h: String,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+struct TradeEntryOut {
+    h1: Option<String>,
+    h2: Option<String>,
+    h3: Option<String>,
+    h4: Option<String>,
+    h5: Option<String>,
+    h6: Option<String>,
+    h7: Option<String>,
+    h8: Option<String>,
+    h9: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+struct TradeEntry {
+    tx: TradeEntryTx,
+    out: Vec<TradeEntryOut>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+struct TradesResult {
+    c: Vec<TradeEntry>,
+}
+
+/// Backend using the rest.bitcoin.com and bitdb.bitcoin.com APIs.
+pub struct RestBackend {
+    network: Network,
+    client: reqwest::Client,
+}
+
+impl RestBackend {
+    pub fn new(network: Network) -> Self {
+        RestBackend {
+            network,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    fn post_bulk<T, R>(&self, path: &str, key: &str, items: &[T]) -> Result<Vec<R>, BackendError>
+            where T: Serialize, for<'de> R: Deserialize<'de> {
+        let mut results = Vec::new();
+        for chunk in items.chunks(MAX_ITEMS_PER_REQUEST) {
+            let mut chunk_results: Vec<R> = self.client
+                .post(&format!("{}{}", self.network.rest_url(), path))
+                .json(&vec![(key, chunk)].into_iter().collect::<HashMap<_, _>>())
+                .send()?
+                .json()?;
+            results.append(&mut chunk_results);
+        }
+        Ok(results)
+    }
+}
+
+fn hex_bytes(hex_str: &Option<String>, len: usize) -> Option<Vec<u8>> {
+    hex::decode(hex_str.as_ref()?).ok().filter(|bytes| bytes.len() == len)
+}
+
+impl Backend for RestBackend {
+    fn get_utxos(&self, addresses: &[Address]) -> Result<Vec<Vec<UtxoEntry>>, BackendError> {
+        let cash_addrs = addresses.iter().map(Address::cash_addr).collect::<Vec<_>>();
+        let results: Vec<UtxoResult> = self.post_bulk("address/utxo", "addresses", &cash_addrs)?;
+        Ok(results.into_iter().map(|result| result.utxos).collect())
+    }
+
+    fn are_addresses_used(&self, addresses: &[Address]) -> Result<Vec<bool>, BackendError> {
+        let cash_addrs = addresses.iter().map(Address::cash_addr).collect::<Vec<_>>();
+        let details: Vec<AddressDetails> =
+            self.post_bulk("address/details", "addresses", &cash_addrs)?;
+        Ok(details.iter().map(|details| !details.transactions.is_empty()).collect())
+    }
+
+    fn broadcast(&self, tx: &Tx) -> Result<String, BackendError> {
+        let mut tx_ser = Vec::new();
+        tx.write_to_stream(&mut tx_ser).unwrap();
+        let response = self.client.get(&format!(
+            "{}rawtransactions/sendRawTransaction/{}",
+            self.network.rest_url(),
+            hex::encode(&tx_ser),
+        )).send()?.text()?;
+        let tx_id = tx.txid();
+        if response.trim().trim_matches('"') != tx_id {
+            return Err(BackendError::BroadcastRejected(response));
+        }
+        Ok(tx_id)
+    }
+
+    fn get_txs(&self, tx_ids: &[String]) -> Result<Vec<Tx>, BackendError> {
+        let txs_hex: Vec<String> =
+            self.post_bulk("rawtransactions/getRawTransaction", "txids", tx_ids)?;
+        Ok(txs_hex.iter().filter_map(|tx_hex| Tx::from_hex(tx_hex).ok()).collect())
+    }
+
+    fn is_output_unspent(&self, tx_id: &str, output_idx: u32) -> Result<bool, BackendError> {
+        let tx_out: Option<TxOutEntry> = self.client.get(&format!(
+            "{}blockchain/getTxOut/{}/{}?include_mempool=true",
+            self.network.rest_url(), tx_id, output_idx,
+        )).send()?.json()?;
+        Ok(tx_out.is_some())
+    }
+
+    fn get_tx_proof(&self, tx_id: &str) -> Result<Option<TxProof>, BackendError> {
+        let mut response = self.client.get(&format!(
+            "{}blockchain/getTxOutProof/{}",
+            self.network.rest_url(), tx_id,
+        )).send()?;
+        // the API responds with an internal error containing the node's error message
+        if response.status() == reqwest::StatusCode::INTERNAL_SERVER_ERROR {
+            let body = response.text()?;
+            if body.contains(TX_NOT_IN_BLOCK_ERROR) {
+                return Ok(None);
+            }
+            return Err(BackendError::InvalidResponse(body));
+        }
+        let proof_hex: String = response.error_for_status()?.json()?;
+        let merkle_block = MerkleBlock::from_hex(&proof_hex)
+            .map_err(|err| BackendError::InvalidResponse(err.to_string()))?;
+        Ok(Some(TxProof::MerkleBlock(merkle_block)))
+    }
+
+    fn validate_slp_txids(&self, tx_ids: &[String]) -> Result<Vec<String>, BackendError> {
+        let validities: Vec<SlpTxValidity> = self.post_bulk("slp/validateTxid", "txids", tx_ids)?;
+        Ok(validities.into_iter()
+            .filter(|validity| validity.valid)
+            .map(|validity| validity.txid)
+            .collect())
+    }
+
+    fn token_info(&self, token_ids: &[String]) -> Result<Vec<TokenEntry>, BackendError> {
+        self.post_bulk("slp/list", "tokenIds", token_ids)
+    }
+
+    fn list_tokens(&self) -> Result<Vec<TokenEntry>, BackendError> {
+        Ok(self.client.get(&format!("{}slp/list", self.network.rest_url())).send()?.json()?)
+    }
+
+    fn find_trade_offers(&self) -> Result<Vec<TradeOfferOutput>, BackendError> {
+        let trades_result: TradesResult = self.client.get(&format!(
+            "{}ewogICJ2IjogMywKICAicSI6IHsKICAgICJmaW5kIjogewogICAgICAib3V0Ln\
+             MxIjogIkVYQ0giLAogICAgICAib3V0LmgyIjogIjAxIiwKICAgICAgIm91dC5zMyI6ICJTRUxMIgogICAgfQogIH0K\
+             fQ==",
+            self.network.bitdb_url(),
+        )).send()?.json()?;
+        let prefix = self.network.bch_prefix();
+        Ok(trades_result.c.iter().flat_map(|tx| tx.out.iter()).filter_map(|out| {
+            if out.h1.as_ref() != Some(&hex::encode(b"EXCH")) {
+                return None;
+            }
+            let mut tx_id = [0; 32];
+            tx_id.copy_from_slice(&hex_bytes(&out.h4, 32)?);
+            let mut receiving_address = [0; 20];
+            receiving_address.copy_from_slice(&hex_bytes(&out.h8, 20)?);
+            let mut cancel_address = [0; 20];
+            cancel_address.copy_from_slice(&hex_bytes(&out.h9, 20)?);
+            Some(TradeOfferOutput {
+                tx_id,
+                output_idx: Cursor::new(hex_bytes(&out.h5, 4)?).read_u32::<BigEndian>().ok()?,
+                sell_amount: Cursor::new(hex_bytes(&out.h6, 8)?).read_u64::<BigEndian>().ok()?,
+                buy_amount: Cursor::new(hex_bytes(&out.h7, 8)?).read_u64::<BigEndian>().ok()?,
+                receiving_address: Address::from_bytes_prefix(prefix, AddressType::P2PKH,
+                                                              receiving_address),
+                cancel_address: Address::from_bytes_prefix(prefix, AddressType::P2PKH,
+                                                           cancel_address),
+            })
+        }).collect())
+    }
+}
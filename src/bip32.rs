@@ -0,0 +1,124 @@
+use crate::hash::hmac_sha512;
+use byteorder::{BigEndian, WriteBytesExt};
+
+
+/// Child indices from this value on use hardened derivation.
+pub const HARDENED: u32 = 0x8000_0000;
+
+#[derive(Clone)]
+pub struct ExtendedPrivKey {
+    secret_key: secp256k1::SecretKey,
+    chain_code: [u8; 32],
+}
+
+impl ExtendedPrivKey {
+    /// Creates the master key m from a seed, e.g. from a BIP39 mnemonic.
+    pub fn from_seed(seed: &[u8]) -> Result<Self, secp256k1::Error> {
+        let hash = hmac_sha512(b"Bitcoin seed", seed);
+        let mut chain_code = [0; 32];
+        chain_code.copy_from_slice(&hash[32..]);
+        Ok(ExtendedPrivKey {
+            secret_key: secp256k1::SecretKey::from_slice(&hash[..32])?,
+            chain_code,
+        })
+    }
+
+    pub fn derive_child(&self, index: u32) -> Result<Self, secp256k1::Error> {
+        let mut data = Vec::with_capacity(33 + 4);
+        if index >= HARDENED {
+            data.push(0);
+            data.extend_from_slice(&self.secret_key[..]);
+        } else {
+            data.extend_from_slice(&self.public_key().serialize());
+        }
+        data.write_u32::<BigEndian>(index).unwrap();
+        let hash = hmac_sha512(&self.chain_code, &data);
+        let mut secret_key = self.secret_key;
+        secret_key.add_assign(&hash[..32])?;
+        let mut chain_code = [0; 32];
+        chain_code.copy_from_slice(&hash[32..]);
+        Ok(ExtendedPrivKey { secret_key, chain_code })
+    }
+
+    /// Derives a descendant key, e.g. &[44 | HARDENED, 145 | HARDENED, 0 | HARDENED] for
+    /// m/44'/145'/0'.
+    pub fn derive_path(&self, path: &[u32]) -> Result<Self, secp256k1::Error> {
+        let mut key = self.clone();
+        for index in path {
+            key = key.derive_child(*index)?;
+        }
+        Ok(key)
+    }
+
+    pub fn secret_key(&self) -> &secp256k1::SecretKey {
+        &self.secret_key
+    }
+
+    pub fn chain_code(&self) -> &[u8; 32] {
+        &self.chain_code
+    }
+
+    pub fn public_key(&self) -> secp256k1::PublicKey {
+        let curve = secp256k1::Secp256k1::signing_only();
+        secp256k1::PublicKey::from_secret_key(&curve, &self.secret_key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_key(key: &ExtendedPrivKey, secret_key: &str, chain_code: &str) {
+        assert_eq!(hex::encode(&key.secret_key()[..]), secret_key);
+        assert_eq!(hex::encode(key.chain_code()), chain_code);
+    }
+
+    /// Test vector 1 of BIP32: each path with its private key and chain code.
+    #[test]
+    fn bip32_vector_1() {
+        let master = ExtendedPrivKey::from_seed(
+            &hex::decode("000102030405060708090a0b0c0d0e0f").unwrap(),
+        ).unwrap();
+        assert_key(&master,
+                   "e8f32e723decf4051aefac8e2c93c9c5b214313817cdb01a1494b917c8436b35",
+                   "873dff81c02f525623fd1fe5167eac3a55a049de3d314bb42ee227ffed37d508");
+        assert_eq!(hex::encode(&master.public_key().serialize()[..]),
+                   "0339a36013301597daef41fbe593a02cc513d0b55527ec2df1050e2e8ff49c85c2");
+        let vectors: &[(&[u32], &str, &str)] = &[
+            (&[HARDENED],
+             "edb2e14f9ee77d26dd93b4ecede8d16ed408ce149b6cd80b0715a2d911a0afea",
+             "47fdacbd0f1097043b78c63c20c34ef4ed9a111d980047ad16282c7ae6236141"),
+            (&[HARDENED, 1],
+             "3c6cb8d0f6a264c91ea8b5030fadaa8e538b020f0a387421a12de9319dc93368",
+             "2a7857631386ba23dacac34180dd1983734e444fdbf774041578e9b6adb37c19"),
+            (&[HARDENED, 1, 2 | HARDENED],
+             "cbce0d719ecf7431d88e6a89fa1483e02e35092af60c042b1df2ff59fa424dca",
+             "04466b9cc8e161e966409ca52986c584f07e9dc81f735db683c3ff6ec7b1503f"),
+            (&[HARDENED, 1, 2 | HARDENED, 2],
+             "0f479245fb19a38a1954c5c7c0ebab2f9bdfd96a17563ef28a6a4b1a2a764ef4",
+             "cfb71883f01676f587d023cc53a35bc7f88f724b1f8c2892ac1275ac822a3edd"),
+            (&[HARDENED, 1, 2 | HARDENED, 2, 1_000_000_000],
+             "471b76e389e528d6de6d816857e012c5455051cad6660850e58372a6c3e6e7c8",
+             "c783e67b921d2beb8f6b389cc646d7263b4145701dadd2161548a8b078e65e9e"),
+        ];
+        for (path, secret_key, chain_code) in vectors {
+            assert_key(&master.derive_path(path).unwrap(), secret_key, chain_code);
+        }
+    }
+
+    /// BIP39 vector of the all zero entropy with passphrase "TREZOR", and its master key.
+    #[test]
+    fn bip39_seed_vector() {
+        let mnemonic = bip39::Mnemonic::parse(
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon \
+             abandon about",
+        ).unwrap();
+        let seed = mnemonic.to_seed("TREZOR");
+        assert_eq!(hex::encode(&seed[..]),
+                   "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599\
+                    d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04");
+        assert_key(&ExtendedPrivKey::from_seed(&seed).unwrap(),
+                   "cbedc75b0d6412c85c79bc13875112ef912fd1e756631b5a00330866f22ff184",
+                   "a3fa8c983223306de0f0f65e74ebb1e98aba751633bf91d5fb56529aa5c132c1");
+    }
+}
@@ -0,0 +1,92 @@
+use crate::hash::double_sha256;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use num::{BigUint, One, ToPrimitive, Zero};
+use std::io;
+
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BlockHeader {
+    pub version: i32,
+    pub prev_block: [u8; 32],
+    pub merkle_root: [u8; 32],
+    pub timestamp: u32,
+    pub bits: u32,
+    pub nonce: u32,
+}
+
+impl BlockHeader {
+    pub fn write_to_stream<W: io::Write>(&self, write: &mut W) -> io::Result<()> {
+        write.write_i32::<LittleEndian>(self.version)?;
+        write.write_all(&self.prev_block)?;
+        write.write_all(&self.merkle_root)?;
+        write.write_u32::<LittleEndian>(self.timestamp)?;
+        write.write_u32::<LittleEndian>(self.bits)?;
+        write.write_u32::<LittleEndian>(self.nonce)?;
+        Ok(())
+    }
+
+    pub fn from_stream<R: io::Read>(read: &mut R) -> io::Result<Self> {
+        let version = read.read_i32::<LittleEndian>()?;
+        let mut prev_block = [0; 32];
+        read.read_exact(&mut prev_block)?;
+        let mut merkle_root = [0; 32];
+        read.read_exact(&mut merkle_root)?;
+        Ok(BlockHeader {
+            version,
+            prev_block,
+            merkle_root,
+            timestamp: read.read_u32::<LittleEndian>()?,
+            bits: read.read_u32::<LittleEndian>()?,
+            nonce: read.read_u32::<LittleEndian>()?,
+        })
+    }
+
+    /// The block's hash in byte order, reverse it for the usual hex form.
+    pub fn hash(&self) -> [u8; 32] {
+        let mut header_ser = Vec::new();
+        self.write_to_stream(&mut header_ser).unwrap();
+        double_sha256(&header_ser)
+    }
+
+    /// Expected number of hashes needed to find a block with the header's target, or None if
+    /// its bits are invalid.
+    pub fn work(&self) -> Option<BigUint> {
+        let target = bits_to_target(self.bits)?;
+        Some((BigUint::one() << 256) / (target + BigUint::one()))
+    }
+}
+
+/// Expands the compact form of a target. Returns None for negative or zero targets and
+/// targets above 256 bits.
+pub fn bits_to_target(bits: u32) -> Option<BigUint> {
+    let size = bits >> 24;
+    let mantissa = bits & 0x007f_ffff;
+    if bits & 0x0080_0000 != 0 {
+        return None;
+    }
+    let target = if size <= 3 {
+        BigUint::from(mantissa >> (8 * (3 - size)))
+    } else {
+        BigUint::from(mantissa) << (8 * (size as usize - 3))
+    };
+    if target.is_zero() || target.bits() > 256 {
+        return None;
+    }
+    Some(target)
+}
+
+/// Compresses the target into its compact form, losing precision beyond 23 bits.
+pub fn target_to_bits(target: &BigUint) -> u32 {
+    let mut size = (target.bits() as u32).div_ceil(8);
+    let mut compact = if size <= 3 {
+        target.to_u32().unwrap() << (8 * (3 - size))
+    } else {
+        (target >> (8 * (size as usize - 3))).to_u32().unwrap()
+    };
+    // the mantissa's highest bit is the sign, so shift it into the size if it's set
+    if compact & 0x0080_0000 != 0 {
+        compact >>= 8;
+        size += 1;
+    }
+    compact | (size << 24)
+}
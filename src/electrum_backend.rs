@@ -0,0 +1,624 @@
+use crate::address::Address;
+use crate::backend::{
+    address_script, Backend, BackendError, TokenEntry, UtxoEntry, UtxoVerification,
+};
+use crate::merkle_block::{MerkleBranch, TxProof};
+use crate::hash::single_sha256;
+use crate::outputs::TradeOfferOutput;
+use crate::script::Script;
+use crate::tx::{Tx, tx_hex_to_hash};
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use std::cell::{RefCell, RefMut};
+use std::collections::{HashSet, VecDeque};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+
+/// Protocol version range requested from the server, Electrum Cash 1.4 is supported by Fulcrum
+/// and ElectrumX.
+const PROTOCOL_VERSION: &str = "1.4";
+const SUBSCRIBE_METHOD: &str = "blockchain.scripthash.subscribe";
+/// Error code of ElectrumX and Fulcrum for errors of the node they pass on, e.g. unknown
+/// transactions.
+const DAEMON_ERROR: i64 = 2;
+
+trait ElectrumStream: Read + Write {}
+
+impl<T: Read + Write> ElectrumStream for T {}
+
+#[derive(Deserialize, Debug)]
+struct ElectrumErrorEntry {
+    code: i64,
+    message: String,
+}
+
+/// Either a response to one of our requests or a notification of a subscription.
+#[derive(Deserialize, Debug)]
+struct ElectrumMessage {
+    id: Option<u64>,
+    method: Option<String>,
+    #[serde(default)]
+    params: Vec<serde_json::Value>,
+    #[serde(default)]
+    result: serde_json::Value,
+    error: Option<ElectrumErrorEntry>,
+}
+
+#[derive(Serialize, Debug)]
+struct ElectrumRequest<'a> {
+    jsonrpc: &'a str,
+    id: u64,
+    method: &'a str,
+    params: serde_json::Value,
+}
+
+#[derive(Deserialize, Debug)]
+struct ElectrumUtxo {
+    tx_hash: String,
+    tx_pos: u32,
+    value: u64,
+}
+
+#[derive(Deserialize, Debug)]
+struct ElectrumHistoryEntry {
+    tx_hash: String,
+    /// 0 for unconfirmed transactions, -1 if they also have unconfirmed inputs.
+    height: i64,
+}
+
+#[derive(Deserialize, Debug)]
+struct ElectrumMerkle {
+    block_height: u32,
+    /// Hashes of the merkle branch in RPC byte order, like txids.
+    merkle: Vec<String>,
+    pos: u32,
+}
+
+struct Connection {
+    stream: BufReader<Box<dyn ElectrumStream>>,
+    /// Handle of the underlying socket, to set read timeouts also for TLS streams.
+    tcp_stream: TcpStream,
+    next_id: u64,
+    subscriptions: HashSet<String>,
+    /// Script hashes for which a notification arrived that no one waited for yet.
+    notifications: VecDeque<String>,
+}
+
+/// Backend using an Electrum Cash server, e.g. Fulcrum, over TCP or TLS.
+///
+/// Electrum servers don't index SLP tokens, so token details, SLP validation and trade offers
+/// are looked up by `slp_indexer`.
+pub struct ElectrumBackend {
+    host: String,
+    port: u16,
+    use_tls: bool,
+    slp_indexer: Box<dyn Backend>,
+    connection: RefCell<Option<Connection>>,
+}
+
+/// Electrum identifies scripts by their reversed SHA-256 hash.
+fn script_hash(script: &Script) -> String {
+    let mut hash = single_sha256(&script.to_vec());
+    hash.reverse();
+    hex::encode(hash)
+}
+
+/// Whether the error is the node's response to an unknown transaction.
+fn is_tx_not_found(code: i64, message: &str) -> bool {
+    code == DAEMON_ERROR && message.contains("No such mempool or blockchain transaction")
+}
+
+impl Connection {
+    fn open(host: &str, port: u16, use_tls: bool) -> Result<Self, BackendError> {
+        let tcp_stream = TcpStream::connect((host, port)).map_err(BackendError::ConnectionError)?;
+        let tcp_stream_handle = tcp_stream.try_clone().map_err(BackendError::ConnectionError)?;
+        let stream: Box<dyn ElectrumStream> = if use_tls {
+            let connector = native_tls::TlsConnector::new()
+                .map_err(|err| BackendError::TlsError(err.to_string()))?;
+            Box::new(connector.connect(host, tcp_stream)
+                .map_err(|err| BackendError::TlsError(err.to_string()))?)
+        } else {
+            Box::new(tcp_stream)
+        };
+        let mut connection = Connection {
+            stream: BufReader::new(stream),
+            tcp_stream: tcp_stream_handle,
+            next_id: 0,
+            subscriptions: HashSet::new(),
+            notifications: VecDeque::new(),
+        };
+        connection.call::<serde_json::Value>(
+            "server.version",
+            json!(["slpagora", PROTOCOL_VERSION]),
+        )?;
+        Ok(connection)
+    }
+
+    fn read_message(&mut self) -> Result<ElectrumMessage, BackendError> {
+        let mut line = String::new();
+        let num_bytes = self.stream.read_line(&mut line).map_err(BackendError::ConnectionError)?;
+        if num_bytes == 0 {
+            return Err(BackendError::ConnectionError(io::ErrorKind::UnexpectedEof.into()));
+        }
+        let message: ElectrumMessage = serde_json::from_str(&line)
+            .map_err(|err| BackendError::InvalidResponse(err.to_string()))?;
+        if message.method.as_deref() == Some(SUBSCRIBE_METHOD) {
+            if let Some(script_hash) = message.params.first().and_then(|param| param.as_str()) {
+                if self.subscriptions.contains(script_hash) &&
+                        !self.notifications.iter().any(|hash| hash == script_hash) {
+                    self.notifications.push_back(script_hash.to_string());
+                }
+            }
+        }
+        Ok(message)
+    }
+
+    fn call<R: DeserializeOwned>(&mut self, method: &str, params: serde_json::Value)
+            -> Result<R, BackendError> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let request = ElectrumRequest { jsonrpc: "2.0", id, method, params };
+        let mut request = serde_json::to_vec(&request)
+            .map_err(|err| BackendError::InvalidResponse(err.to_string()))?;
+        request.push(b'\n');
+        self.stream.get_mut().write_all(&request).map_err(BackendError::ConnectionError)?;
+        loop {
+            let message = self.read_message()?;
+            if message.method.is_some() || message.id != Some(id) {
+                continue;
+            }
+            if let Some(error) = message.error {
+                return Err(BackendError::RpcError(error.code, error.message));
+            }
+            return serde_json::from_value(message.result)
+                .map_err(|err| BackendError::InvalidResponse(err.to_string()));
+        }
+    }
+
+    /// Waits until data arrives, at most for `timeout`. Returns false if none arrived.
+    fn wait_readable(&mut self, timeout: Duration) -> Result<bool, BackendError> {
+        self.tcp_stream.set_read_timeout(Some(timeout)).map_err(BackendError::ConnectionError)?;
+        let result = match self.stream.fill_buf() {
+            Ok(_) => Ok(true),
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock ||
+                            err.kind() == io::ErrorKind::TimedOut => Ok(false),
+            Err(err) => Err(BackendError::ConnectionError(err)),
+        };
+        self.tcp_stream.set_read_timeout(None).map_err(BackendError::ConnectionError)?;
+        result
+    }
+
+    /// Waits for a notification for the script hash, subscribing to it first if necessary.
+    /// Returns false if none arrived within `timeout`.
+    fn wait_for_notification(&mut self, script_hash: &str, timeout: Duration)
+            -> Result<bool, BackendError> {
+        if !self.subscriptions.contains(script_hash) {
+            // changes before the subscription aren't notified, so the caller has to check first
+            self.call::<serde_json::Value>(SUBSCRIBE_METHOD, json!([script_hash]))?;
+            self.subscriptions.insert(script_hash.to_string());
+            return Ok(true);
+        }
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(idx) = self.notifications.iter().position(|hash| hash == script_hash) {
+                self.notifications.remove(idx);
+                return Ok(true);
+            }
+            let now = Instant::now();
+            // only read once a message started arriving, so a timeout never splits one
+            if now >= deadline || !self.wait_readable(deadline - now)? {
+                return Ok(false);
+            }
+            self.read_message()?;
+        }
+    }
+}
+
+impl ElectrumBackend {
+    pub fn new(host: String, port: u16, use_tls: bool, slp_indexer: Box<dyn Backend>) -> Self {
+        ElectrumBackend {
+            host,
+            port,
+            use_tls,
+            slp_indexer,
+            connection: RefCell::new(None),
+        }
+    }
+
+    fn connection(&self) -> Result<RefMut<'_, Connection>, BackendError> {
+        let mut connection = self.connection.borrow_mut();
+        if connection.is_none() {
+            *connection = Some(Connection::open(&self.host, self.port, self.use_tls)?);
+        }
+        Ok(RefMut::map(connection, |connection| connection.as_mut().unwrap()))
+    }
+
+    /// Runs `f` on the connection. If the connection broke, it's reopened on the next use.
+    fn with_connection<R>(&self, f: impl FnOnce(&mut Connection) -> Result<R, BackendError>)
+            -> Result<R, BackendError> {
+        let result = f(&mut *self.connection()?);
+        if let Err(BackendError::ConnectionError(_)) = result {
+            *self.connection.borrow_mut() = None;
+        }
+        result
+    }
+
+    fn call<R: DeserializeOwned>(&self, method: &str, params: serde_json::Value)
+            -> Result<R, BackendError> {
+        self.with_connection(|connection| connection.call(method, params))
+    }
+
+    fn list_unspent(&self, address: &Address) -> Result<Vec<ElectrumUtxo>, BackendError> {
+        let script_hash = script_hash(&address_script(address));
+        self.call("blockchain.scripthash.listunspent", json!([script_hash]))
+    }
+
+    /// Height of the block including the transaction, None if it's unconfirmed or unknown.
+    fn tx_height(&self, tx_id: &str) -> Result<Option<u32>, BackendError> {
+        // the protocol can't look up transactions' heights, so look in the history of a script
+        let tx = match self.get_txs(&[tx_id.to_string()])?.pop() {
+            Some(tx) => tx,
+            None => return Ok(None),
+        };
+        for output in tx.outputs() {
+            let history: Vec<ElectrumHistoryEntry> = self.call(
+                "blockchain.scripthash.get_history",
+                json!([script_hash(&output.script)]),
+            )?;
+            if let Some(entry) = history.iter().find(|entry| entry.tx_hash == tx_id) {
+                return Ok(if entry.height > 0 { Some(entry.height as u32) } else { None });
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Backend for ElectrumBackend {
+    fn get_utxos(&self, addresses: &[Address]) -> Result<Vec<Vec<UtxoEntry>>, BackendError> {
+        addresses.iter().map(|address| {
+            Ok(self.list_unspent(address)?.into_iter().map(|utxo| UtxoEntry {
+                txid: utxo.tx_hash,
+                vout: utxo.tx_pos,
+                amount: utxo.value as f64 / 100_000_000.0,
+                satoshis: utxo.value,
+                confirmation_height: None,
+                verification: UtxoVerification::Unverified,
+            }).collect())
+        }).collect()
+    }
+
+    fn are_addresses_used(&self, addresses: &[Address]) -> Result<Vec<bool>, BackendError> {
+        addresses.iter().map(|address| {
+            let script_hash = script_hash(&address_script(address));
+            let history: Vec<serde_json::Value> =
+                self.call("blockchain.scripthash.get_history", json!([script_hash]))?;
+            Ok(!history.is_empty())
+        }).collect()
+    }
+
+    fn broadcast(&self, tx: &Tx) -> Result<String, BackendError> {
+        let mut tx_ser = Vec::new();
+        tx.write_to_stream(&mut tx_ser).unwrap();
+        match self.call("blockchain.transaction.broadcast", json!([hex::encode(&tx_ser)])) {
+            Ok(tx_id) => Ok(tx_id),
+            Err(BackendError::RpcError(_, message)) => {
+                Err(BackendError::BroadcastRejected(message))
+            },
+            Err(err) => Err(err),
+        }
+    }
+
+    fn get_txs(&self, tx_ids: &[String]) -> Result<Vec<Tx>, BackendError> {
+        let mut txs = Vec::new();
+        for tx_id in tx_ids {
+            let tx_hex: String = match self.call("blockchain.transaction.get", json!([tx_id])) {
+                Ok(tx_hex) => tx_hex,
+                Err(BackendError::RpcError(code, ref message))
+                    if is_tx_not_found(code, message) => continue,
+                Err(err) => return Err(err),
+            };
+            if let Ok(tx) = Tx::from_hex(&tx_hex) {
+                txs.push(tx);
+            }
+        }
+        Ok(txs)
+    }
+
+    fn is_output_unspent(&self, tx_id: &str, output_idx: u32) -> Result<bool, BackendError> {
+        // the protocol can't look up outputs directly, so look among the UTXOs of its script
+        let tx = match self.get_txs(&[tx_id.to_string()])?.pop() {
+            Some(tx) => tx,
+            None => return Ok(false),
+        };
+        let output = match tx.outputs().get(output_idx as usize) {
+            Some(output) => output,
+            None => return Ok(false),
+        };
+        let utxos: Vec<ElectrumUtxo> =
+            self.call("blockchain.scripthash.listunspent", json!([script_hash(&output.script)]))?;
+        Ok(utxos.iter().any(|utxo| utxo.tx_hash == tx_id && utxo.tx_pos == output_idx))
+    }
+
+    fn get_tx_proof(&self, tx_id: &str) -> Result<Option<TxProof>, BackendError> {
+        let height = match self.tx_height(tx_id)? {
+            Some(height) => height,
+            None => return Ok(None),
+        };
+        let merkle: ElectrumMerkle =
+            self.call("blockchain.transaction.get_merkle", json!([tx_id, height]))?;
+        let hashes = merkle.merkle.iter()
+            .map(|hash_hex| match hex::decode(hash_hex) {
+                Ok(ref hash) if hash.len() == 32 => Ok(tx_hex_to_hash(hash_hex)),
+                _ => Err(BackendError::InvalidResponse(format!("invalid hash {}", hash_hex))),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Some(TxProof::MerkleBranch(MerkleBranch {
+            block_height: merkle.block_height,
+            pos: merkle.pos,
+            hashes,
+        })))
+    }
+
+    fn validate_slp_txids(&self, tx_ids: &[String]) -> Result<Vec<String>, BackendError> {
+        self.slp_indexer.validate_slp_txids(tx_ids)
+    }
+
+    fn token_info(&self, token_ids: &[String]) -> Result<Vec<TokenEntry>, BackendError> {
+        self.slp_indexer.token_info(token_ids)
+    }
+
+    fn list_tokens(&self) -> Result<Vec<TokenEntry>, BackendError> {
+        self.slp_indexer.list_tokens()
+    }
+
+    fn find_trade_offers(&self) -> Result<Vec<TradeOfferOutput>, BackendError> {
+        self.slp_indexer.find_trade_offers()
+    }
+
+    fn wait_for_address_change(&self, address: &Address, timeout: Duration)
+            -> Result<bool, BackendError> {
+        let script_hash = script_hash(&address_script(address));
+        self.with_connection(|connection| connection.wait_for_notification(&script_hash, timeout))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::AddressType;
+    use crate::backend::RestBackend;
+    use crate::network::Network;
+    use crate::tx::{TxInput, TxOutpoint, TxOutput};
+    use serde_json::Value;
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+
+    /// Time after a subscription until the fake server notifies a change.
+    const NOTIFICATION_DELAY: Duration = Duration::from_millis(200);
+
+    type Handler = dyn Fn(&str, &Value) -> Result<Value, (i64, &'static str)> + Send + Sync;
+
+    /// Electrum server on the loopback interface answering calls with
+    /// `handler(method, params)`, which returns the result or an error code and message. It
+    /// notifies a change of each subscribed script hash once, shortly after the subscription.
+    struct FakeServer {
+        port: u16,
+        requests: Arc<Mutex<Vec<Value>>>,
+    }
+
+    fn serve_connection(stream: TcpStream, handler: &Handler, requests: &Mutex<Vec<Value>>)
+            -> io::Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut stream = stream;
+        let mut line = String::new();
+        while reader.read_line(&mut line)? > 0 {
+            let request: Value = serde_json::from_str(&line).unwrap();
+            line.clear();
+            requests.lock().unwrap().push(request.clone());
+            let method = request["method"].as_str().unwrap();
+            let result = match method {
+                "server.version" => Ok(json!(["FakeServer 1.0", PROTOCOL_VERSION])),
+                SUBSCRIBE_METHOD => Ok(Value::Null),
+                method => handler(method, &request["params"]),
+            };
+            let response = match result {
+                Ok(result) => json!({"jsonrpc": "2.0", "id": request["id"], "result": result}),
+                Err((code, message)) => json!({
+                    "jsonrpc": "2.0", "id": request["id"],
+                    "error": {"code": code, "message": message},
+                }),
+            };
+            writeln!(stream, "{}", response)?;
+            if method == SUBSCRIBE_METHOD {
+                std::thread::sleep(NOTIFICATION_DELAY);
+                writeln!(stream, "{}", json!({
+                    "jsonrpc": "2.0",
+                    "method": SUBSCRIBE_METHOD,
+                    "params": [request["params"][0], "0123456789abcdef"],
+                }))?;
+            }
+        }
+        Ok(())
+    }
+
+    impl FakeServer {
+        fn start(handler: impl Fn(&str, &Value) -> Result<Value, (i64, &'static str)>
+                     + Send + Sync + 'static) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let requests = Arc::new(Mutex::new(Vec::new()));
+            let handler: Arc<Handler> = Arc::new(handler);
+            let server_requests = requests.clone();
+            std::thread::spawn(move || {
+                for stream in listener.incoming() {
+                    serve_connection(stream.unwrap(), &*handler, &server_requests).ok();
+                }
+            });
+            FakeServer { port, requests }
+        }
+
+        fn backend(&self) -> ElectrumBackend {
+            ElectrumBackend::new("127.0.0.1".to_string(),
+                                 self.port,
+                                 false,
+                                 Box::new(RestBackend::new(Network::Mainnet)))
+        }
+
+        fn calls(&self, method: &str) -> Vec<Value> {
+            self.requests.lock().unwrap().iter()
+                .filter(|request| request["method"] == method)
+                .map(|request| request["params"].clone())
+                .collect()
+        }
+    }
+
+    /// An address and the script hash of its script,
+    /// 76a91477bff20c60e522dfaa3350c39b030a5d004e839a88ac.
+    fn example_address() -> (Address, &'static str) {
+        (Address::from_legacy("1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2").unwrap(),
+         "eafd9bc024177ba93572c1cc3a83f555dadbb81ca94cd9761ef5211ce794cea9")
+    }
+
+    fn example_tx(address: &Address) -> Tx {
+        Tx::new(
+            1,
+            vec![TxInput::new(TxOutpoint { tx_hash: [1; 32], output_idx: 0 }, Script::empty(),
+                              0xffff_ffff)],
+            vec![TxOutput::new(5000, address_script(address))],
+            0,
+        )
+    }
+
+    fn tx_hex(tx: &Tx) -> String {
+        let mut tx_ser = Vec::new();
+        tx.write_to_stream(&mut tx_ser).unwrap();
+        hex::encode(tx_ser)
+    }
+
+    #[test]
+    fn list_unspent() {
+        let (address, expected_script_hash) = example_address();
+        let server = FakeServer::start(|method, _| match method {
+            "blockchain.scripthash.listunspent" => Ok(json!([
+                {"tx_hash": "ab".repeat(32), "tx_pos": 2, "height": 0, "value": 12345},
+            ])),
+            _ => Err((-32601, "unknown method")),
+        });
+        let utxos = server.backend().get_utxos(&[address]).unwrap();
+        assert_eq!(utxos.len(), 1);
+        assert_eq!(utxos[0].len(), 1);
+        assert_eq!(utxos[0][0].txid, "ab".repeat(32));
+        assert_eq!(utxos[0][0].vout, 2);
+        assert_eq!(utxos[0][0].satoshis, 12345);
+        assert_eq!(server.calls("blockchain.scripthash.listunspent"),
+                   [json!([expected_script_hash])]);
+    }
+
+    #[test]
+    fn broadcast() {
+        let (address, _) = example_address();
+        let tx = example_tx(&address);
+        let expected_hex = tx_hex(&tx);
+        let txid = tx.txid();
+        let server = FakeServer::start(move |method, params| match method {
+            "blockchain.transaction.broadcast" if params[0] == expected_hex.as_str() =>
+                Ok(json!(txid)),
+            "blockchain.transaction.broadcast" => Err((1, "the transaction was rejected")),
+            _ => Err((-32601, "unknown method")),
+        });
+        let backend = server.backend();
+        assert_eq!(backend.broadcast(&tx).unwrap(), tx.txid());
+        match backend.broadcast(&example_tx(&Address::from_bytes(AddressType::P2PKH, [0; 20]))) {
+            Err(BackendError::BroadcastRejected(message)) =>
+                assert_eq!(message, "the transaction was rejected"),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn notification_wakes_wait_for_address_change() {
+        let (address, expected_script_hash) = example_address();
+        let server = FakeServer::start(|_, _| Err((-32601, "unknown method")));
+        let backend = server.backend();
+        // subscribing returns immediately, changes before it aren't notified
+        assert!(backend.wait_for_address_change(&address, Duration::from_secs(5)).unwrap());
+        assert_eq!(server.calls(SUBSCRIBE_METHOD), [json!([expected_script_hash])]);
+        let start = Instant::now();
+        assert!(backend.wait_for_address_change(&address, Duration::from_secs(5)).unwrap());
+        assert!(start.elapsed() < Duration::from_secs(5));
+        // the notification is consumed and the subscription reused
+        assert!(!backend.wait_for_address_change(&address, Duration::from_millis(100)).unwrap());
+        assert_eq!(server.calls(SUBSCRIBE_METHOD).len(), 1);
+    }
+
+    #[test]
+    fn unknown_txs_are_skipped() {
+        let server = FakeServer::start(|method, params| match method {
+            "blockchain.transaction.get" if params[0] == "00".repeat(32).as_str() =>
+                Err((DAEMON_ERROR, "daemon error: No such mempool or blockchain transaction. \
+                                    Use gettransaction for wallet transactions.")),
+            "blockchain.transaction.get" => Err((DAEMON_ERROR, "daemon error: Work queue depth \
+                                                                exceeded")),
+            _ => Err((-32601, "unknown method")),
+        });
+        let backend = server.backend();
+        assert!(backend.get_txs(&["00".repeat(32)]).unwrap().is_empty());
+        match backend.get_txs(&["11".repeat(32)]) {
+            Err(BackendError::RpcError(DAEMON_ERROR, _)) => {},
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn merkle_branch_proof() {
+        let (address, expected_script_hash) = example_address();
+        let tx = example_tx(&address);
+        let confirmed_txid = tx.txid();
+        let tx_hex = tx_hex(&tx);
+        let server = FakeServer::start(move |method, params| match method {
+            "blockchain.transaction.get" => Ok(json!(tx_hex)),
+            "blockchain.scripthash.get_history" => Ok(json!([
+                {"tx_hash": "cd".repeat(32), "height": 0},
+                {"tx_hash": confirmed_txid, "height": 650_000},
+            ])),
+            "blockchain.transaction.get_merkle" if params[1] == 650_000 => Ok(json!({
+                "block_height": 650_000,
+                "merkle": ["11".repeat(31) + "22", "33".repeat(32)],
+                "pos": 2,
+            })),
+            _ => Err((-32601, "unknown method")),
+        });
+        let backend = server.backend();
+        let branch = match backend.get_tx_proof(&tx.txid()).unwrap() {
+            Some(TxProof::MerkleBranch(branch)) => branch,
+            proof => panic!("unexpected proof {:?}", proof),
+        };
+        assert_eq!(branch.block_height, 650_000);
+        assert_eq!(branch.pos, 2);
+        let mut first_hash = [0x11; 32];
+        first_hash[0] = 0x22;
+        assert_eq!(branch.hashes, [first_hash, [0x33; 32]]);
+        assert_eq!(server.calls("blockchain.scripthash.get_history"),
+                   [json!([expected_script_hash])]);
+        assert_eq!(server.calls("blockchain.transaction.get_merkle"),
+                   [json!([tx.txid(), 650_000])]);
+    }
+
+    #[test]
+    fn unconfirmed_tx_has_no_proof() {
+        let (address, _) = example_address();
+        let tx = example_tx(&address);
+        let txid = tx.txid();
+        let tx_hex = tx_hex(&tx);
+        let server = FakeServer::start(move |method, _| match method {
+            "blockchain.transaction.get" => Ok(json!(tx_hex)),
+            "blockchain.scripthash.get_history" => Ok(json!([{"tx_hash": txid, "height": -1}])),
+            _ => Err((-32601, "unknown method")),
+        });
+        assert!(server.backend().get_tx_proof(&tx.txid()).unwrap().is_none());
+        assert!(server.calls("blockchain.transaction.get_merkle").is_empty());
+    }
+}
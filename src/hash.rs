@@ -1,5 +1,7 @@
-use sha2::{Sha256, Digest};
+use sha2::{Sha256, Sha512, Digest};
+use hmac::{Hmac, Mac};
 use ripemd160::Ripemd160;
+use sha1::Sha1;
 
 pub fn single_sha256(data: &[u8]) -> [u8; 32] {
     let sha = Sha256::digest(data);
@@ -21,3 +23,23 @@ pub fn hash160(data: &[u8]) -> [u8; 20] {
     arr.copy_from_slice(&Ripemd160::digest(&Sha256::digest(data)));
     arr
 }
+
+pub fn single_sha1(data: &[u8]) -> [u8; 20] {
+    let mut arr = [0; 20];
+    arr.copy_from_slice(&Sha1::digest(data));
+    arr
+}
+
+pub fn single_ripemd160(data: &[u8]) -> [u8; 20] {
+    let mut arr = [0; 20];
+    arr.copy_from_slice(&Ripemd160::digest(data));
+    arr
+}
+
+pub fn hmac_sha512(key: &[u8], data: &[u8]) -> [u8; 64] {
+    let mut mac = Hmac::<Sha512>::new_varkey(key).expect("HMAC accepts keys of any length");
+    mac.input(data);
+    let mut arr = [0; 64];
+    arr.copy_from_slice(&mac.result().code());
+    arr
+}
@@ -0,0 +1,588 @@
+use crate::block_header::{BlockHeader, bits_to_target, target_to_bits};
+use crate::merkle_block::{MerkleError, TxProof};
+use crate::message_error::MessageError;
+use crate::network::Network;
+use crate::network_message::{GetHeadersMessage, NetworkMessage, MAX_HEADERS_SIZE};
+use crate::peer::{Peer, PROTOCOL_VERSION};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use num::{BigUint, Zero};
+use std::collections::HashMap;
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+
+const TARGET_SPACING: i64 = 600;
+/// Half life of the ASERT difficulty adjustment, two days.
+const ASERT_HALF_LIFE: i64 = 2 * 24 * 60 * 60;
+/// Blocks may be at most two hours ahead of our clock.
+const MAX_FUTURE_BLOCK_TIME: i64 = 2 * 60 * 60;
+const MEDIAN_TIME_SPAN: usize = 11;
+const HEADER_SIZE: u64 = 80;
+/// The store starts with the height of its first header.
+const STORE_PREFIX_SIZE: u64 = 4;
+const SYNC_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug)]
+pub enum HeaderChainError {
+    /// The peer didn't send the checkpoint header or sent a different one.
+    CheckpointMismatch,
+    /// The headers don't connect to any header of the chain.
+    UnconnectedHeaders,
+    /// The headers of a batch don't build on each other.
+    NotContinuous,
+    InvalidProofOfWork(u32),
+    /// The header at the height has bits different from the ones required by the DAA.
+    UnexpectedDifficulty(u32, u32),
+    TimestampTooOld(u32),
+    TimestampTooNew(u32),
+    CorruptedStore,
+    /// The block isn't part of the chain, it might be newer than our tip or on a stale branch.
+    UnknownBlock,
+    InvalidMerkleProof(MerkleError),
+    /// The merkle proof is valid but doesn't include the transaction.
+    TxNotInProof,
+    IoError(io::Error),
+    PeerError(MessageError),
+}
+
+impl From<io::Error> for HeaderChainError {
+    fn from(err: io::Error) -> Self {
+        HeaderChainError::IoError(err)
+    }
+}
+
+impl From<MessageError> for HeaderChainError {
+    fn from(err: MessageError) -> Self {
+        HeaderChainError::PeerError(err)
+    }
+}
+
+impl std::fmt::Display for HeaderChainError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for HeaderChainError {
+
+}
+
+/// Chain of validated block headers, starting at the network's checkpoint and persisted in a
+/// file of the checkpoint's height followed by the raw headers.
+pub struct HeaderChain {
+    network: Network,
+    base_height: u32,
+    headers: Vec<BlockHeader>,
+    hashes: Vec<[u8; 32]>,
+    heights: HashMap<[u8; 32], u32>,
+    path: PathBuf,
+}
+
+fn checkpoint_hash(network: Network) -> [u8; 32] {
+    let mut hash = [0; 32];
+    hash.copy_from_slice(&hex::decode(network.header_checkpoint().1).unwrap());
+    hash.reverse();
+    hash
+}
+
+/// Target of the block after `prev_height` according to aserti3-2d, as specified in
+/// https://upgradespecs.bitcoincashnode.org/2020-11-15-asert/.
+fn asert_target(anchor_target: &BigUint,
+                pow_limit: &BigUint,
+                time_diff: i64,
+                height_diff: i64) -> BigUint {
+    let exponent = ((time_diff - TARGET_SPACING * (height_diff + 1)) * 65536) / ASERT_HALF_LIFE;
+    let mut shifts = exponent >> 16;
+    let frac = exponent as u16 as u128;
+    // approximates 2^(frac / 65536) * 65536 with a cubic polynomial
+    let factor = 65536 + ((195_766_423_245_049 * frac + 971_821_376 * frac * frac +
+                           5127 * frac * frac * frac + (1 << 47)) >> 48) as u64;
+    let mut next_target = anchor_target * factor;
+    shifts -= 16;
+    if shifts <= 0 {
+        next_target >>= (-shifts) as usize;
+    } else {
+        next_target <<= shifts as usize;
+    }
+    if next_target.is_zero() {
+        next_target = BigUint::from(1u32);
+    } else if &next_target > pow_limit {
+        next_target = pow_limit.clone();
+    }
+    next_target
+}
+
+fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64
+}
+
+impl HeaderChain {
+    /// Opens the chain stored at `path`, or creates an empty chain if the file doesn't exist.
+    pub fn open(network: Network, path: PathBuf) -> Result<Self, HeaderChainError> {
+        let mut chain = HeaderChain {
+            network,
+            base_height: network.header_checkpoint().0,
+            headers: Vec::new(),
+            hashes: Vec::new(),
+            heights: HashMap::new(),
+            path,
+        };
+        let file_bytes = match std::fs::read(&chain.path) {
+            Ok(file_bytes) => file_bytes,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(chain),
+            Err(err) => return Err(err.into()),
+        };
+        let file_len = file_bytes.len() as u64;
+        if file_len < STORE_PREFIX_SIZE
+                || !(file_len - STORE_PREFIX_SIZE).is_multiple_of(HEADER_SIZE) {
+            return Err(HeaderChainError::CorruptedStore);
+        }
+        let mut cur = io::Cursor::new(&file_bytes);
+        if cur.read_u32::<LittleEndian>()? != chain.base_height {
+            return Err(HeaderChainError::CorruptedStore);
+        }
+        while cur.position() < file_bytes.len() as u64 {
+            let header = BlockHeader::from_stream(&mut cur)?;
+            let hash = header.hash();
+            let is_connected = match chain.hashes.last() {
+                Some(tip_hash) => &header.prev_block == tip_hash,
+                None => hash == checkpoint_hash(network),
+            };
+            // linkage covers all but the last header, a damaged one fails the proof of work
+            if !is_connected || !chain.has_valid_proof_of_work(&header, &hash) {
+                return Err(HeaderChainError::CorruptedStore);
+            }
+            chain.push_header(header);
+        }
+        Ok(chain)
+    }
+
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    fn push_header(&mut self, header: BlockHeader) {
+        let hash = header.hash();
+        self.heights.insert(hash, self.base_height + self.headers.len() as u32);
+        self.hashes.push(hash);
+        self.headers.push(header);
+    }
+
+    pub fn tip_height(&self) -> Option<u32> {
+        if self.headers.is_empty() {
+            return None;
+        }
+        Some(self.base_height + self.headers.len() as u32 - 1)
+    }
+
+    /// Hash of the newest header in byte order.
+    pub fn tip_hash(&self) -> Option<[u8; 32]> {
+        self.hashes.last().cloned()
+    }
+
+    pub fn header_at(&self, height: u32) -> Option<&BlockHeader> {
+        self.headers.get(height.checked_sub(self.base_height)? as usize)
+    }
+
+    /// Height of the block with the hash (in byte order), if it's part of the chain.
+    pub fn height_of(&self, hash: &[u8; 32]) -> Option<u32> {
+        self.heights.get(hash).cloned()
+    }
+
+    /// Number of confirmations of a block at the height, i.e. 1 for the tip.
+    pub fn confirmations(&self, height: u32) -> u32 {
+        match self.tip_height() {
+            Some(tip_height) if height <= tip_height => tip_height - height + 1,
+            _ => 0,
+        }
+    }
+
+    /// Checks that `proof` proves the transaction with the hash (in byte order) to be in a block
+    /// of the chain, and returns the block's height.
+    pub fn verify_tx_inclusion(&self, tx_hash: &[u8; 32], proof: &TxProof)
+            -> Result<u32, HeaderChainError> {
+        match proof {
+            TxProof::MerkleBlock(merkle_block) => {
+                let height = self.height_of(&merkle_block.header.hash())
+                    .ok_or(HeaderChainError::UnknownBlock)?;
+                let matches = merkle_block.extract_matches()
+                    .map_err(HeaderChainError::InvalidMerkleProof)?;
+                if !matches.contains(tx_hash) {
+                    return Err(HeaderChainError::TxNotInProof);
+                }
+                Ok(height)
+            },
+            TxProof::MerkleBranch(merkle_branch) => {
+                let header = self.header_at(merkle_branch.block_height)
+                    .ok_or(HeaderChainError::UnknownBlock)?;
+                let merkle_root = merkle_branch.merkle_root(tx_hash)
+                    .map_err(HeaderChainError::InvalidMerkleProof)?;
+                if merkle_root != header.merkle_root {
+                    return Err(HeaderChainError::InvalidMerkleProof(MerkleError::RootMismatch));
+                }
+                Ok(merkle_branch.block_height)
+            },
+        }
+    }
+
+    /// Hashes of the last 10 headers, then exponentially fewer back to the checkpoint.
+    pub fn locator(&self) -> Vec<[u8; 32]> {
+        let mut locator = Vec::new();
+        let mut idx = self.hashes.len() as i64 - 1;
+        let mut step = 1;
+        while idx > 0 {
+            locator.push(self.hashes[idx as usize]);
+            if locator.len() >= 10 {
+                step *= 2;
+            }
+            idx -= step;
+        }
+        if let Some(base_hash) = self.hashes.first() {
+            locator.push(*base_hash);
+        }
+        locator
+    }
+
+    /// Bits the block after `prev_height` must have.
+    fn required_bits(&self, prev_height: u32, prev: &BlockHeader, header: &BlockHeader) -> u32 {
+        let (anchor_height, anchor_bits, anchor_parent_time) = match self.network.asert_anchor() {
+            Some(anchor) => anchor,
+            None => return prev.bits,
+        };
+        if self.network.allows_min_difficulty_blocks() &&
+                header.timestamp as i64 > prev.timestamp as i64 + 2 * TARGET_SPACING {
+            return self.network.pow_limit_bits();
+        }
+        let target = asert_target(
+            &bits_to_target(anchor_bits).unwrap(),
+            &bits_to_target(self.network.pow_limit_bits()).unwrap(),
+            prev.timestamp as i64 - anchor_parent_time,
+            prev_height as i64 - anchor_height as i64,
+        );
+        target_to_bits(&target)
+    }
+
+    /// Whether the header's hash meets the target of its bits, within the network's limit.
+    fn has_valid_proof_of_work(&self, header: &BlockHeader, hash: &[u8; 32]) -> bool {
+        let pow_limit = bits_to_target(self.network.pow_limit_bits()).unwrap();
+        let target = match bits_to_target(header.bits) {
+            Some(target) if target <= pow_limit => target,
+            _ => return false,
+        };
+        let mut hash = *hash;
+        hash.reverse();
+        BigUint::from_bytes_be(&hash) <= target
+    }
+
+    /// Validates `header` at `height`; `prev_headers` are the headers before it, oldest first.
+    fn validate_header(&self, height: u32, header: &BlockHeader, prev_headers: &[&BlockHeader])
+            -> Result<(), HeaderChainError> {
+        let prev = prev_headers.last().expect("the checkpoint is never validated");
+        if !self.has_valid_proof_of_work(header, &header.hash()) {
+            return Err(HeaderChainError::InvalidProofOfWork(height));
+        }
+        if header.bits != self.required_bits(height - 1, prev, header) {
+            return Err(HeaderChainError::UnexpectedDifficulty(height, header.bits));
+        }
+        let mut timestamps = prev_headers.iter()
+            .rev()
+            .take(MEDIAN_TIME_SPAN)
+            .map(|header| header.timestamp)
+            .collect::<Vec<_>>();
+        timestamps.sort();
+        if header.timestamp <= timestamps[timestamps.len() / 2] {
+            return Err(HeaderChainError::TimestampTooOld(height));
+        }
+        if header.timestamp as i64 > now() + MAX_FUTURE_BLOCK_TIME {
+            return Err(HeaderChainError::TimestampTooNew(height));
+        }
+        Ok(())
+    }
+
+    /// Adds the headers to the chain, validating them first. If they fork off from the chain
+    /// and have more work than the chain after the fork, they replace it. Returns the number of
+    /// headers added.
+    pub fn connect_headers(&mut self, headers: &[BlockHeader]) -> Result<usize, HeaderChainError> {
+        if headers.is_empty() {
+            return Ok(0);
+        }
+        for pair in headers.windows(2) {
+            if pair[1].prev_block != pair[0].hash() {
+                return Err(HeaderChainError::NotContinuous);
+            }
+        }
+        let mut fork_height = self.height_of(&headers[0].prev_block)
+            .ok_or(HeaderChainError::UnconnectedHeaders)?;
+        let mut new_headers = headers;
+        while let Some(header) = new_headers.first() {
+            match self.hashes.get((fork_height + 1 - self.base_height) as usize) {
+                Some(hash) if hash == &header.hash() => {
+                    fork_height += 1;
+                    new_headers = &new_headers[1..];
+                },
+                _ => break,
+            }
+        }
+        if new_headers.is_empty() {
+            return Ok(0);
+        }
+        let fork_idx = (fork_height - self.base_height) as usize;
+        let first_prev_idx = (fork_idx + 1).saturating_sub(MEDIAN_TIME_SPAN);
+        let mut prev_headers = self.headers[first_prev_idx..=fork_idx].iter().collect::<Vec<_>>();
+        let mut branch_work = BigUint::zero();
+        for (i, header) in new_headers.iter().enumerate() {
+            self.validate_header(fork_height + 1 + i as u32, header, &prev_headers)?;
+            branch_work += header.work().unwrap();
+            prev_headers.push(header);
+        }
+        let mut replaced_work = BigUint::zero();
+        for header in &self.headers[fork_idx + 1..] {
+            replaced_work += header.work().ok_or(HeaderChainError::CorruptedStore)?;
+        }
+        if fork_idx + 1 < self.headers.len() && branch_work <= replaced_work {
+            // a competing branch that doesn't have more work, keep our chain
+            return Ok(0);
+        }
+        for hash in self.hashes.drain(fork_idx + 1..) {
+            self.heights.remove(&hash);
+        }
+        self.headers.truncate(fork_idx + 1);
+        for header in new_headers {
+            self.push_header(header.clone());
+        }
+        self.write_from(fork_idx + 1)?;
+        Ok(new_headers.len())
+    }
+
+    /// Writes the headers from `idx` on to the store, replacing the ones stored there.
+    fn write_from(&self, idx: usize) -> io::Result<()> {
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&self.path)?;
+        if idx == 0 {
+            file.set_len(0)?;
+            file.write_u32::<LittleEndian>(self.base_height)?;
+        } else {
+            file.set_len(STORE_PREFIX_SIZE + idx as u64 * HEADER_SIZE)?;
+            file.seek(SeekFrom::End(0))?;
+        }
+        let mut headers_ser = Vec::new();
+        for header in &self.headers[idx..] {
+            header.write_to_stream(&mut headers_ser)?;
+        }
+        file.write_all(&headers_ser)?;
+        file.sync_all()
+    }
+
+    fn request_headers(&self, peer: &mut Peer, locator_hashes: Vec<[u8; 32]>, hash_stop: [u8; 32])
+            -> Result<Vec<BlockHeader>, HeaderChainError> {
+        peer.send(&NetworkMessage::GetHeaders(GetHeadersMessage {
+            version: PROTOCOL_VERSION as u32,
+            locator_hashes,
+            hash_stop,
+        }))?;
+        loop {
+            if let NetworkMessage::Headers(headers) = peer.receive()? {
+                return Ok(headers);
+            }
+        }
+    }
+
+    /// Downloads and validates all headers the peer has beyond our tip. An empty chain first
+    /// fetches the checkpoint header. Returns the number of headers added.
+    pub fn sync(&mut self, peer: &mut Peer) -> Result<usize, HeaderChainError> {
+        peer.set_read_timeout(Some(SYNC_TIMEOUT))?;
+        let result = self.sync_headers(peer);
+        peer.set_read_timeout(None)?;
+        result
+    }
+
+    fn sync_headers(&mut self, peer: &mut Peer) -> Result<usize, HeaderChainError> {
+        if self.headers.is_empty() {
+            // with an empty locator, peers send just the header of the stop hash
+            let checkpoint_hash = checkpoint_hash(self.network);
+            let headers = self.request_headers(peer, vec![], checkpoint_hash)?;
+            match headers.first() {
+                Some(header) if header.hash() == checkpoint_hash => {
+                    self.push_header(header.clone());
+                    self.write_from(0)?;
+                },
+                _ => return Err(HeaderChainError::CheckpointMismatch),
+            }
+        }
+        let mut num_added = 0;
+        loop {
+            let headers = self.request_headers(peer, self.locator(), [0; 32])?;
+            let num_new = self.connect_headers(&headers)?;
+            num_added += num_new;
+            if num_new == 0 || (headers.len() as u64) < MAX_HEADERS_SIZE {
+                return Ok(num_added);
+            }
+        }
+    }
+}
+
+/// Store of the network's headers in `dir`, e.g. ~/.slpagora/headers-mainnet.dat.
+pub fn header_store_path(dir: &Path, network: Network) -> PathBuf {
+    dir.join(format!("headers-{}.dat", network.name()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Anchor height, anchor parent time, anchor bits and blocks as height, time and bits.
+    type AsertRun = (u32, i64, u32, &'static [(u32, i64, u32)]);
+
+    /// Runs in the layout of the aserti3-2d spec's test vectors: anchor height, anchor parent
+    /// time and anchor bits, then the height and time of blocks with the bits required for the
+    /// block after them. The exact doublings and halvings don't depend on the approximation.
+    const ASERT_RUNS: &[AsertRun] = &[
+        // ideal block times keep the anchor's target
+        (1, 0, 0x1d00ffff, &[
+            (1, 600, 0x1d00ffff),
+            (2, 1200, 0x1d00ffff),
+            (3, 1800, 0x1d00ffff),
+            (1000, 600_000, 0x1d00ffff),
+            (100_000, 60_000_000, 0x1d00ffff),
+        ]),
+        // the mainnet anchor
+        (661_647, 1_605_447_844, 0x1804dafe, &[
+            (661_647, 1_605_448_444, 0x1804dafe),
+            (661_647, 1_605_621_244, 0x1809b5fc),
+            (661_647, 1_605_275_644, 0x18026d7f),
+            (661_657, 1_605_800_044, 0x18136bf8),
+            (661_652, 1_605_451_744, 0x1804dc7d),
+            (661_652, 1_605_451_144, 0x1804d983),
+            (662_647, 1_606_060_789, 0x18051a35),
+            (663_663, 1_606_558_045, 0x18034034),
+            (711_647, 1_643_226_221, 0x1d00ffff),
+            (661_648, 1_605_449_043, 0x1804dafe),
+        ]),
+        // clamped to the pow limit and to a target of 1
+        (1, 0, 0x1d00ffff, &[
+            (2, 174_600, 0x1d00ffff),
+            (2, -43_198_200, 0x01010000),
+            (100, 61_200, 0x1d00ffff),
+        ]),
+    ];
+
+    #[test]
+    fn asert_runs() {
+        let pow_limit = bits_to_target(Network::Mainnet.pow_limit_bits()).unwrap();
+        for &(anchor_height, anchor_parent_time, anchor_bits, blocks) in ASERT_RUNS {
+            let anchor_target = bits_to_target(anchor_bits).unwrap();
+            for &(height, time, bits) in blocks {
+                let target = asert_target(&anchor_target,
+                                          &pow_limit,
+                                          time - anchor_parent_time,
+                                          height as i64 - anchor_height as i64);
+                assert_eq!(target_to_bits(&target), bits, "block {} at {}", height, time);
+            }
+        }
+    }
+
+    fn regtest_genesis() -> BlockHeader {
+        let mut merkle_root = [0; 32];
+        merkle_root.copy_from_slice(&hex::decode(
+            "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b",
+        ).unwrap());
+        merkle_root.reverse();
+        BlockHeader {
+            version: 1,
+            prev_block: [0; 32],
+            merkle_root,
+            timestamp: 1_296_688_602,
+            bits: 0x207fffff,
+            nonce: 2,
+        }
+    }
+
+    /// A regtest chain of just the genesis block, stored in a fresh file.
+    fn regtest_chain(name: &str) -> HeaderChain {
+        let path = std::env::temp_dir()
+            .join(format!("slpagora-test-{}-{}.headers", std::process::id(), name));
+        std::fs::remove_file(&path).ok();
+        let mut chain = HeaderChain::open(Network::Regtest, path).unwrap();
+        chain.push_header(regtest_genesis());
+        chain.write_from(0).unwrap();
+        chain
+    }
+
+    /// Mines `len` headers on top of `prev`, `branch` makes the branches' headers differ.
+    fn mine(chain: &HeaderChain, prev: &BlockHeader, len: u32, branch: u8) -> Vec<BlockHeader> {
+        let mut headers = Vec::new();
+        let mut prev = prev.clone();
+        for _ in 0..len {
+            let mut header = BlockHeader {
+                version: 4,
+                prev_block: prev.hash(),
+                merkle_root: [branch; 32],
+                timestamp: prev.timestamp + TARGET_SPACING as u32,
+                bits: prev.bits,
+                nonce: 0,
+            };
+            while !chain.has_valid_proof_of_work(&header, &header.hash()) {
+                header.nonce += 1;
+            }
+            headers.push(header.clone());
+            prev = header;
+        }
+        headers
+    }
+
+    #[test]
+    fn reorg_to_branch_with_more_work() {
+        let mut chain = regtest_chain("more-work");
+        let genesis = regtest_genesis();
+        let ours = mine(&chain, &genesis, 2, 1);
+        assert_eq!(chain.connect_headers(&ours).unwrap(), 2);
+        let theirs = mine(&chain, &genesis, 3, 2);
+        assert_eq!(chain.connect_headers(&theirs).unwrap(), 3);
+        assert_eq!(chain.tip_height(), Some(3));
+        assert_eq!(chain.tip_hash(), Some(theirs[2].hash()));
+        assert_eq!(chain.height_of(&ours[1].hash()), None);
+        let reopened = HeaderChain::open(Network::Regtest, chain.path.clone()).unwrap();
+        assert_eq!(reopened.tip_hash(), Some(theirs[2].hash()));
+        std::fs::remove_file(&chain.path).unwrap();
+    }
+
+    #[test]
+    fn keep_chain_against_branch_without_more_work() {
+        let mut chain = regtest_chain("less-work");
+        let genesis = regtest_genesis();
+        let ours = mine(&chain, &genesis, 2, 1);
+        chain.connect_headers(&ours).unwrap();
+        // less work, then the same work as our chain
+        let theirs = mine(&chain, &genesis, 2, 2);
+        assert_eq!(chain.connect_headers(&theirs[..1]).unwrap(), 0);
+        assert_eq!(chain.connect_headers(&theirs).unwrap(), 0);
+        assert_eq!(chain.tip_hash(), Some(ours[1].hash()));
+        assert_eq!(chain.height_of(&theirs[0].hash()), None);
+        let reopened = HeaderChain::open(Network::Regtest, chain.path.clone()).unwrap();
+        assert_eq!(reopened.tip_hash(), Some(ours[1].hash()));
+        std::fs::remove_file(&chain.path).unwrap();
+    }
+
+    #[test]
+    fn open_rejects_damaged_store() {
+        let mut chain = regtest_chain("damaged");
+        let headers = mine(&chain, &regtest_genesis(), 2, 1);
+        chain.connect_headers(&headers).unwrap();
+        let mut file_bytes = std::fs::read(&chain.path).unwrap();
+        // zero bits in the last header, which no other header links to
+        let bits_offset = file_bytes.len() - 8;
+        file_bytes[bits_offset..bits_offset + 4].copy_from_slice(&[0; 4]);
+        for damaged in &[&file_bytes[..], &file_bytes[..file_bytes.len() - 1]] {
+            std::fs::write(&chain.path, damaged).unwrap();
+            match HeaderChain::open(Network::Regtest, chain.path.clone()) {
+                Err(HeaderChainError::CorruptedStore) => {},
+                _ => panic!("damaged store was accepted"),
+            }
+        }
+        std::fs::remove_file(&chain.path).unwrap();
+    }
+}
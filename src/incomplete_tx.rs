@@ -2,6 +2,7 @@ use crate::tx::{TxInput, TxOutput, TxOutpoint, Tx};
 use crate::script::*;
 use crate::hash::{double_sha256};
 use crate::serialize::write_var_int;
+use crate::script_interpreter::{verify_input, ScriptError};
 
 use std::io::Write;
 
@@ -79,47 +80,19 @@ impl IncompleteTx {
     }
 
     pub fn pre_images(&self, sighash_type: u32) -> Vec<PreImage> {
-        let mut hash_prevouts = [0u8; 32];
-        let mut hash_sequence = [0u8; 32];
-        let mut hash_outputs = [0u8; 32];
-        {
-            let mut outpoints_serialized = Vec::new();
-            for input in self.inputs.iter() {
-                outpoints_serialized.write(&input.outpoint.tx_hash).unwrap();
-                outpoints_serialized.write_u32::<LittleEndian>(input.outpoint.output_idx).unwrap();
-            }
-            hash_prevouts.copy_from_slice(&double_sha256(&outpoints_serialized));
-        }
-        {
-            let mut sequence_serialized = Vec::new();
-            for input in self.inputs.iter() {
-                sequence_serialized.write_u32::<LittleEndian>(input.sequence).unwrap();
-            }
-            hash_sequence.copy_from_slice(&double_sha256(&sequence_serialized));
-        }
-        {
-            let mut outputs_serialized = Vec::new();
-            for output in self.outputs.iter() {
-                output.write_to_stream(&mut outputs_serialized).unwrap();
-            }
-            hash_outputs.copy_from_slice(&double_sha256(&outputs_serialized));
-        }
-        let mut pre_images = Vec::new();
-        for input in self.inputs.iter() {
-            pre_images.push(PreImage {
-                version: self.version,
-                hash_prevouts: hash_prevouts.clone(),
-                hash_sequence: hash_sequence.clone(),
-                outpoint: input.outpoint.clone(),
-                script_code: input.output.script_code(),
-                value: input.output.value(),
-                sequence: input.sequence,
-                hash_outputs: hash_outputs.clone(),
-                lock_time: self.lock_time,
-                sighash_type,
-            });
-        }
-        pre_images
+        let unsigned_inputs = self.inputs.iter()
+            .map(|input| TxInput::new(input.outpoint.clone(), Script::empty(), input.sequence))
+            .collect();
+        let unsigned_tx = Tx::new(self.version,
+                                  unsigned_inputs,
+                                  self.outputs.clone(),
+                                  self.lock_time);
+        self.inputs.iter().enumerate()
+            .map(|(input_idx, input)| unsigned_tx.pre_image(input_idx,
+                                                            &input.output.script_code(),
+                                                            input.output.value(),
+                                                            sighash_type))
+            .collect()
     }
 
     pub fn sign(&self) -> Tx {
@@ -155,6 +128,15 @@ impl IncompleteTx {
         Tx::new(self.version, tx_inputs, self.outputs.clone(), self.lock_time)
     }
 
+    /// Runs the scripts of all inputs of `tx`, which should be the result of `sign`.
+    pub fn verify(&self, tx: &Tx) -> Result<(), ScriptError> {
+        for (input_idx, input) in self.inputs.iter().enumerate() {
+            let prev_output = TxOutput::new(input.output.value(), input.output.script());
+            verify_input(tx, input_idx, &prev_output)?;
+        }
+        Ok(())
+    }
+
     pub fn estimate_size(&self) -> u64 {
         use std::mem::{size_of_val};
         let mut size = 0;
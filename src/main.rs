@@ -2,63 +2,244 @@ pub mod message_header;
 pub mod message;
 pub mod message_error;
 pub mod version_message;
+pub mod network_message;
+pub mod block_header;
+pub mod merkle_block;
+pub mod header_chain;
 pub mod hash;
 pub mod serialize;
 pub mod tx;
 pub mod incomplete_tx;
 pub mod script;
 pub mod script_interpreter;
+pub mod script_type;
 pub mod address;
+pub mod bip32;
+pub mod network;
 pub mod outputs;
+pub mod backend;
+pub mod rpc_backend;
+pub mod electrum_backend;
+pub mod peer;
 pub mod wallet;
+pub mod tx_watcher;
+pub mod tx_store;
+pub mod wallet_file;
+pub mod wif;
 pub mod trade;
 pub mod display_qr;
 
 use std::io::{self, Write, Read};
 use text_io::{read, try_read, try_scan};
 use std::env;
+use std::rc::Rc;
 
 
 const WALLET_FILE_NAME: &str = "trade.dat";
 const SLP_AGORA_PATH: &str = ".slpagora";
 
 
-fn ensure_wallet_interactive() -> Result<wallet::Wallet, Box<std::error::Error>> {
+fn data_dir() -> Result<std::path::PathBuf, Box<dyn std::error::Error>> {
     let trades_dir = dirs::home_dir().unwrap_or(env::current_dir()?).join(SLP_AGORA_PATH);
-    let wallet_file_path = trades_dir.as_path().join(WALLET_FILE_NAME);
-    std::fs::create_dir_all(trades_dir)?;
-    match std::fs::File::open(&wallet_file_path) {
+    std::fs::create_dir_all(&trades_dir)?;
+    Ok(trades_dir)
+}
+
+fn wallet_file_path() -> Result<std::path::PathBuf, Box<dyn std::error::Error>> {
+    Ok(data_dir()?.join(WALLET_FILE_NAME))
+}
+
+fn read_new_passphrase() -> io::Result<String> {
+    loop {
+        let passphrase = rpassword::read_password_from_tty(
+            Some("Choose a passphrase to encrypt your wallet: "),
+        )?;
+        let repeated = rpassword::read_password_from_tty(Some("Repeat the passphrase: "))?;
+        if passphrase == repeated {
+            return Ok(passphrase);
+        }
+        println!("The passphrases don't match, please try again.");
+    }
+}
+
+/// Decrypts the wallet file's content, giving the user three attempts to enter the passphrase.
+fn decrypt_wallet_interactive(file_bytes: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    for _ in 0..3 {
+        let passphrase = rpassword::read_password_from_tty(
+            Some("Enter your wallet's passphrase: "),
+        )?;
+        match wallet_file::decrypt(file_bytes, &passphrase) {
+            Ok(wallet_data) => return Ok(wallet_data),
+            Err(wallet_file::WalletFileError::WrongPassphrase) => println!("Wrong passphrase."),
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Err(wallet_file::WalletFileError::WrongPassphrase.into())
+}
+
+fn wallet_from_data(wallet_data: &[u8], network: network::Network)
+        -> Result<wallet::Wallet, Box<dyn std::error::Error>> {
+    let phrase = std::str::from_utf8(wallet_data).ok()
+        .and_then(|phrase| bip39::Mnemonic::parse(phrase.trim()).ok());
+    Ok(match phrase {
+        Some(mnemonic) => wallet::Wallet::from_mnemonic(mnemonic, network)?,
+        // wallet files from before HD support contain the raw secret
+        None => wallet::Wallet::from_secret(wallet_data, network)?,
+    })
+}
+
+fn ensure_wallet_interactive(network: network::Network, backend: Rc<dyn backend::Backend>)
+        -> Result<wallet::Wallet, Box<dyn std::error::Error>> {
+    let wallet_file_path = wallet_file_path()?;
+    let mut wallet = match std::fs::File::open(&wallet_file_path) {
         Ok(mut file) => {
             println!("Using wallet file at {}", wallet_file_path.display());
-            let mut secret_bytes = [0; 32];
-            file.read(&mut secret_bytes)?;
-            Ok(wallet::Wallet::from_secret(&secret_bytes)?)
+            let mut file_bytes = Vec::new();
+            file.read_to_end(&mut file_bytes)?;
+            if wallet_file::is_encrypted(&file_bytes) {
+                wallet_from_data(&decrypt_wallet_interactive(&file_bytes)?, network)?
+            } else {
+                let wallet = wallet_from_data(&file_bytes, network)?;
+                println!("Your wallet file isn't encrypted yet.");
+                let passphrase = read_new_passphrase()?;
+                wallet_file::write_atomic(
+                    &wallet_file_path,
+                    &wallet_file::encrypt(&file_bytes, &passphrase)?,
+                )?;
+                println!("Encrypted the wallet file.");
+                wallet
+            }
         },
         Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+            print!("Enter your mnemonic phrase to restore a wallet, or leave empty to create a \
+                    new one: ");
+            io::stdout().flush()?;
+            let phrase: String = read!("{}\n");
+            let mnemonic = if phrase.trim().is_empty() {
+                use rand::RngCore;
+                let mut rng = rand::rngs::OsRng::new().unwrap();
+                let mut entropy = [0; 16];
+                rng.fill_bytes(&mut entropy);
+                let mnemonic = bip39::Mnemonic::from_entropy(&entropy)?;
+                println!("Your wallet's mnemonic phrase is:");
+                println!("{}", mnemonic);
+                println!("Write it down and keep it safe, it's the only way to restore your \
+                          wallet.");
+                mnemonic
+            } else {
+                bip39::Mnemonic::parse(phrase.trim())?
+            };
+            let passphrase = read_new_passphrase()?;
             println!("Creating wallet at {}", wallet_file_path.display());
-            use rand::RngCore;
-            let mut rng = rand::rngs::OsRng::new().unwrap();
-            let mut secret_bytes = [0; 32];
-            rng.fill_bytes(&mut secret_bytes);
-            let _ = secp256k1::SecretKey::from_slice(&secret_bytes)?;
-            std::fs::File::create(wallet_file_path)?.write(&secret_bytes)?;
-            Ok(wallet::Wallet::from_secret(&secret_bytes)?)
+            wallet_file::write_atomic(
+                &wallet_file_path,
+                &wallet_file::encrypt(mnemonic.to_string().as_bytes(), &passphrase)?,
+            )?;
+            wallet::Wallet::from_mnemonic(mnemonic, network)?
         },
         err => {err?; unreachable!()},
+    };
+    wallet.set_backend(backend);
+    match header_chain::HeaderChain::open(
+        network,
+        header_chain::header_store_path(&data_dir()?, network),
+    ) {
+        // headers are only stored after running sync-headers
+        Ok(chain) => if chain.tip_height().is_some() {
+            wallet.set_header_chain(Rc::new(chain));
+        },
+        Err(err) => println!("Couldn't load the stored block headers ({}), transactions won't \
+                              be verified.", err),
     }
+    let tx_store_path = tx_store::tx_store_path(
+        &data_dir()?,
+        network,
+        wallet.slp_address().cash_addr(),
+    );
+    match tx_store::TxStore::open(tx_store_path) {
+        Ok(tx_store) => wallet.set_tx_store(tx_store),
+        Err(err) => println!("Couldn't load the local transaction store ({}), transactions won't \
+                              be recorded.", err),
+    }
+    println!("Looking for used addresses...");
+    if let Err(err) = wallet.discover_addresses() {
+        println!("Couldn't discover the wallet's used addresses ({}), the balance might be \
+                  incomplete.", err);
+    }
+    Ok(wallet)
 }
 
-fn show_balance(w: &wallet::Wallet) {
-    let balance = w.get_balance();
+fn change_passphrase_command() -> Result<(), Box<dyn std::error::Error>> {
+    let wallet_file_path = wallet_file_path()?;
+    let file_bytes = std::fs::read(&wallet_file_path)?;
+    if !wallet_file::is_encrypted(&file_bytes) {
+        println!("The wallet file at {} isn't encrypted yet. Run slpagora without a command to \
+                  encrypt it.", wallet_file_path.display());
+        return Ok(());
+    }
+    let wallet_data = decrypt_wallet_interactive(&file_bytes)?;
+    let passphrase = read_new_passphrase()?;
+    wallet_file::write_atomic(&wallet_file_path, &wallet_file::encrypt(&wallet_data, &passphrase)?)?;
+    println!("Changed the passphrase of {}.", wallet_file_path.display());
+    Ok(())
+}
+
+fn show_balance(w: &wallet::Wallet) -> Result<(), Box<dyn std::error::Error>> {
+    let balance = match (w.get_balance(), w.tx_store()) {
+        (Ok(balance), _) => balance,
+        (Err(err), Some(tx_store)) if tx_store.last_update().is_some() => {
+            println!("Couldn't fetch the balance ({}), showing the one stored at UNIX time {}.",
+                     err, tx_store.last_update().unwrap());
+            tx_store.cached_balance()
+        },
+        (Err(err), _) => return Err(err.into()),
+    };
     println!("Your wallet's balance is: {} sats or {} BCH.",
              balance,
              balance as f64 / 100_000_000.0);
+    if let Some(chain) = w.header_chain() {
+        println!("Of that, {} sats are verified to be in blocks up to height {}.",
+                 w.get_verified_balance()?,
+                 chain.tip_height().unwrap());
+    }
     println!("Your wallet's address is: {}", w.address().cash_addr());
     display_qr::display(w.address().cash_addr().as_bytes());
+    println!("Your wallet's address for receiving tokens is: {}", w.slp_address().cash_addr());
+    Ok(())
+}
+
+fn show_history(w: &wallet::Wallet) {
+    let tx_store = match w.tx_store() {
+        Some(tx_store) => tx_store,
+        None => {
+            println!("There's no local transaction store.");
+            return;
+        },
+    };
+    println!("Transactions sent from this wallet:");
+    for stored_tx in tx_store.transactions() {
+        println!("{} at UNIX time {}", stored_tx.txid, stored_tx.timestamp);
+    }
+    println!("Unspent outputs as of the last update:");
+    for output in tx_store.outputs().iter().filter(|output| !output.is_spent) {
+        let pending = if tx_store.is_pending_spend(&output.txid, output.vout) {
+            " (spent by a pending transaction)"
+        } else {
+            ""
+        };
+        println!("{}:{} {:10} sats at {}{}",
+                 output.txid, output.vout, output.satoshis, output.address, pending);
+    }
+    println!("Trade contracts created by this wallet:");
+    for trade in tx_store.trades() {
+        println!("{} selling {} of token {}... for {} sats, {}",
+                 trade.contract_address, trade.sell_amount, &trade.token_id[..8],
+                 trade.buy_amount, trade.status());
+    }
 }
 
 fn do_transaction(w: &wallet::Wallet) -> Result<(), Box<std::error::Error>> {
-    let (mut tx_build, balance) = w.init_transaction();
+    let (mut tx_build, balance) = w.init_transaction()?;
     println!("Your wallet's balance is: {} sats or {} BCH.",
              balance,
              balance as f64 / 100_000_000.0);
@@ -67,20 +248,41 @@ fn do_transaction(w: &wallet::Wallet) -> Result<(), Box<std::error::Error>> {
                   BCH to your wallet's address: {}", balance, w.address().cash_addr());
         return Ok(());
     }
-    print!("Enter the address to send to: ");
+    print!("Enter the address to send to (CashAddr or legacy): ");
     io::stdout().flush()?;
     let addr_str: String = read!("{}\n");
     let addr_str = addr_str.trim();
-    let receiving_addr = match address::Address::from_cash_addr(addr_str.to_string())  {
+    let receiving_addr = match address::Address::from_any(addr_str, w.network())  {
         Ok(addr) => addr,
         Err(err) => {
             println!("Please enter a valid address: {:?}", err);
             return Ok(());
         }
     };
-    if receiving_addr.prefix() == "simpleledger" {
-        println!("Note: You entered a Simple Ledger Protocol (SLP) address, but this wallet only \
-                  contains ordinary non-token BCH. The program will proceed anyways.");
+    let receiving_addr = match receiving_addr.network() {
+        Some(network) if network == w.network() => receiving_addr,
+        // legacy addresses can't tell testnet and regtest apart
+        Some(network::Network::Testnet) if w.network() == network::Network::Regtest &&
+                receiving_addr.legacy_addr().as_deref() == Some(addr_str) =>
+            address::Address::from_hash_prefix(w.network().bch_prefix(),
+                                                receiving_addr.addr_type(),
+                                                receiving_addr.bytes().to_vec())?,
+        _ => {
+            println!("The address {} is not an address on {}.", addr_str, w.network());
+            return Ok(());
+        },
+    };
+    if receiving_addr.is_slp() {
+        println!("You entered a Simple Ledger Protocol (SLP) address, but this wallet only \
+                  contains ordinary non-token BCH. Wallets receiving on SLP addresses usually \
+                  expect tokens, and sending BCH to them can cause tokens to be burned later.");
+        print!("Type \"send anyway\" (without quotes) to send to this address regardless: ");
+        io::stdout().flush()?;
+        let override_str: String = read!("{}\n");
+        if override_str.trim() != "send anyway" {
+            println!("Not sending.");
+            return Ok(());
+        }
     }
     print!("Enter the amount in satoshis to send, or \"all\" (without quotes) to send the entire \
             balance: ");
@@ -99,7 +301,7 @@ fn do_transaction(w: &wallet::Wallet) -> Result<(), Box<std::error::Error>> {
     let send_idx = tx_build.add_output(&output_send);
     let mut output_back_to_wallet = outputs::P2PKHOutput {
         value: 0,
-        address: w.address().clone(),
+        address: w.change_address(),
     };
     let back_to_wallet_idx = tx_build.add_output(&output_back_to_wallet);
     let estimated_size = tx_build.estimate_size();
@@ -117,14 +319,243 @@ fn do_transaction(w: &wallet::Wallet) -> Result<(), Box<std::error::Error>> {
         tx_build.replace_output(back_to_wallet_idx, &output_back_to_wallet);
     }
     let tx = tx_build.sign();
+    tx_build.verify(&tx)?;
     let response = w.send_tx(&tx)?;
     println!("Sent transaction. Transaction ID is: {}", response);
 
     Ok(())
 }
 
+fn show_backup(w: &wallet::Wallet) {
+    match w.mnemonic() {
+        Some(mnemonic) => println!("{}", mnemonic),
+        None => {
+            println!("This wallet doesn't have a mnemonic phrase.");
+            return;
+        },
+    }
+    if let Some((address, wif)) = w.legacy_key_wif() {
+        println!("This wallet was created before mnemonic phrases were supported. The phrase \
+                  doesn't restore its old address {}, so also back up its private key:",
+                 address.cash_addr());
+        println!("{}", wif);
+        println!("Alternatively, sweep the key into this wallet (option 7) and keep the phrase \
+                  only. Anyone knowing the key can spend its funds. Never share it.");
+    }
+}
+
+fn export_wif(w: &wallet::Wallet) {
+    println!("Anyone knowing these keys can spend the wallet's BCH. Never share them.");
+    for (address, wif) in w.export_wif() {
+        println!("{} {}", address.cash_addr(), wif);
+    }
+}
+
+fn sweep_interactive(w: &wallet::Wallet) -> Result<(), Box<dyn std::error::Error>> {
+    let wif_str = rpassword::read_password_from_tty(
+        Some("Enter the private key (WIF) to sweep: "),
+    )?;
+    let wif_key = match wif::from_wif(wif_str.trim()) {
+        Ok(wif_key) => wif_key,
+        Err(err) => {
+            println!("Please enter a valid private key: {}", err);
+            return Ok(());
+        },
+    };
+    let mut swept_wallet = match wallet::Wallet::from_wif(&wif_key, w.network()) {
+        Ok(swept_wallet) => swept_wallet,
+        Err(err) => {
+            println!("The private key can't be used on {}: {}", w.network(), err);
+            return Ok(());
+        },
+    };
+    swept_wallet.set_backend(w.backend().clone());
+    println!("Sweeping address {}", swept_wallet.address().cash_addr());
+    let (mut tx_build, balance) = swept_wallet.init_transaction()?;
+    let mut output_sweep = outputs::P2PKHOutput {
+        value: 0,  // for generating tx size
+        address: w.fresh_receive_address(),
+    };
+    let sweep_idx = tx_build.add_output(&output_sweep);
+    let estimated_size = {
+        let mut tx_ser = Vec::new();
+        tx_build.sign().write_to_stream(&mut tx_ser)?;
+        tx_ser.len() as u64
+    };
+    let fee = estimated_size + 5;
+    if balance < fee + w.dust_amount() {
+        println!("The key's balance ({} sats) isn't sufficient to pay for sweeping it.", balance);
+        return Ok(());
+    }
+    output_sweep.value = balance - fee;
+    tx_build.replace_output(sweep_idx, &output_sweep);
+    let tx = tx_build.sign();
+    tx_build.verify(&tx)?;
+    let response = w.send_tx(&tx)?;
+    println!("Swept {} sats into {}. Transaction ID is: {}",
+             output_sweep.value, output_sweep.address.cash_addr(), response);
+    Ok(())
+}
+
+fn trace_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    if args.len() != 4 {
+        println!("Usage: slpagora trace <tx hex> <input index> <prev output script hex> \
+                  <prev output value in satoshis>");
+        return Ok(());
+    }
+    let tx = tx::Tx::from_hex(&args[0])?;
+    let input_idx: usize = args[1].parse()?;
+    let prev_output = tx::TxOutput::new(
+        args[3].parse()?,
+        script::Script::from_serialized_lenient(&hex::decode(&args[2])?),
+    );
+    let (trace, result) = script_interpreter::trace_input(&tx, input_idx, &prev_output);
+    let mut script_idx = None;
+    for (i, step) in trace.iter().enumerate() {
+        if script_idx != Some(step.script_idx) {
+            script_idx = Some(step.script_idx);
+            println!("--------------- script {} ---------------", step.script_idx);
+        }
+        print!("{:4} {}", i, step);
+    }
+    match result {
+        Ok(()) => println!("Input {} is valid.", input_idx),
+        Err(err) => println!("Input {} is invalid: {}", input_idx, err),
+    }
+    Ok(())
+}
+
+fn sync_headers_command(network: network::Network, args: &[String])
+        -> Result<(), Box<dyn std::error::Error>> {
+    let peer_addr = match args {
+        [peer_addr] => peer_addr,
+        _ => {
+            println!("Usage: slpagora sync-headers <host[:port]>");
+            return Ok(());
+        },
+    };
+    let mut chain = header_chain::HeaderChain::open(
+        network,
+        header_chain::header_store_path(&data_dir()?, network),
+    )?;
+    println!("Connecting to {}...", peer_addr);
+    let mut peer = peer::Peer::connect_host(peer_addr, network, false)?;
+    let num_added = chain.sync(&mut peer)?;
+    let mut tip_hash = chain.tip_hash().unwrap();
+    tip_hash.reverse();
+    println!("Added {} headers. Tip is block {} at height {}.",
+             num_added, hex::encode(tip_hash), chain.tip_height().unwrap());
+    Ok(())
+}
+
+fn print_usage() -> Result<(), Box<dyn std::error::Error>> {
+    println!("Usage: slpagora [--network <mainnet|testnet|regtest>] \
+              [--rpc-url <http://[user:password@]host:port>] [--rpc-cookie <path>] [--rpc-scan] \
+              [--electrum <tcp|tls>://host:port] [--broadcast-peer <host[:port]>] [command]");
+    Ok(())
+}
+
+/// Creates a backend for the node at `rpc_url`. The RPC credentials are either part of the URL
+/// or read from the node's cookie file.
+fn rpc_backend_from_args(network: network::Network,
+                         rpc_url: &str,
+                         rpc_cookie: Option<String>,
+                         utxo_lookup: rpc_backend::UtxoLookup)
+        -> Result<rpc_backend::RpcBackend, Box<dyn std::error::Error>> {
+    let mut url = reqwest::Url::parse(rpc_url)?;
+    let auth = match (rpc_cookie, url.password()) {
+        (Some(path), _) => rpc_backend::RpcAuth::Cookie(path.into()),
+        (None, Some(password)) => {
+            rpc_backend::RpcAuth::UserPass(url.username().to_string(), password.to_string())
+        },
+        (None, None) => rpc_backend::RpcAuth::None,
+    };
+    url.set_username("").unwrap();
+    url.set_password(None).unwrap();
+    Ok(rpc_backend::RpcBackend::new(
+        network,
+        url.to_string(),
+        auth,
+        utxo_lookup,
+        Box::new(backend::RestBackend::new(network)),
+    ))
+}
+
+/// Creates a backend for the Electrum server at `electrum_url`, e.g. tls://fulcrum.example:50002.
+fn electrum_backend_from_args(network: network::Network, electrum_url: &str)
+        -> Result<electrum_backend::ElectrumBackend, Box<dyn std::error::Error>> {
+    let url = reqwest::Url::parse(electrum_url)?;
+    let use_tls = match url.scheme() {
+        "tcp" => false,
+        "tls" | "ssl" => true,
+        scheme => return Err(format!("Unknown Electrum protocol: {}", scheme).into()),
+    };
+    let host = url.host_str().ok_or("Electrum URL is missing the host")?;
+    let port = url.port().ok_or("Electrum URL is missing the port")?;
+    Ok(electrum_backend::ElectrumBackend::new(
+        host.to_string(),
+        port,
+        use_tls,
+        Box::new(backend::RestBackend::new(network)),
+    ))
+}
+
 fn main() -> Result<(), Box<std::error::Error>> {
-    let wallet = ensure_wallet_interactive()?;
+    let mut args = env::args().skip(1).collect::<Vec<_>>();
+    let mut network = network::Network::Mainnet;
+    let mut rpc_url = None;
+    let mut rpc_cookie = None;
+    let mut electrum_url = None;
+    let mut broadcast_peer = None;
+    let mut utxo_lookup = rpc_backend::UtxoLookup::WatchOnly;
+    while let Some(flag) = args.first().filter(|arg| arg.starts_with("--")).cloned() {
+        let value = args.get(1).cloned();
+        match (flag.as_str(), value) {
+            ("--network", Some(name)) => match network::Network::from_name(&name) {
+                Some(selected_network) => network = selected_network,
+                None => return print_usage(),
+            },
+            ("--rpc-url", Some(url)) => rpc_url = Some(url),
+            ("--rpc-cookie", Some(path)) => rpc_cookie = Some(path),
+            ("--electrum", Some(url)) => electrum_url = Some(url),
+            ("--broadcast-peer", Some(peer_addr)) => broadcast_peer = Some(peer_addr),
+            ("--rpc-scan", _) => {
+                utxo_lookup = rpc_backend::UtxoLookup::ScanTxOutSet;
+                args.remove(0);
+                continue;
+            },
+            _ => return print_usage(),
+        }
+        args.drain(..2);
+    }
+    match args.first().map(String::as_str) {
+        Some("trace") => return trace_command(&args[1..]),
+        Some("change-passphrase") => return change_passphrase_command(),
+        Some("sync-headers") => return sync_headers_command(network, &args[1..]),
+        Some(command) => {
+            println!("Unknown command: {}", command);
+            return Ok(());
+        },
+        None => {},
+    }
+
+    let mut backend: Box<dyn backend::Backend> = match (rpc_url, electrum_url) {
+        (Some(_), Some(_)) => return print_usage(),
+        (Some(rpc_url), None) => {
+            Box::new(rpc_backend_from_args(network, &rpc_url, rpc_cookie, utxo_lookup)?)
+        },
+        (None, Some(electrum_url)) => {
+            Box::new(electrum_backend_from_args(network, &electrum_url)?)
+        },
+        (None, None) => Box::new(backend::RestBackend::new(network)),
+    };
+    if let Some(peer_addr) = broadcast_peer {
+        backend = Box::new(peer::PeerBroadcastBackend::new(network, peer_addr, backend));
+    }
+    let wallet = ensure_wallet_interactive(network, Rc::from(backend))?;
+    if network != network::Network::Mainnet {
+        println!("Using {}.", network);
+    }
     println!("Your wallet address is: {}", wallet.address().cash_addr());
 
     loop {
@@ -134,15 +565,25 @@ fn main() -> Result<(), Box<std::error::Error>> {
         println!("2: Send BCH from this wallet to an address");
         println!("3: Create a new trade for a token on the BCH blockchain");
         println!("4: List all available token trades on the BCH blockchain");
+        println!("5: Show the wallet's mnemonic phrase for backup");
+        println!("6: Export the wallet's private keys (WIF)");
+        println!("7: Sweep all BCH of a private key (WIF) into this wallet");
+        println!("8: Show the wallet's transactions and trades (works offline)");
+        println!("9: Resume or cancel a trade created by this wallet");
         println!("Anything else: Exit");
         print!("Your choice: ");
         io::stdout().flush()?;
         let choice: String = read!("{}\n");
         match choice.trim() {
-            "1" => show_balance(&wallet),
+            "1" => show_balance(&wallet)?,
             "2" => do_transaction(&wallet)?,
             "3" => trade::create_trade_interactive(&wallet)?,
             "4" => trade::accept_trades_interactive(&wallet)?,
+            "5" => show_backup(&wallet),
+            "6" => export_wif(&wallet),
+            "7" => sweep_interactive(&wallet)?,
+            "8" => show_history(&wallet),
+            "9" => trade::resume_trade_interactive(&wallet)?,
             _ => {
                 println!("Bye, have a great time!");
                 return Ok(());
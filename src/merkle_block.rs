@@ -0,0 +1,379 @@
+use crate::block_header::BlockHeader;
+use crate::hash::double_sha256;
+use crate::serialize::{read_var_int, write_var_int};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{self, Read, Write};
+
+
+/// Blocks can't contain more transactions than fit into the maximum block size.
+const MAX_BLOCK_SIZE: u32 = 32_000_000;
+const MIN_TX_SIZE: u32 = 60;
+
+#[derive(Debug)]
+pub enum MerkleError {
+    NoTransactions,
+    TooManyTransactions(u32),
+    TooManyHashes,
+    /// The flags ended before the tree was fully traversed.
+    NotEnoughFlags,
+    /// The hashes ended before the tree was fully traversed.
+    NotEnoughHashes,
+    /// Both children of a node have the same hash, which can be used to fake inclusion.
+    DuplicateHashes,
+    UnusedFlags,
+    UnusedHashes,
+    /// The tree's root doesn't match the header's merkle root.
+    RootMismatch,
+    /// The position of a merkle branch's transaction doesn't fit the branch's length.
+    InvalidPosition(u32),
+}
+
+impl std::fmt::Display for MerkleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for MerkleError {
+
+}
+
+/// A block header with a partial merkle tree proving that some of the block's transactions are
+/// included in it, as sent in 'merkleblock' messages and returned by gettxoutproof.
+#[derive(Clone, Debug)]
+pub struct MerkleBlock {
+    pub header: BlockHeader,
+    pub total_txs: u32,
+    /// Hashes of the tree in depth-first order, in byte order.
+    pub hashes: Vec<[u8; 32]>,
+    /// Bits in depth-first order whether a node is the parent of a matched transaction, least
+    /// significant bit first.
+    pub flags: Vec<u8>,
+}
+
+/// The hashes needed to compute the merkle root from a single transaction, as returned by
+/// Electrum servers.
+#[derive(Clone, Debug)]
+pub struct MerkleBranch {
+    pub block_height: u32,
+    /// Index of the transaction in the block.
+    pub pos: u32,
+    /// Siblings of the nodes from the transaction up to the root, in byte order.
+    pub hashes: Vec<[u8; 32]>,
+}
+
+/// Proof that a transaction is included in a block, depending on what the backend provides.
+#[derive(Clone, Debug)]
+pub enum TxProof {
+    MerkleBlock(MerkleBlock),
+    MerkleBranch(MerkleBranch),
+}
+
+/// Traversal state of a partial merkle tree.
+struct TreeTraversal<'a> {
+    merkle_block: &'a MerkleBlock,
+    bits_used: usize,
+    hashes_used: usize,
+    matches: Vec<[u8; 32]>,
+}
+
+impl<'a> TreeTraversal<'a> {
+    fn tree_width(&self, height: u32) -> u32 {
+        ((self.merkle_block.total_txs as u64 + (1 << height) - 1) >> height) as u32
+    }
+
+    fn next_flag(&mut self) -> Result<bool, MerkleError> {
+        let flags = &self.merkle_block.flags;
+        if self.bits_used >= flags.len() * 8 {
+            return Err(MerkleError::NotEnoughFlags);
+        }
+        let flag = flags[self.bits_used / 8] & (1 << (self.bits_used % 8)) != 0;
+        self.bits_used += 1;
+        Ok(flag)
+    }
+
+    fn next_hash(&mut self) -> Result<[u8; 32], MerkleError> {
+        let hash = *self.merkle_block.hashes.get(self.hashes_used)
+            .ok_or(MerkleError::NotEnoughHashes)?;
+        self.hashes_used += 1;
+        Ok(hash)
+    }
+
+    /// Computes the hash of the node at `height` and `pos`, collecting matched transactions.
+    fn traverse(&mut self, height: u32, pos: u32) -> Result<[u8; 32], MerkleError> {
+        let is_parent_of_match = self.next_flag()?;
+        if height == 0 || !is_parent_of_match {
+            let hash = self.next_hash()?;
+            if height == 0 && is_parent_of_match {
+                self.matches.push(hash);
+            }
+            return Ok(hash);
+        }
+        let left = self.traverse(height - 1, pos * 2)?;
+        let right = if pos * 2 + 1 < self.tree_width(height - 1) {
+            let right = self.traverse(height - 1, pos * 2 + 1)?;
+            if right == left {
+                return Err(MerkleError::DuplicateHashes);
+            }
+            right
+        } else {
+            left
+        };
+        let mut concat = left.to_vec();
+        concat.extend_from_slice(&right);
+        Ok(double_sha256(&concat))
+    }
+}
+
+impl MerkleBlock {
+    pub fn write_to_stream<W: Write>(&self, write: &mut W) -> io::Result<()> {
+        self.header.write_to_stream(write)?;
+        write.write_u32::<LittleEndian>(self.total_txs)?;
+        write_var_int(write, self.hashes.len() as u64)?;
+        for hash in self.hashes.iter() {
+            write.write_all(hash)?;
+        }
+        write_var_int(write, self.flags.len() as u64)?;
+        write.write_all(&self.flags)?;
+        Ok(())
+    }
+
+    pub fn from_stream<R: Read>(read: &mut R) -> io::Result<Self> {
+        let header = BlockHeader::from_stream(read)?;
+        let total_txs = read.read_u32::<LittleEndian>()?;
+        let num_hashes = read_var_int(read)?;
+        if num_hashes > total_txs as u64 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "too many hashes"));
+        }
+        let mut hashes = Vec::new();
+        for _ in 0..num_hashes {
+            let mut hash = [0; 32];
+            read.read_exact(&mut hash)?;
+            hashes.push(hash);
+        }
+        let num_flag_bytes = read_var_int(read)?;
+        let mut flags = Vec::new();
+        read.take(num_flag_bytes).read_to_end(&mut flags)?;
+        if flags.len() as u64 != num_flag_bytes {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(MerkleBlock { header, total_txs, hashes, flags })
+    }
+
+    pub fn from_hex(hex_str: &str) -> io::Result<Self> {
+        let bytes = hex::decode(hex_str)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid hex"))?;
+        Self::from_stream(&mut io::Cursor::new(bytes))
+    }
+
+    /// Verifies the partial merkle tree against the header's merkle root and returns the hashes
+    /// (in byte order) of the transactions it proves to be in the block.
+    pub fn extract_matches(&self) -> Result<Vec<[u8; 32]>, MerkleError> {
+        if self.total_txs == 0 {
+            return Err(MerkleError::NoTransactions);
+        }
+        if self.total_txs > MAX_BLOCK_SIZE / MIN_TX_SIZE {
+            return Err(MerkleError::TooManyTransactions(self.total_txs));
+        }
+        if self.hashes.len() > self.total_txs as usize {
+            return Err(MerkleError::TooManyHashes);
+        }
+        let mut traversal = TreeTraversal {
+            merkle_block: self,
+            bits_used: 0,
+            hashes_used: 0,
+            matches: Vec::new(),
+        };
+        let mut height = 0;
+        while traversal.tree_width(height) > 1 {
+            height += 1;
+        }
+        let root = traversal.traverse(height, 0)?;
+        if traversal.bits_used.div_ceil(8) != self.flags.len() {
+            return Err(MerkleError::UnusedFlags);
+        }
+        if traversal.hashes_used != self.hashes.len() {
+            return Err(MerkleError::UnusedHashes);
+        }
+        if root != self.header.merkle_root {
+            return Err(MerkleError::RootMismatch);
+        }
+        Ok(traversal.matches)
+    }
+}
+
+impl MerkleBranch {
+    /// Computes the merkle root of a block including the transaction with the hash (in byte
+    /// order) at `pos`.
+    pub fn merkle_root(&self, tx_hash: &[u8; 32]) -> Result<[u8; 32], MerkleError> {
+        if self.hashes.len() >= 32 || self.pos >> self.hashes.len() != 0 {
+            return Err(MerkleError::InvalidPosition(self.pos));
+        }
+        let mut hash = *tx_hash;
+        for (height, sibling) in self.hashes.iter().enumerate() {
+            let mut concat = Vec::with_capacity(64);
+            if self.pos & (1 << height) == 0 {
+                concat.extend_from_slice(&hash);
+                concat.extend_from_slice(sibling);
+            } else {
+                concat.extend_from_slice(sibling);
+                concat.extend_from_slice(&hash);
+            }
+            hash = double_sha256(&concat);
+        }
+        Ok(hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tx::tx_hex_to_hash;
+
+    /// Transactions of mainnet block 100000 and its merkle root.
+    const BLOCK_100000_TXIDS: [&str; 4] = [
+        "8c14f0db3df150123e6f3dbbf30f8b955a8249b62ac1d1ff16284aefa3d06d87",
+        "fff2525b8931402dd09222c50775608f75787bd2b87e56995a7bdd30f79702c4",
+        "6359f0868171b1d194cbee1af2f16ea598ae8fad666d9b012c8ed2b79a236ec4",
+        "e9a66845e05d5abc0ad04ec80f774a7e585c6e8db975962d069a522137b80c1d",
+    ];
+    const BLOCK_100000_MERKLE_ROOT: &str =
+        "f3e94742aca4b5ef85488dc37c06c3282295ffec960994b2c0d5ac2a25a95766";
+
+    /// gettxoutproof of the third transaction of block 100000.
+    const BLOCK_100000_PROOF: &str = "\
+        0100000050120119172a610421a6c3011dd330d9df07b63616c2cc1f1cd00200000000006657a9252aacd5\
+        c0b2940996ecff952228c3067cc38d4885efb5a4ac4247e9f337221b4d4c86041b0f2b5710040000000315\
+        b88c5107195bf09eb9da89b83d95b3d070079a3c5c5d3d17d0dcd873fbdaccc46e239ab7d28e2c019b6d66\
+        ad8fae98a56ef1f21aeecb94d1b1718186f059631d0cb83721529a062d9675b98d6e5c587e4a770fc84ed0\
+        0abc5a5de04568a6e9010d";
+    /// gettxoutproof of the coinbase of the genesis block, the only transaction in the block.
+    const GENESIS_PROOF: &str = "\
+        0100000000000000000000000000000000000000000000000000000000000000000000003ba3edfd7a7b12\
+        b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a29ab5f49ffff001d1dac2b7c01000000013b\
+        a3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a0101";
+
+    fn hash_pair(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+        let mut concat = left.to_vec();
+        concat.extend_from_slice(right);
+        double_sha256(&concat)
+    }
+
+    /// Merkle branch of the transaction at `pos`, duplicating the last node of odd levels.
+    fn branch(tx_hashes: &[[u8; 32]], pos: u32) -> MerkleBranch {
+        let mut level = tx_hashes.to_vec();
+        let mut idx = pos as usize;
+        let mut hashes = Vec::new();
+        while level.len() > 1 {
+            if level.len() % 2 == 1 {
+                level.push(*level.last().unwrap());
+            }
+            hashes.push(level[idx ^ 1]);
+            level = level.chunks(2).map(|pair| hash_pair(&pair[0], &pair[1])).collect();
+            idx /= 2;
+        }
+        MerkleBranch { block_height: 100_000, pos, hashes }
+    }
+
+    fn block_100000_hashes() -> Vec<[u8; 32]> {
+        BLOCK_100000_TXIDS.iter().map(|txid| tx_hex_to_hash(txid)).collect()
+    }
+
+    #[test]
+    fn merkle_branch_block_100000() {
+        let tx_hashes = block_100000_hashes();
+        let merkle_root = tx_hex_to_hash(BLOCK_100000_MERKLE_ROOT);
+        for (pos, tx_hash) in tx_hashes.iter().enumerate() {
+            let branch = branch(&tx_hashes, pos as u32);
+            assert_eq!(branch.merkle_root(tx_hash).unwrap(), merkle_root);
+            // a branch only proves the transaction at its position
+            let other_hash = &tx_hashes[(pos + 1) % tx_hashes.len()];
+            assert_ne!(branch.merkle_root(other_hash).unwrap(), merkle_root);
+        }
+    }
+
+    #[test]
+    fn merkle_branch_odd_width() {
+        let tx_hashes = block_100000_hashes()[..3].to_vec();
+        let merkle_root = hash_pair(&hash_pair(&tx_hashes[0], &tx_hashes[1]),
+                                    &hash_pair(&tx_hashes[2], &tx_hashes[2]));
+        for (pos, tx_hash) in tx_hashes.iter().enumerate() {
+            assert_eq!(branch(&tx_hashes, pos as u32).merkle_root(tx_hash).unwrap(), merkle_root);
+        }
+    }
+
+    #[test]
+    fn merkle_branch_single_tx() {
+        let tx_hash = block_100000_hashes()[0];
+        let branch = MerkleBranch { block_height: 1, pos: 0, hashes: vec![] };
+        assert_eq!(branch.merkle_root(&tx_hash).unwrap(), tx_hash);
+    }
+
+    #[test]
+    fn merkle_branch_rejects_invalid_position() {
+        let tx_hashes = block_100000_hashes();
+        let mut branch = branch(&tx_hashes, 3);
+        branch.pos = 4;
+        assert!(matches!(branch.merkle_root(&tx_hashes[3]), Err(MerkleError::InvalidPosition(4))));
+    }
+
+    #[test]
+    fn merkle_block_block_100000() {
+        let merkle_block = MerkleBlock::from_hex(BLOCK_100000_PROOF).unwrap();
+        let mut block_hash = merkle_block.header.hash();
+        block_hash.reverse();
+        assert_eq!(hex::encode(block_hash),
+                   "000000000003ba27aa200b1cecaad478d2b00432346c3f1f3986da1afd33e506");
+        assert_eq!(merkle_block.extract_matches().unwrap(), vec![block_100000_hashes()[2]]);
+        let mut proof_ser = Vec::new();
+        merkle_block.write_to_stream(&mut proof_ser).unwrap();
+        assert_eq!(hex::encode(proof_ser), BLOCK_100000_PROOF);
+    }
+
+    #[test]
+    fn merkle_block_single_tx() {
+        let merkle_block = MerkleBlock::from_hex(GENESIS_PROOF).unwrap();
+        assert_eq!(merkle_block.total_txs, 1);
+        assert_eq!(merkle_block.extract_matches().unwrap(), vec![merkle_block.header.merkle_root]);
+    }
+
+    /// Proof of the last transaction of a block with the first three transactions of block
+    /// 100000, which has no sibling at the bottom level.
+    fn odd_width_proof() -> MerkleBlock {
+        let tx_hashes = block_100000_hashes();
+        let mut header = MerkleBlock::from_hex(BLOCK_100000_PROOF).unwrap().header;
+        header.merkle_root = hash_pair(&hash_pair(&tx_hashes[0], &tx_hashes[1]),
+                                       &hash_pair(&tx_hashes[2], &tx_hashes[2]));
+        MerkleBlock {
+            header,
+            total_txs: 3,
+            hashes: vec![hash_pair(&tx_hashes[0], &tx_hashes[1]), tx_hashes[2]],
+            flags: vec![0b1101],
+        }
+    }
+
+    #[test]
+    fn merkle_block_odd_width() {
+        let merkle_block = odd_width_proof();
+        assert_eq!(merkle_block.extract_matches().unwrap(), vec![block_100000_hashes()[2]]);
+    }
+
+    #[test]
+    fn merkle_block_rejects_duplicate_hashes() {
+        // CVE-2012-2459: duplicating the last transaction keeps the merkle root, so a block of
+        // four transactions would prove a transaction at a position the block doesn't have
+        let mut merkle_block = odd_width_proof();
+        let tx_hash = merkle_block.hashes[1];
+        merkle_block.total_txs = 4;
+        merkle_block.hashes.push(tx_hash);
+        merkle_block.flags = vec![0b10101];
+        assert!(matches!(merkle_block.extract_matches(), Err(MerkleError::DuplicateHashes)));
+    }
+
+    #[test]
+    fn merkle_block_rejects_root_mismatch() {
+        let mut merkle_block = MerkleBlock::from_hex(BLOCK_100000_PROOF).unwrap();
+        merkle_block.header.merkle_root[0] ^= 1;
+        assert!(matches!(merkle_block.extract_matches(), Err(MerkleError::RootMismatch)));
+    }
+}
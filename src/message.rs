@@ -2,8 +2,12 @@ use std::{io, io::Write};
 use crate::message_header::MessageHeader;
 use crate::message_error::MessageError;
 use crate::hash::double_sha256;
+use crate::network::Network;
 
 
+/// Upper bound for payloads, the size of the largest blocks on BCH.
+pub const MAX_PAYLOAD_SIZE: u32 = 32 * 1024 * 1024;
+
 #[derive(Clone, Debug)]
 pub struct Message {
     header: MessageHeader,
@@ -11,10 +15,19 @@ pub struct Message {
 }
 
 impl Message {
-    pub fn from_stream<R: io::Read>(read: &mut R) -> Result<Message, MessageError> {
-        let header = MessageHeader::from_stream(read)?;
-        let mut payload = vec![0; header.payload_size() as usize];
-        read.read_exact(&mut payload[..])?;
+    pub fn from_stream<R: io::Read>(network: Network, read: &mut R) -> Result<Message, MessageError> {
+        use std::io::Read;
+        let header = MessageHeader::from_stream(network, read)?;
+        if header.payload_size() > MAX_PAYLOAD_SIZE {
+            return Err(MessageError::PayloadTooLarge(header.payload_size()))
+        }
+        // the size comes from the peer, only allocate for data that actually arrived
+        let payload_size = header.payload_size() as u64;
+        let mut payload = Vec::new();
+        read.take(payload_size).read_to_end(&mut payload)?;
+        if (payload.len() as u64) < payload_size {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
         let hash = double_sha256(&payload);
         if &hash[..4] != header.checksum() {
             return Err(MessageError::InvalidChecksum)
@@ -25,13 +38,14 @@ impl Message {
         })
     }
 
-    pub fn from_payload(command: &[u8], payload: Vec<u8>) -> Message {
+    pub fn from_payload(network: Network, command: &[u8], payload: Vec<u8>) -> Message {
         let hash = double_sha256(&payload);
         let mut checksum = [0; 4];
         checksum.copy_from_slice(&hash[..4]);
         let mut command_padded = [0u8; 12];
         io::Cursor::new(&mut command_padded[..]).write(command).unwrap();
         let header = MessageHeader::new(
+            network,
             command_padded,
             payload.len() as u32,
             checksum,
@@ -44,13 +58,17 @@ impl Message {
 
     pub fn write_to_stream<W: io::Write>(&self, write: &mut W) -> Result<(), MessageError> {
         self.header.write_to_stream(write)?;
-        write.write(&self.payload)?;
+        write.write_all(&self.payload)?;
         Ok(())
     }
 
     pub fn header(&self) -> &MessageHeader {
         &self.header
     }
+
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
 }
 
 impl std::fmt::Display for Message {
@@ -60,3 +78,38 @@ impl std::fmt::Display for Message {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn serialize(message: &Message) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        message.write_to_stream(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn round_trip() {
+        let message = Message::from_payload(Network::Mainnet, b"ping", vec![1; 8]);
+        let bytes = serialize(&message);
+        let decoded = Message::from_stream(Network::Mainnet, &mut io::Cursor::new(&bytes)).unwrap();
+        assert_eq!(decoded.payload(), message.payload());
+        assert_eq!(serialize(&decoded), bytes);
+    }
+
+    #[test]
+    fn rejects_missing_payload() {
+        let message = Message::from_payload(Network::Mainnet, b"ping", vec![1, 2, 3, 4]);
+        let mut bytes = serialize(&message);
+        // claims the maximum size, but only 4 bytes follow
+        bytes[16..20].copy_from_slice(&MAX_PAYLOAD_SIZE.to_le_bytes());
+        match Message::from_stream(Network::Mainnet, &mut io::Cursor::new(&bytes)) {
+            Err(MessageError::IoError(err)) => assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof),
+            result => panic!("unexpected result {:?}", result),
+        }
+        bytes[16..20].copy_from_slice(&(MAX_PAYLOAD_SIZE + 1).to_le_bytes());
+        assert!(matches!(Message::from_stream(Network::Mainnet, &mut io::Cursor::new(&bytes)),
+                         Err(MessageError::PayloadTooLarge(_))));
+    }
+}
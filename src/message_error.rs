@@ -7,6 +7,12 @@ use std::fmt::{Display, Formatter};
 pub enum MessageError {
     WrongMagic,
     InvalidChecksum,
+    PayloadTooLarge(u32),
+    UnknownCommand(String),
+    /// The payload of the message with the given command couldn't be decoded.
+    MalformedPayload(String),
+    /// The peer rejected a message we sent, with its reason.
+    Rejected(String),
     IoError(io::Error),
 }
 
@@ -2,38 +2,40 @@ use std::io;
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 
 use crate::message_error::MessageError;
+use crate::network::Network;
 
 
 #[derive(Clone, Debug)]
 pub struct MessageHeader {
+    network: Network,
     command: [u8; 12],
     payload_size: u32,
     checksum: [u8; 4],
 }
 
-pub const MESSAGE_MAGIC: &[u8] = b"\xe3\xe1\xf3\xe8";
-
 impl MessageHeader {
-    pub fn new(command: [u8; 12],
+    pub fn new(network: Network,
+               command: [u8; 12],
                payload_size: u32,
                checksum: [u8; 4]) -> Self {
         MessageHeader {
-            command, payload_size, checksum,
+            network, command, payload_size, checksum,
         }
     }
 
-    pub fn from_stream<R: io::Read>(read: &mut R) -> Result<Self, MessageError> {
+    pub fn from_stream<R: io::Read>(network: Network, read: &mut R) -> Result<Self, MessageError> {
         let mut magic = [0; 4];
         let mut command = [0; 12];
         let mut checksum = [0; 4];
         read.read_exact(&mut magic)?;
-        if &magic[..] != MESSAGE_MAGIC {
+        if &magic != network.message_magic() {
             return Err(MessageError::WrongMagic)
         }
         read.read_exact(&mut command)?;
         let payload_size = read.read_u32::<LittleEndian>()?;
         read.read_exact(&mut checksum)?;
         Ok(MessageHeader {
+            network,
             command,
             payload_size,
             checksum,
@@ -41,13 +43,17 @@ impl MessageHeader {
     }
 
     pub fn write_to_stream<W: io::Write>(&self, write: &mut W) -> Result<(), MessageError> {
-        write.write(MESSAGE_MAGIC)?;
-        write.write(&self.command)?;
+        write.write_all(self.network.message_magic())?;
+        write.write_all(&self.command)?;
         write.write_u32::<LittleEndian>(self.payload_size)?;
-        write.write(&self.checksum)?;
+        write.write_all(&self.checksum)?;
         Ok(())
     }
 
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
     pub fn command(&self) -> &[u8; 12] {
         &self.command
     }
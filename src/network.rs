@@ -0,0 +1,154 @@
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Network {
+    Mainnet,
+    Testnet,
+    Regtest,
+}
+
+impl Network {
+    pub fn from_name(name: &str) -> Option<Network> {
+        match name {
+            "mainnet" => Some(Network::Mainnet),
+            "testnet" => Some(Network::Testnet),
+            "regtest" => Some(Network::Regtest),
+            _ => None,
+        }
+    }
+
+    /// Returns the network of a BCH or SLP CashAddr prefix.
+    pub fn from_prefix(prefix: &str) -> Option<Network> {
+        [Network::Mainnet, Network::Testnet, Network::Regtest].iter()
+            .cloned()
+            .find(|network| network.bch_prefix() == prefix || network.slp_prefix() == prefix)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Network::Mainnet => "mainnet",
+            Network::Testnet => "testnet",
+            Network::Regtest => "regtest",
+        }
+    }
+
+    pub fn bch_prefix(self) -> &'static str {
+        match self {
+            Network::Mainnet => "bitcoincash",
+            Network::Testnet => "bchtest",
+            Network::Regtest => "bchreg",
+        }
+    }
+
+    pub fn slp_prefix(self) -> &'static str {
+        match self {
+            Network::Mainnet => "simpleledger",
+            Network::Testnet => "slptest",
+            Network::Regtest => "slpreg",
+        }
+    }
+
+    /// Version byte of legacy P2PKH addresses. Testnet and regtest share the same versions.
+    pub fn legacy_p2pkh_version(self) -> u8 {
+        match self {
+            Network::Mainnet => 0x00,
+            Network::Testnet | Network::Regtest => 0x6f,
+        }
+    }
+
+    pub fn legacy_p2sh_version(self) -> u8 {
+        match self {
+            Network::Mainnet => 0x05,
+            Network::Testnet | Network::Regtest => 0xc4,
+        }
+    }
+
+    /// Version byte of WIF private keys.
+    pub fn wif_version(self) -> u8 {
+        match self {
+            Network::Mainnet => 0x80,
+            Network::Testnet | Network::Regtest => 0xef,
+        }
+    }
+
+    /// Magic bytes at the beginning of every P2P message.
+    pub fn message_magic(self) -> &'static [u8; 4] {
+        match self {
+            Network::Mainnet => b"\xe3\xe1\xf3\xe8",
+            Network::Testnet => b"\xf4\xe5\xf3\xf4",
+            Network::Regtest => b"\xda\xb5\xbf\xfa",
+        }
+    }
+
+    pub fn default_port(self) -> u16 {
+        match self {
+            Network::Mainnet => 8333,
+            Network::Testnet => 18333,
+            Network::Regtest => 18444,
+        }
+    }
+
+    /// Block the header chain starts at, as height and block hash. Headers before it aren't
+    /// validated; on mainnet and testnet it's the anchor block of the ASERT difficulty adjustment.
+    pub fn header_checkpoint(self) -> (u32, &'static str) {
+        match self {
+            Network::Mainnet => {
+                (661_647, "00000000000000000083ed4b7a780d59e3983513215518ad75654bb02deee62f")
+            },
+            Network::Testnet => {
+                (1_421_481, "00000000062c7f32591d883c99fc89ebe74a83287c0f2b7ffeef72e62217d40b")
+            },
+            Network::Regtest => {
+                (0, "0f9188f13cb7b2c71f2a335e3a4fc328bf5beb436012afca590b1a11466e2206")
+            },
+        }
+    }
+
+    /// Compact form of the easiest allowed target.
+    pub fn pow_limit_bits(self) -> u32 {
+        match self {
+            Network::Mainnet | Network::Testnet => 0x1d00ffff,
+            Network::Regtest => 0x207fffff,
+        }
+    }
+
+    /// Anchor of the ASERT difficulty adjustment (aserti3-2d) as its height, its bits and the
+    /// timestamp of its parent. None on regtest, where the difficulty never changes.
+    pub fn asert_anchor(self) -> Option<(u32, u32, i64)> {
+        match self {
+            Network::Mainnet => Some((661_647, 0x1804dafe, 1_605_447_844)),
+            Network::Testnet => Some((1_421_481, 0x1d00ffff, 1_605_445_400)),
+            Network::Regtest => None,
+        }
+    }
+
+    /// Whether blocks more than 20 minutes after their parent may use the easiest difficulty.
+    pub fn allows_min_difficulty_blocks(self) -> bool {
+        match self {
+            Network::Mainnet => false,
+            Network::Testnet | Network::Regtest => true,
+        }
+    }
+
+    /// Base URL of the rest.bitcoin.com API, ending with a slash.
+    pub fn rest_url(self) -> &'static str {
+        match self {
+            Network::Mainnet => "https://rest.bitcoin.com/v2/",
+            Network::Testnet => "https://trest.bitcoin.com/v2/",
+            Network::Regtest => "http://localhost:3000/v2/",
+        }
+    }
+
+    /// Base URL of the bitdb query API, ending with a slash.
+    pub fn bitdb_url(self) -> &'static str {
+        match self {
+            Network::Mainnet => "https://bitdb.bitcoin.com/q/",
+            Network::Testnet => "https://tbitdb.bitcoin.com/q/",
+            Network::Regtest => "http://localhost:3001/q/",
+        }
+    }
+}
+
+impl std::fmt::Display for Network {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
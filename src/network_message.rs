@@ -0,0 +1,469 @@
+use crate::block_header::BlockHeader;
+use crate::merkle_block::MerkleBlock;
+use crate::message::{Message, MAX_PAYLOAD_SIZE};
+use crate::message_error::MessageError;
+use crate::network::Network;
+use crate::serialize::{read_var_int, read_var_str, write_var_int, write_var_str};
+use crate::tx::Tx;
+use crate::version_message::{VersionMessage, VerackMessage};
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{self, Read, Write};
+
+
+/// Maximum number of entries of inv, getdata and notfound messages.
+pub const MAX_INV_SIZE: u64 = 50_000;
+/// Maximum number of headers in a headers message.
+pub const MAX_HEADERS_SIZE: u64 = 2_000;
+/// Maximum number of addresses in an addr message.
+pub const MAX_ADDR_SIZE: u64 = 1_000;
+/// Maximum number of block locator hashes in a getheaders message.
+pub const MAX_LOCATOR_SIZE: u64 = 101;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum InvType {
+    Error,
+    Tx,
+    Block,
+    FilteredBlock,
+    CompactBlock,
+    Unknown(u32),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Inventory {
+    pub inv_type: InvType,
+    /// Hash of the transaction or block, in byte order (not reversed like txids).
+    pub hash: [u8; 32],
+}
+
+#[derive(Clone, Debug)]
+pub struct NetAddress {
+    pub timestamp: u32,
+    pub services: u64,
+    /// IPv6 address, or IPv4-mapped IPv6 address.
+    pub addr: [u8; 16],
+    pub port: u16,
+}
+
+#[derive(Clone, Debug)]
+pub struct GetHeadersMessage {
+    pub version: u32,
+    /// Hashes of blocks we know, from the tip backwards.
+    pub locator_hashes: Vec<[u8; 32]>,
+    /// Last block to send headers up to, or all zeros for as many as possible.
+    pub hash_stop: [u8; 32],
+}
+
+#[derive(Clone, Debug)]
+pub struct RejectMessage {
+    /// Command of the rejected message, e.g. 'tx'.
+    pub message: Vec<u8>,
+    pub code: u8,
+    pub reason: Vec<u8>,
+    /// Usually the hash of the rejected transaction or block.
+    pub data: Vec<u8>,
+}
+
+/// The P2P messages understood by this crate, decoded from their payload.
+#[derive(Clone, Debug)]
+pub enum NetworkMessage {
+    Version(VersionMessage),
+    Verack,
+    Ping(u64),
+    Pong(u64),
+    Inv(Vec<Inventory>),
+    GetData(Vec<Inventory>),
+    NotFound(Vec<Inventory>),
+    Tx(Tx),
+    GetHeaders(GetHeadersMessage),
+    Headers(Vec<BlockHeader>),
+    MerkleBlock(MerkleBlock),
+    Addr(Vec<NetAddress>),
+    SendHeaders,
+    FeeFilter(u64),
+    Reject(RejectMessage),
+    Mempool,
+}
+
+impl InvType {
+    pub fn from_u32(inv_type: u32) -> Self {
+        match inv_type {
+            0 => InvType::Error,
+            1 => InvType::Tx,
+            2 => InvType::Block,
+            3 => InvType::FilteredBlock,
+            4 => InvType::CompactBlock,
+            inv_type => InvType::Unknown(inv_type),
+        }
+    }
+
+    pub fn to_u32(self) -> u32 {
+        match self {
+            InvType::Error => 0,
+            InvType::Tx => 1,
+            InvType::Block => 2,
+            InvType::FilteredBlock => 3,
+            InvType::CompactBlock => 4,
+            InvType::Unknown(inv_type) => inv_type,
+        }
+    }
+}
+
+fn read_count<R: Read>(read: &mut R, max_count: u64) -> io::Result<u64> {
+    let count = read_var_int(read)?;
+    if count > max_count {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "too many entries"));
+    }
+    Ok(count)
+}
+
+fn read_hash<R: Read>(read: &mut R) -> io::Result<[u8; 32]> {
+    let mut hash = [0; 32];
+    read.read_exact(&mut hash)?;
+    Ok(hash)
+}
+
+fn read_inventory<R: Read>(read: &mut R) -> io::Result<Vec<Inventory>> {
+    let count = read_count(read, MAX_INV_SIZE)?;
+    let mut inventory = Vec::new();
+    for _ in 0..count {
+        inventory.push(Inventory {
+            inv_type: InvType::from_u32(read.read_u32::<LittleEndian>()?),
+            hash: read_hash(read)?,
+        });
+    }
+    Ok(inventory)
+}
+
+fn write_inventory<W: Write>(write: &mut W, inventory: &[Inventory]) -> io::Result<()> {
+    write_var_int(write, inventory.len() as u64)?;
+    for entry in inventory {
+        write.write_u32::<LittleEndian>(entry.inv_type.to_u32())?;
+        write.write_all(&entry.hash)?;
+    }
+    Ok(())
+}
+
+impl NetworkMessage {
+    pub fn command(&self) -> &'static [u8] {
+        match self {
+            NetworkMessage::Version(_) => VersionMessage::command(),
+            NetworkMessage::Verack => b"verack",
+            NetworkMessage::Ping(_) => b"ping",
+            NetworkMessage::Pong(_) => b"pong",
+            NetworkMessage::Inv(_) => b"inv",
+            NetworkMessage::GetData(_) => b"getdata",
+            NetworkMessage::NotFound(_) => b"notfound",
+            NetworkMessage::Tx(_) => b"tx",
+            NetworkMessage::GetHeaders(_) => b"getheaders",
+            NetworkMessage::Headers(_) => b"headers",
+            NetworkMessage::MerkleBlock(_) => b"merkleblock",
+            NetworkMessage::Addr(_) => b"addr",
+            NetworkMessage::SendHeaders => b"sendheaders",
+            NetworkMessage::FeeFilter(_) => b"feefilter",
+            NetworkMessage::Reject(_) => b"reject",
+            NetworkMessage::Mempool => b"mempool",
+        }
+    }
+
+    pub fn to_message(&self, network: Network) -> Result<Message, MessageError> {
+        let mut payload = Vec::new();
+        match self {
+            NetworkMessage::Version(version) => return Ok(version.message(network)),
+            NetworkMessage::Verack => return Ok(VerackMessage.message(network)),
+            NetworkMessage::SendHeaders | NetworkMessage::Mempool => {},
+            NetworkMessage::Ping(nonce) | NetworkMessage::Pong(nonce) => {
+                payload.write_u64::<LittleEndian>(*nonce)?;
+            },
+            NetworkMessage::Inv(inventory) |
+            NetworkMessage::GetData(inventory) |
+            NetworkMessage::NotFound(inventory) => write_inventory(&mut payload, inventory)?,
+            NetworkMessage::Tx(tx) => tx.write_to_stream(&mut payload)?,
+            NetworkMessage::GetHeaders(get_headers) => {
+                payload.write_u32::<LittleEndian>(get_headers.version)?;
+                write_var_int(&mut payload, get_headers.locator_hashes.len() as u64)?;
+                for hash in get_headers.locator_hashes.iter() {
+                    payload.write_all(hash)?;
+                }
+                payload.write_all(&get_headers.hash_stop)?;
+            },
+            NetworkMessage::Headers(headers) => {
+                write_var_int(&mut payload, headers.len() as u64)?;
+                for header in headers {
+                    header.write_to_stream(&mut payload)?;
+                    write_var_int(&mut payload, 0)?;  // number of transactions
+                }
+            },
+            NetworkMessage::MerkleBlock(merkle_block) => {
+                merkle_block.write_to_stream(&mut payload)?;
+            },
+            NetworkMessage::Addr(addrs) => {
+                write_var_int(&mut payload, addrs.len() as u64)?;
+                for addr in addrs {
+                    payload.write_u32::<LittleEndian>(addr.timestamp)?;
+                    payload.write_u64::<LittleEndian>(addr.services)?;
+                    payload.write_all(&addr.addr)?;
+                    payload.write_u16::<BigEndian>(addr.port)?;
+                }
+            },
+            NetworkMessage::FeeFilter(fee_per_kb) => {
+                payload.write_u64::<LittleEndian>(*fee_per_kb)?;
+            },
+            NetworkMessage::Reject(reject) => {
+                write_var_str(&mut payload, &reject.message)?;
+                payload.write_u8(reject.code)?;
+                write_var_str(&mut payload, &reject.reason)?;
+                payload.write_all(&reject.data)?;
+            },
+        }
+        if payload.len() > MAX_PAYLOAD_SIZE as usize {
+            return Err(MessageError::PayloadTooLarge(payload.len() as u32));
+        }
+        Ok(Message::from_payload(network, self.command(), payload))
+    }
+
+    /// Decodes the message's payload based on its command. Unknown commands result in
+    /// MessageError::UnknownCommand, invalid payloads in MessageError::MalformedPayload.
+    pub fn from_message(message: &Message) -> Result<NetworkMessage, MessageError> {
+        let command = message.header().command_name();
+        let command_str = String::from_utf8_lossy(command).to_string();
+        if command == VersionMessage::command() {
+            return Ok(NetworkMessage::Version(VersionMessage::from_payload(message.payload())?));
+        }
+        let mut cur = io::Cursor::new(message.payload());
+        let network_message = match command {
+            b"verack" => Ok(NetworkMessage::Verack),
+            b"ping" => cur.read_u64::<LittleEndian>().map(NetworkMessage::Ping),
+            b"pong" => cur.read_u64::<LittleEndian>().map(NetworkMessage::Pong),
+            b"inv" => read_inventory(&mut cur).map(NetworkMessage::Inv),
+            b"getdata" => read_inventory(&mut cur).map(NetworkMessage::GetData),
+            b"notfound" => read_inventory(&mut cur).map(NetworkMessage::NotFound),
+            b"tx" => Tx::from_stream(&mut cur).map(NetworkMessage::Tx),
+            b"getheaders" => Self::read_get_headers(&mut cur).map(NetworkMessage::GetHeaders),
+            b"headers" => Self::read_headers(&mut cur).map(NetworkMessage::Headers),
+            b"merkleblock" => MerkleBlock::from_stream(&mut cur).map(NetworkMessage::MerkleBlock),
+            b"addr" => Self::read_addrs(&mut cur).map(NetworkMessage::Addr),
+            b"sendheaders" => Ok(NetworkMessage::SendHeaders),
+            b"feefilter" => cur.read_u64::<LittleEndian>().map(NetworkMessage::FeeFilter),
+            b"reject" => Self::read_reject(&mut cur).map(NetworkMessage::Reject),
+            b"mempool" => Ok(NetworkMessage::Mempool),
+            _ => return Err(MessageError::UnknownCommand(command_str)),
+        };
+        network_message.map_err(|_| MessageError::MalformedPayload(command_str))
+    }
+
+    fn read_get_headers(cur: &mut io::Cursor<&[u8]>) -> io::Result<GetHeadersMessage> {
+        let version = cur.read_u32::<LittleEndian>()?;
+        let count = read_count(cur, MAX_LOCATOR_SIZE)?;
+        let mut locator_hashes = Vec::new();
+        for _ in 0..count {
+            locator_hashes.push(read_hash(cur)?);
+        }
+        Ok(GetHeadersMessage { version, locator_hashes, hash_stop: read_hash(cur)? })
+    }
+
+    fn read_headers(cur: &mut io::Cursor<&[u8]>) -> io::Result<Vec<BlockHeader>> {
+        let count = read_count(cur, MAX_HEADERS_SIZE)?;
+        let mut headers = Vec::new();
+        for _ in 0..count {
+            headers.push(BlockHeader::from_stream(cur)?);
+            read_var_int(cur)?;  // number of transactions, always 0
+        }
+        Ok(headers)
+    }
+
+    fn read_addrs(cur: &mut io::Cursor<&[u8]>) -> io::Result<Vec<NetAddress>> {
+        let count = read_count(cur, MAX_ADDR_SIZE)?;
+        let mut addrs = Vec::new();
+        for _ in 0..count {
+            let timestamp = cur.read_u32::<LittleEndian>()?;
+            let services = cur.read_u64::<LittleEndian>()?;
+            let mut addr = [0; 16];
+            cur.read_exact(&mut addr)?;
+            let port = cur.read_u16::<BigEndian>()?;
+            addrs.push(NetAddress { timestamp, services, addr, port });
+        }
+        Ok(addrs)
+    }
+
+    fn read_reject(cur: &mut io::Cursor<&[u8]>) -> io::Result<RejectMessage> {
+        let message = read_var_str(cur)?;
+        let code = cur.read_u8()?;
+        let reason = read_var_str(cur)?;
+        let mut data = Vec::new();
+        cur.read_to_end(&mut data)?;
+        Ok(RejectMessage { message, code, reason, data })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script::Script;
+    use crate::tx::{TxInput, TxOutpoint, TxOutput};
+
+    /// gettxoutproof of the coinbase of the genesis block.
+    const GENESIS_PROOF: &str = "\
+        0100000000000000000000000000000000000000000000000000000000000000000000003ba3edfd7a7b12\
+        b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a29ab5f49ffff001d1dac2b7c01000000013b\
+        a3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a0101";
+
+    /// Sends the message through a stream and decodes it again.
+    fn round_trip(network_message: &NetworkMessage) -> NetworkMessage {
+        let mut bytes = Vec::new();
+        network_message.to_message(Network::Mainnet).unwrap().write_to_stream(&mut bytes).unwrap();
+        let message = Message::from_stream(Network::Mainnet, &mut io::Cursor::new(&bytes)).unwrap();
+        assert_eq!(message.header().command_name(), network_message.command());
+        let decoded = NetworkMessage::from_message(&message).unwrap();
+        // encoding the decoded message results in the same payload
+        assert_eq!(decoded.to_message(Network::Mainnet).unwrap().payload(), message.payload());
+        decoded
+    }
+
+    fn payload(network_message: &NetworkMessage) -> Vec<u8> {
+        network_message.to_message(Network::Mainnet).unwrap().payload().to_vec()
+    }
+
+    fn version_message() -> VersionMessage {
+        VersionMessage {
+            version: 70015,
+            services: 1,
+            timestamp: 1_600_000_000,
+            recv_services: 1,
+            recv_addr: [1; 16],
+            recv_port: 8333,
+            send_services: 0,
+            send_addr: [2; 16],
+            send_port: 18333,
+            nonce: 0x0123_4567_89ab_cdef,
+            user_agent: b"/slpagora:0.1/".to_vec(),
+            start_height: 600_000,
+            relay: false,
+        }
+    }
+
+    fn genesis_proof() -> MerkleBlock {
+        MerkleBlock::from_hex(GENESIS_PROOF).unwrap()
+    }
+
+    fn tx() -> Tx {
+        Tx::new(
+            2,
+            vec![TxInput::new(TxOutpoint { tx_hash: [0xab; 32], output_idx: 3 },
+                              Script::from_asm("abcd 1").unwrap(),
+                              0xffff_fffe)],
+            vec![TxOutput::new(1234, Script::from_asm("OP_RETURN 0102030405").unwrap())],
+            100,
+        )
+    }
+
+    fn reject() -> RejectMessage {
+        RejectMessage {
+            message: b"tx".to_vec(),
+            code: 0x10,
+            reason: b"bad-txns-inputs-missingorspent".to_vec(),
+            data: vec![0xcd; 32],
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        match round_trip(&NetworkMessage::Version(version_message())) {
+            NetworkMessage::Version(version) => {
+                assert_eq!(version.version, 70015);
+                assert_eq!(version.recv_addr, [1; 16]);
+                assert_eq!(version.send_port, 18333);
+                assert_eq!(version.nonce, 0x0123_4567_89ab_cdef);
+                assert_eq!(version.user_agent, b"/slpagora:0.1/");
+                assert_eq!(version.start_height, 600_000);
+                assert!(!version.relay);
+            },
+            other => panic!("{:?}", other),
+        }
+        assert!(matches!(round_trip(&NetworkMessage::Verack), NetworkMessage::Verack));
+        assert!(matches!(round_trip(&NetworkMessage::Ping(7)), NetworkMessage::Ping(7)));
+        assert!(matches!(round_trip(&NetworkMessage::Pong(u64::MAX)),
+                         NetworkMessage::Pong(u64::MAX)));
+        let inventory = vec![
+            Inventory { inv_type: InvType::Tx, hash: [1; 32] },
+            Inventory { inv_type: InvType::FilteredBlock, hash: [2; 32] },
+            Inventory { inv_type: InvType::Unknown(0x4000_0001), hash: [3; 32] },
+        ];
+        match round_trip(&NetworkMessage::Inv(inventory.clone())) {
+            NetworkMessage::Inv(decoded) => assert_eq!(decoded, inventory),
+            other => panic!("{:?}", other),
+        }
+        let headers = vec![genesis_proof().header, genesis_proof().header];
+        match round_trip(&NetworkMessage::Headers(headers.clone())) {
+            NetworkMessage::Headers(decoded) => assert_eq!(decoded, headers),
+            other => panic!("{:?}", other),
+        }
+        match round_trip(&NetworkMessage::MerkleBlock(genesis_proof())) {
+            NetworkMessage::MerkleBlock(merkle_block) => {
+                assert_eq!(merkle_block.header, genesis_proof().header);
+                assert_eq!(merkle_block.total_txs, 1);
+                assert_eq!(merkle_block.hashes, genesis_proof().hashes);
+                assert_eq!(merkle_block.flags, vec![1]);
+            },
+            other => panic!("{:?}", other),
+        }
+        match round_trip(&NetworkMessage::Tx(tx())) {
+            NetworkMessage::Tx(decoded) => assert_eq!(decoded.txid(), tx().txid()),
+            other => panic!("{:?}", other),
+        }
+        match round_trip(&NetworkMessage::Reject(reject())) {
+            NetworkMessage::Reject(decoded) => {
+                assert_eq!(decoded.message, b"tx");
+                assert_eq!(decoded.code, 0x10);
+                assert_eq!(decoded.reason, reject().reason);
+                assert_eq!(decoded.data, vec![0xcd; 32]);
+            },
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn truncated_payloads() {
+        let cases = [
+            NetworkMessage::Version(version_message()),
+            NetworkMessage::Ping(7),
+            NetworkMessage::Pong(7),
+            NetworkMessage::Inv(vec![Inventory { inv_type: InvType::Tx, hash: [1; 32] }]),
+            NetworkMessage::Headers(vec![genesis_proof().header]),
+            NetworkMessage::MerkleBlock(genesis_proof()),
+            NetworkMessage::Tx(tx()),
+            NetworkMessage::Reject(reject()),
+        ];
+        for network_message in cases.iter() {
+            let payload = payload(network_message);
+            // cut off in the middle, as e.g. the relay flag of version and the data of reject
+            // are optional at the end
+            let truncated = Message::from_payload(Network::Mainnet,
+                                                  network_message.command(),
+                                                  payload[..payload.len() / 2].to_vec());
+            match NetworkMessage::from_message(&truncated) {
+                Err(MessageError::MalformedPayload(command)) =>
+                    assert_eq!(command.as_bytes(), network_message.command()),
+                result => panic!("unexpected result {:?}", result),
+            }
+        }
+    }
+
+    #[test]
+    fn too_many_entries() {
+        let mut payload = Vec::new();
+        write_var_int(&mut payload, MAX_INV_SIZE + 1).unwrap();
+        let message = Message::from_payload(Network::Mainnet, b"inv", payload);
+        assert!(matches!(NetworkMessage::from_message(&message),
+                         Err(MessageError::MalformedPayload(_))));
+    }
+
+    #[test]
+    fn unknown_command() {
+        let message = Message::from_payload(Network::Mainnet, b"alert", vec![1, 2, 3]);
+        match NetworkMessage::from_message(&message) {
+            Err(MessageError::UnknownCommand(command)) => assert_eq!(command, "alert"),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+}
@@ -12,6 +12,13 @@ pub struct P2PKHOutput {
     pub address: Address,
 }
 
+/// P2PKH output of an uncompressed public key, e.g. from an imported uncompressed WIF key.
+#[derive(Clone, Debug)]
+pub struct P2PKHUncompressedOutput {
+    pub value: u64,
+    pub address: Address,
+}
+
 //#[derive(Clone, Debug)]
 pub struct P2SHOutput<O: Output> {
     pub output: O,
@@ -85,6 +92,31 @@ impl Output for P2PKHOutput {
     }
 }
 
+impl Output for P2PKHUncompressedOutput {
+    fn value(&self) -> u64 {
+        self.value
+    }
+
+    fn script(&self) -> Script {
+        P2PKHOutput { value: self.value, address: self.address.clone() }.script()
+    }
+
+    fn script_code(&self) -> Script {
+        self.script()
+    }
+
+    fn sig_script(&self,
+                  serialized_sig: Vec<u8>,
+                  pub_key: &secp256k1::PublicKey,
+                  _pre_image: &PreImage,
+                  _outputs: &[TxOutput]) -> Script {
+        Script::new(vec![
+            Op::Push(serialized_sig),
+            Op::Push(pub_key.serialize_uncompressed().to_vec()),
+        ])
+    }
+}
+
 impl<O: Output> Output for P2SHOutput<O> {
     fn value(&self) -> u64 {
         self.output.value()
@@ -369,3 +401,90 @@ impl TradeOfferOutput {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::AddressType;
+    use crate::incomplete_tx::{IncompleteTx, Utxo};
+    use crate::script_interpreter::ScriptError;
+    use crate::tx::{Tx, TxOutpoint};
+    use secp256k1::{Secp256k1, PublicKey, SecretKey};
+
+    const TOKEN_ID: [u8; 32] = [0x12; 32];
+    const SELL_AMOUNT: u64 = 1000;
+    const BUY_AMOUNT: u64 = 50_000;
+
+    fn cancel_key() -> SecretKey {
+        SecretKey::from_slice(&[7; 32]).unwrap()
+    }
+
+    fn accept_key() -> SecretKey {
+        SecretKey::from_slice(b"TruthIsTreasonInTheEmpireOfLies.").unwrap()
+    }
+
+    fn receiving_address() -> Address {
+        Address::from_bytes(AddressType::P2PKH, [9; 20])
+    }
+
+    fn contract(is_cancel: bool) -> EnforceOutputsOutput {
+        let cancel_pub_key = PublicKey::from_secret_key(&Secp256k1::new(), &cancel_key());
+        EnforceOutputsOutput {
+            value: 546,
+            enforced_outputs: vec![
+                Box::new(SLPSendOutput {
+                    token_type: 1,
+                    token_id: TOKEN_ID,
+                    output_quantities: vec![0, SELL_AMOUNT],
+                }),
+                Box::new(P2PKHOutput { value: BUY_AMOUNT, address: receiving_address() }),
+            ],
+            cancel_address: Address::from_pub_key("bitcoincash", &cancel_pub_key),
+            is_cancel: Some(is_cancel),
+        }
+    }
+
+    /// A tx spending the contract with `key`, sending `buy_amount` to the receiving address.
+    fn spend(is_cancel: bool, key: SecretKey, buy_amount: u64) -> (IncompleteTx, Tx) {
+        let mut tx_build = IncompleteTx::new_simple();
+        tx_build.add_utxo(Utxo {
+            outpoint: TxOutpoint { tx_hash: [0x34; 32], output_idx: 1 },
+            sequence: 0xffff_ffff,
+            output: Box::new(P2SHOutput { output: contract(is_cancel) }),
+            key,
+        });
+        tx_build.add_output(&SLPSendOutput {
+            token_type: 1,
+            token_id: TOKEN_ID,
+            output_quantities: vec![0, SELL_AMOUNT],
+        });
+        tx_build.add_output(&P2PKHOutput { value: buy_amount, address: receiving_address() });
+        tx_build.add_output(&P2PKHOutput {
+            value: 546,
+            address: Address::from_bytes(AddressType::P2PKH, [5; 20]),
+        });
+        let tx = tx_build.sign();
+        (tx_build, tx)
+    }
+
+    #[test]
+    fn enforce_outputs_accept() {
+        let (tx_build, tx) = spend(false, accept_key(), BUY_AMOUNT);
+        tx_build.verify(&tx).unwrap();
+        // the tx must pay the enforced outputs, even though the signature itself is valid
+        let (tx_build, tx) = spend(false, accept_key(), BUY_AMOUNT - 1);
+        assert!(matches!(tx_build.verify(&tx), Err(ScriptError::NullFail)));
+    }
+
+    #[test]
+    fn enforce_outputs_cancel() {
+        let (tx_build, tx) = spend(true, cancel_key(), BUY_AMOUNT);
+        tx_build.verify(&tx).unwrap();
+        // the outputs are free to choose when cancelling
+        let (tx_build, tx) = spend(true, cancel_key(), 1);
+        tx_build.verify(&tx).unwrap();
+        let (tx_build, tx) = spend(true, accept_key(), BUY_AMOUNT);
+        assert!(matches!(tx_build.verify(&tx),
+                         Err(ScriptError::VerifyFailed(OpCodeType::OpEqualVerify))));
+    }
+}
@@ -0,0 +1,336 @@
+use crate::address::Address;
+use crate::backend::{address_script, Backend, BackendError, TokenEntry, UtxoEntry};
+use crate::merkle_block::TxProof;
+use crate::message::Message;
+use crate::message_error::MessageError;
+use crate::network::Network;
+use crate::network_message::{InvType, NetworkMessage};
+use crate::outputs::TradeOfferOutput;
+use crate::script::Script;
+use crate::tx::Tx;
+use crate::version_message::VersionMessage;
+use std::cell::RefCell;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+
+pub const PROTOCOL_VERSION: i32 = 70015;
+const USER_AGENT: &[u8] = b"/slpagora:0.1.0/";
+/// Service bit of peers serving the full block chain.
+const NODE_NETWORK: u64 = 1;
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A connection to a node of the BCH network, after the version/verack handshake.
+pub struct Peer {
+    network: Network,
+    stream: BufReader<TcpStream>,
+    /// None until the handshake completed.
+    peer_version: Option<VersionMessage>,
+}
+
+fn ip_to_bytes(ip: IpAddr) -> [u8; 16] {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped().octets(),
+        IpAddr::V6(ip) => ip.octets(),
+    }
+}
+
+fn random_nonce() -> u64 {
+    use rand::RngCore;
+    rand::rngs::OsRng::new().unwrap().next_u64()
+}
+
+impl Peer {
+    /// Connects to the peer and performs the handshake. With `relay`, the peer announces new
+    /// transactions to us.
+    pub fn connect<A: ToSocketAddrs>(addr: A, network: Network, relay: bool)
+            -> Result<Peer, MessageError> {
+        let addr = addr.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "peer address didn't resolve")
+        })?;
+        let stream = TcpStream::connect_timeout(&addr, TIMEOUT)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        let mut peer = Peer {
+            network,
+            stream: BufReader::new(stream),
+            peer_version: None,
+        };
+        peer.send(&NetworkMessage::Version(Self::our_version(addr, relay)))?;
+        let mut got_verack = false;
+        while peer.peer_version.is_none() || !got_verack {
+            match peer.receive()? {
+                NetworkMessage::Version(version) => {
+                    peer.peer_version = Some(version);
+                    peer.send(&NetworkMessage::Verack)?;
+                },
+                NetworkMessage::Verack => got_verack = true,
+                _ => {},
+            }
+        }
+        peer.set_read_timeout(None)?;
+        Ok(peer)
+    }
+
+    /// Connects to a host name or IP, optionally with a port. Without a port, the network's
+    /// default port is used.
+    pub fn connect_host(host: &str, network: Network, relay: bool) -> Result<Peer, MessageError> {
+        match host.parse::<SocketAddr>() {
+            Ok(addr) => Peer::connect(addr, network, relay),
+            Err(_) if host.contains(':') => Peer::connect(host, network, relay),
+            Err(_) => Peer::connect((host, network.default_port()), network, relay),
+        }
+    }
+
+    fn our_version(addr: SocketAddr, relay: bool) -> VersionMessage {
+        VersionMessage {
+            version: PROTOCOL_VERSION,
+            services: 0,
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64,
+            recv_services: NODE_NETWORK,
+            recv_addr: ip_to_bytes(addr.ip()),
+            recv_port: addr.port(),
+            send_services: 0,
+            send_addr: [0; 16],
+            send_port: 0,
+            nonce: random_nonce(),
+            user_agent: USER_AGENT.to_vec(),
+            start_height: 0,
+            relay,
+        }
+    }
+
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    /// The version message the peer sent during the handshake.
+    pub fn peer_version(&self) -> &VersionMessage {
+        self.peer_version.as_ref().expect("handshake completed")
+    }
+
+    /// Sets how long `receive` waits for a message, None to wait forever.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), MessageError> {
+        self.stream.get_ref().set_read_timeout(timeout)?;
+        Ok(())
+    }
+
+    pub fn send(&mut self, message: &NetworkMessage) -> Result<(), MessageError> {
+        let mut message_ser = Vec::new();
+        message.to_message(self.network)?.write_to_stream(&mut message_ser)?;
+        self.stream.get_mut().write_all(&message_ser)?;
+        Ok(())
+    }
+
+    /// Returns the next message from the peer. Pings are answered with pongs and, like messages
+    /// with unknown commands, not returned.
+    pub fn receive(&mut self) -> Result<NetworkMessage, MessageError> {
+        loop {
+            let message = Message::from_stream(self.network, &mut self.stream)?;
+            if let Some(network_message) = self.handle_message(&message)? {
+                return Ok(network_message);
+            }
+        }
+    }
+
+    /// Like `receive`, but returns None if no message arrives within `timeout`. Messages are
+    /// only read once they started arriving, so the timeout never splits one.
+    pub fn receive_timeout(&mut self, timeout: Duration)
+            -> Result<Option<NetworkMessage>, MessageError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let now = Instant::now();
+            if now >= deadline || !self.wait_readable(deadline - now)? {
+                return Ok(None);
+            }
+            self.set_read_timeout(Some(TIMEOUT))?;
+            let message = Message::from_stream(self.network, &mut self.stream);
+            self.set_read_timeout(None)?;
+            if let Some(network_message) = self.handle_message(&message?)? {
+                return Ok(Some(network_message));
+            }
+        }
+    }
+
+    /// Decodes the message, answering pings and skipping unknown commands.
+    fn handle_message(&mut self, message: &Message)
+            -> Result<Option<NetworkMessage>, MessageError> {
+        match NetworkMessage::from_message(message) {
+            Ok(NetworkMessage::Ping(nonce)) => {
+                self.send(&NetworkMessage::Pong(nonce))?;
+                Ok(None)
+            },
+            Ok(network_message) => Ok(Some(network_message)),
+            Err(MessageError::UnknownCommand(_)) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Waits until data arrives, at most for `timeout`. Returns false if none arrived.
+    fn wait_readable(&mut self, timeout: Duration) -> Result<bool, MessageError> {
+        self.set_read_timeout(Some(timeout))?;
+        let result = match self.stream.fill_buf() {
+            Ok([]) => Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+            Ok(_) => Ok(true),
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock ||
+                            err.kind() == io::ErrorKind::TimedOut => Ok(false),
+            Err(err) => Err(err.into()),
+        };
+        self.set_read_timeout(None)?;
+        result
+    }
+
+    /// Waits for a transaction paying to `script` or a new block announced by the peer, at most
+    /// for `timeout`. Returns false if neither arrived. Needs a connection with `relay`.
+    pub fn watch_script(&mut self, script: &Script, timeout: Duration)
+            -> Result<bool, MessageError> {
+        let script_ser = script.to_vec();
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.receive_timeout(remaining)? {
+                None => return Ok(false),
+                Some(NetworkMessage::Inv(inventory)) => {
+                    if inventory.iter().any(|entry| entry.inv_type == InvType::Block) {
+                        return Ok(true);
+                    }
+                    let tx_inventory = inventory.into_iter()
+                        .filter(|entry| entry.inv_type == InvType::Tx)
+                        .collect::<Vec<_>>();
+                    if !tx_inventory.is_empty() {
+                        self.send(&NetworkMessage::GetData(tx_inventory))?;
+                    }
+                },
+                Some(NetworkMessage::Tx(tx)) => {
+                    if tx.outputs().iter().any(|output| output.script.to_vec() == script_ser) {
+                        return Ok(true);
+                    }
+                },
+                Some(_) => {},
+            }
+        }
+    }
+
+    /// Sends the transaction to the peer. A ping is sent after it and as the peer processes
+    /// messages in order, its pong confirms that the transaction arrived. Rejections announced by
+    /// the peer before that are returned as MessageError::Rejected.
+    ///
+    /// Success only means the transaction was sent, not that it was accepted: peers may drop
+    /// invalid transactions silently, as newer nodes don't send reject messages anymore.
+    pub fn broadcast_tx(&mut self, tx: &Tx) -> Result<(), MessageError> {
+        self.send(&NetworkMessage::Tx(tx.clone()))?;
+        let tx_hash = tx.hash();
+        let nonce = random_nonce();
+        self.send(&NetworkMessage::Ping(nonce))?;
+        self.set_read_timeout(Some(TIMEOUT))?;
+        let result = loop {
+            match self.receive() {
+                Ok(NetworkMessage::Pong(pong_nonce)) if pong_nonce == nonce => break Ok(()),
+                // rejects of other transactions, e.g. relayed ones, don't concern us
+                Ok(NetworkMessage::Reject(reject))
+                        if reject.message == b"tx" && reject.data == tx_hash => {
+                    break Err(MessageError::Rejected(
+                        String::from_utf8_lossy(&reject.reason).to_string(),
+                    ));
+                },
+                Ok(_) => {},
+                Err(err) => break Err(err),
+            }
+        };
+        self.set_read_timeout(None)?;
+        result
+    }
+}
+
+/// Backend broadcasting transactions directly to a peer of the BCH network and watching it for
+/// new transactions, and using `inner` for everything else.
+pub struct PeerBroadcastBackend {
+    network: Network,
+    peer_addr: String,
+    inner: Box<dyn Backend>,
+    /// Connection with transaction relay, opened when an address is watched the first time.
+    relay_peer: RefCell<Option<Peer>>,
+}
+
+impl PeerBroadcastBackend {
+    /// `peer_addr` is passed to Peer::connect_host.
+    pub fn new(network: Network, peer_addr: String, inner: Box<dyn Backend>) -> Self {
+        PeerBroadcastBackend {
+            network,
+            peer_addr,
+            inner,
+            relay_peer: RefCell::new(None),
+        }
+    }
+}
+
+impl Backend for PeerBroadcastBackend {
+    fn get_utxos(&self, addresses: &[Address]) -> Result<Vec<Vec<UtxoEntry>>, BackendError> {
+        self.inner.get_utxos(addresses)
+    }
+
+    fn are_addresses_used(&self, addresses: &[Address]) -> Result<Vec<bool>, BackendError> {
+        self.inner.are_addresses_used(addresses)
+    }
+
+    /// Returns the txid once the peer received the transaction, which doesn't guarantee it
+    /// accepted it, see `Peer::broadcast_tx`.
+    fn broadcast(&self, tx: &Tx) -> Result<String, BackendError> {
+        match Peer::connect_host(&self.peer_addr, self.network, false)
+            .and_then(|mut peer| peer.broadcast_tx(tx)) {
+            Ok(()) => Ok(tx.txid()),
+            Err(MessageError::Rejected(reason)) => Err(BackendError::BroadcastRejected(reason)),
+            Err(err) => Err(BackendError::PeerError(err)),
+        }
+    }
+
+    fn get_txs(&self, tx_ids: &[String]) -> Result<Vec<Tx>, BackendError> {
+        self.inner.get_txs(tx_ids)
+    }
+
+    fn is_output_unspent(&self, tx_id: &str, output_idx: u32) -> Result<bool, BackendError> {
+        self.inner.is_output_unspent(tx_id, output_idx)
+    }
+
+    fn get_tx_proof(&self, tx_id: &str) -> Result<Option<TxProof>, BackendError> {
+        self.inner.get_tx_proof(tx_id)
+    }
+
+    fn validate_slp_txids(&self, tx_ids: &[String]) -> Result<Vec<String>, BackendError> {
+        self.inner.validate_slp_txids(tx_ids)
+    }
+
+    fn token_info(&self, token_ids: &[String]) -> Result<Vec<TokenEntry>, BackendError> {
+        self.inner.token_info(token_ids)
+    }
+
+    fn list_tokens(&self) -> Result<Vec<TokenEntry>, BackendError> {
+        self.inner.list_tokens()
+    }
+
+    fn find_trade_offers(&self) -> Result<Vec<TradeOfferOutput>, BackendError> {
+        self.inner.find_trade_offers()
+    }
+
+    fn wait_for_address_change(&self, address: &Address, timeout: Duration)
+            -> Result<bool, BackendError> {
+        let mut relay_peer = self.relay_peer.borrow_mut();
+        let peer = match relay_peer.as_mut() {
+            Some(peer) => peer,
+            None => {
+                // transactions before connecting aren't announced, so the caller has to check
+                *relay_peer = Some(Peer::connect_host(&self.peer_addr, self.network, true)
+                    .map_err(BackendError::PeerError)?);
+                return Ok(true);
+            },
+        };
+        match peer.watch_script(&address_script(address), timeout) {
+            Ok(changed) => Ok(changed),
+            Err(err) => {
+                // reconnect the next time
+                *relay_peer = None;
+                Err(BackendError::PeerError(err))
+            },
+        }
+    }
+}
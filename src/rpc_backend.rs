@@ -0,0 +1,575 @@
+use crate::address::Address;
+use crate::backend::{
+    address_script, Backend, BackendError, TokenEntry, UtxoEntry, UtxoVerification,
+    TX_NOT_IN_BLOCK_ERROR,
+};
+use crate::merkle_block::{MerkleBlock, TxProof};
+use crate::network::Network;
+use crate::outputs::TradeOfferOutput;
+use crate::tx::Tx;
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+
+/// bitcoind's error code for unknown transactions and addresses.
+const RPC_INVALID_ADDRESS_OR_KEY: i64 = -5;
+
+/// Label of the watch-only addresses imported into the node's wallet.
+const WATCH_ONLY_LABEL: &str = "slpagora";
+
+pub enum RpcAuth {
+    None,
+    UserPass(String, String),
+    /// Path to the node's .cookie file, which is re-read on each call as it changes with every
+    /// restart of the node.
+    Cookie(PathBuf),
+}
+
+/// How the UTXOs of the wallet's addresses are looked up.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum UtxoLookup {
+    /// Imports the addresses into the node's wallet as watch-only (without rescanning) and uses
+    /// listunspent. Sees unconfirmed transactions.
+    WatchOnly,
+    /// Uses scantxoutset, which needs no wallet on the node, but only sees confirmed UTXOs and
+    /// can't tell whether an address was used in the past.
+    ScanTxOutSet,
+}
+
+#[derive(Deserialize, Debug)]
+struct RpcErrorEntry {
+    code: i64,
+    message: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct RpcResponse {
+    result: serde_json::Value,
+    error: Option<RpcErrorEntry>,
+}
+
+#[derive(Serialize, Debug)]
+struct RpcRequest<'a> {
+    jsonrpc: &'a str,
+    id: &'a str,
+    method: &'a str,
+    params: serde_json::Value,
+}
+
+#[derive(Deserialize, Debug)]
+struct RpcUtxo {
+    txid: String,
+    vout: u32,
+    #[serde(rename = "scriptPubKey")]
+    script_pub_key: String,
+    amount: f64,
+}
+
+#[derive(Deserialize, Debug)]
+struct ScanResult {
+    unspents: Vec<RpcUtxo>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ReceivedByAddress {
+    address: String,
+    txids: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ImportResult {
+    success: bool,
+    error: Option<RpcErrorEntry>,
+}
+
+/// Backend using a Bitcoin Cash node's JSON-RPC interface, e.g. of Bitcoin Cash Node.
+///
+/// Nodes don't index SLP tokens, so token details, SLP validation and trade offers are looked up
+/// by `slp_indexer`.
+pub struct RpcBackend {
+    network: Network,
+    url: String,
+    auth: RpcAuth,
+    utxo_lookup: UtxoLookup,
+    slp_indexer: Box<dyn Backend>,
+    client: reqwest::Client,
+    imported_addresses: RefCell<HashSet<String>>,
+}
+
+fn to_satoshis(amount: f64) -> u64 {
+    (amount * 100_000_000.0).round() as u64
+}
+
+impl RpcBackend {
+    pub fn new(network: Network,
+               url: String,
+               auth: RpcAuth,
+               utxo_lookup: UtxoLookup,
+               slp_indexer: Box<dyn Backend>) -> Self {
+        RpcBackend {
+            network,
+            url,
+            auth,
+            utxo_lookup,
+            slp_indexer,
+            client: reqwest::Client::new(),
+            imported_addresses: RefCell::new(HashSet::new()),
+        }
+    }
+
+    fn call<R: DeserializeOwned>(&self, method: &str, params: serde_json::Value)
+            -> Result<R, BackendError> {
+        let mut request = self.client.post(&self.url).json(&RpcRequest {
+            jsonrpc: "1.0",
+            id: "slpagora",
+            method,
+            params,
+        });
+        match &self.auth {
+            RpcAuth::None => {},
+            RpcAuth::UserPass(user, password) => {
+                request = request.basic_auth(user, Some(password));
+            },
+            RpcAuth::Cookie(path) => {
+                let cookie = std::fs::read_to_string(path).map_err(BackendError::CookieError)?;
+                let mut parts = cookie.trim().splitn(2, ':');
+                let user = parts.next().unwrap_or("");
+                request = request.basic_auth(user, parts.next());
+            },
+        }
+        let mut response = request.send()?;
+        if response.status() == reqwest::StatusCode::UNAUTHORIZED {
+            return Err(BackendError::InvalidResponse("RPC authentication failed".to_string()));
+        }
+        // bitcoind responds to failed calls with an error status, but still with a JSON body
+        let response: RpcResponse = response.json()?;
+        if let Some(error) = response.error {
+            return Err(BackendError::RpcError(error.code, error.message));
+        }
+        serde_json::from_value(response.result)
+            .map_err(|err| BackendError::InvalidResponse(err.to_string()))
+    }
+
+    /// Imports the addresses the node's wallet doesn't watch yet. No rescan is done, so
+    /// transactions from before the import only show up after a manual rescan of the node.
+    fn import_addresses(&self, addresses: &[Address]) -> Result<(), BackendError> {
+        let new_addresses = addresses.iter()
+            .map(Address::cash_addr)
+            .filter(|cash_addr| !self.imported_addresses.borrow().contains(*cash_addr))
+            .collect::<Vec<_>>();
+        if new_addresses.is_empty() {
+            return Ok(());
+        }
+        let requests = new_addresses.iter().map(|cash_addr| json!({
+            "scriptPubKey": {"address": cash_addr},
+            "timestamp": "now",
+            "watchonly": true,
+            "label": WATCH_ONLY_LABEL,
+        })).collect::<Vec<_>>();
+        let results: Vec<ImportResult> =
+            self.call("importmulti", json!([requests, {"rescan": false}]))?;
+        if let Some(error) = results.into_iter().filter(|result| !result.success)
+                                                .filter_map(|result| result.error).next() {
+            return Err(BackendError::RpcError(error.code, error.message));
+        }
+        self.imported_addresses.borrow_mut()
+            .extend(new_addresses.into_iter().map(str::to_string));
+        Ok(())
+    }
+
+    fn find_utxos(&self, addresses: &[Address]) -> Result<Vec<RpcUtxo>, BackendError> {
+        match self.utxo_lookup {
+            UtxoLookup::WatchOnly => {
+                self.import_addresses(addresses)?;
+                let cash_addrs = addresses.iter().map(Address::cash_addr).collect::<Vec<_>>();
+                self.call("listunspent", json!([0, 9_999_999, cash_addrs, true]))
+            },
+            UtxoLookup::ScanTxOutSet => {
+                let descriptors = addresses.iter()
+                    .map(|address| format!("addr({})", address.cash_addr()))
+                    .collect::<Vec<_>>();
+                let result: ScanResult = self.call("scantxoutset", json!(["start", descriptors]))?;
+                Ok(result.unspents)
+            },
+        }
+    }
+}
+
+impl Backend for RpcBackend {
+    fn get_utxos(&self, addresses: &[Address]) -> Result<Vec<Vec<UtxoEntry>>, BackendError> {
+        let mut utxos_by_address = vec![Vec::new(); addresses.len()];
+        let scripts = addresses.iter()
+            .map(|address| hex::encode(address_script(address).to_vec()))
+            .collect::<Vec<_>>();
+        for utxo in self.find_utxos(addresses)? {
+            if let Some(idx) = scripts.iter().position(|script| script == &utxo.script_pub_key) {
+                utxos_by_address[idx].push(UtxoEntry {
+                    txid: utxo.txid,
+                    vout: utxo.vout,
+                    amount: utxo.amount,
+                    satoshis: to_satoshis(utxo.amount),
+                    confirmation_height: None,
+                    verification: UtxoVerification::Unverified,
+                });
+            }
+        }
+        Ok(utxos_by_address)
+    }
+
+    fn are_addresses_used(&self, addresses: &[Address]) -> Result<Vec<bool>, BackendError> {
+        match self.utxo_lookup {
+            UtxoLookup::WatchOnly => {
+                self.import_addresses(addresses)?;
+                let received: Vec<ReceivedByAddress> =
+                    self.call("listreceivedbyaddress", json!([0, false, true]))?;
+                let used_addresses = received.into_iter()
+                    .filter(|entry| !entry.txids.is_empty())
+                    .filter_map(|entry| {
+                        Address::from_cash_addr_network(&entry.address, self.network).ok()
+                    })
+                    .map(|address| address_script(&address).to_vec())
+                    .collect::<HashSet<_>>();
+                Ok(addresses.iter()
+                    .map(|address| used_addresses.contains(&address_script(address).to_vec()))
+                    .collect())
+            },
+            UtxoLookup::ScanTxOutSet => {
+                Ok(self.get_utxos(addresses)?.iter().map(|utxos| !utxos.is_empty()).collect())
+            },
+        }
+    }
+
+    fn broadcast(&self, tx: &Tx) -> Result<String, BackendError> {
+        let mut tx_ser = Vec::new();
+        tx.write_to_stream(&mut tx_ser).unwrap();
+        match self.call("sendrawtransaction", json!([hex::encode(&tx_ser)])) {
+            Ok(tx_id) => Ok(tx_id),
+            Err(BackendError::RpcError(_, message)) => {
+                Err(BackendError::BroadcastRejected(message))
+            },
+            Err(err) => Err(err),
+        }
+    }
+
+    fn get_txs(&self, tx_ids: &[String]) -> Result<Vec<Tx>, BackendError> {
+        let mut txs = Vec::new();
+        for tx_id in tx_ids {
+            let tx_hex: String = match self.call("getrawtransaction", json!([tx_id, false])) {
+                Ok(tx_hex) => tx_hex,
+                Err(BackendError::RpcError(RPC_INVALID_ADDRESS_OR_KEY, _)) => continue,
+                Err(err) => return Err(err),
+            };
+            if let Ok(tx) = Tx::from_hex(&tx_hex) {
+                txs.push(tx);
+            }
+        }
+        Ok(txs)
+    }
+
+    fn is_output_unspent(&self, tx_id: &str, output_idx: u32) -> Result<bool, BackendError> {
+        let tx_out: Option<serde_json::Value> =
+            self.call("gettxout", json!([tx_id, output_idx, true]))?;
+        Ok(tx_out.is_some())
+    }
+
+    fn get_tx_proof(&self, tx_id: &str) -> Result<Option<TxProof>, BackendError> {
+        // without -txindex, the node only finds transactions with unspent outputs
+        let proof_hex: String = match self.call("gettxoutproof", json!([[tx_id]])) {
+            Ok(proof_hex) => proof_hex,
+            // the node uses the same code for other errors, e.g. an unknown block
+            Err(BackendError::RpcError(RPC_INVALID_ADDRESS_OR_KEY, ref message))
+                if message.contains(TX_NOT_IN_BLOCK_ERROR) => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let merkle_block = MerkleBlock::from_hex(&proof_hex)
+            .map_err(|err| BackendError::InvalidResponse(err.to_string()))?;
+        Ok(Some(TxProof::MerkleBlock(merkle_block)))
+    }
+
+    fn validate_slp_txids(&self, tx_ids: &[String]) -> Result<Vec<String>, BackendError> {
+        self.slp_indexer.validate_slp_txids(tx_ids)
+    }
+
+    fn token_info(&self, token_ids: &[String]) -> Result<Vec<TokenEntry>, BackendError> {
+        self.slp_indexer.token_info(token_ids)
+    }
+
+    fn list_tokens(&self) -> Result<Vec<TokenEntry>, BackendError> {
+        self.slp_indexer.list_tokens()
+    }
+
+    fn find_trade_offers(&self) -> Result<Vec<TradeOfferOutput>, BackendError> {
+        self.slp_indexer.find_trade_offers()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::AddressType;
+    use crate::backend::RestBackend;
+    use serde_json::Value;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::{Arc, Mutex};
+
+    /// A request received by the mock node: its Authorization header and JSON-RPC body.
+    type ReceivedRequest = (Option<String>, Value);
+
+    /// Minimal HTTP server answering JSON-RPC calls with `handler(method, params)`, which
+    /// returns the result or an error code and message. Requests without the expected
+    /// Authorization header are refused. It records all requests.
+    struct MockNode {
+        url: String,
+        requests: Arc<Mutex<Vec<ReceivedRequest>>>,
+    }
+
+    type Handler = dyn Fn(&str, &Value) -> Result<Value, (i64, &'static str)> + Send + Sync;
+
+    fn serve_connection(stream: TcpStream,
+                        expected_auth: Option<&str>,
+                        handler: &Handler,
+                        requests: &Mutex<Vec<ReceivedRequest>>) -> std::io::Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut stream = stream;
+        loop {
+            let mut authorization = None;
+            let mut content_length = 0;
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            loop {
+                line.clear();
+                reader.read_line(&mut line)?;
+                let line = line.trim_end();
+                if line.is_empty() {
+                    break;
+                }
+                let (name, value) = line.split_at(line.find(':').unwrap());
+                let value = value[1..].trim();
+                if name.eq_ignore_ascii_case("authorization") {
+                    authorization = Some(value.to_string());
+                } else if name.eq_ignore_ascii_case("content-length") {
+                    content_length = value.parse().unwrap();
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body)?;
+            let request: Value = serde_json::from_slice(&body).unwrap();
+            let (status, response) = if authorization.as_deref() != expected_auth {
+                ("401 Unauthorized", String::new())
+            } else {
+                let method = request["method"].as_str().unwrap();
+                match handler(method, &request["params"]) {
+                    Ok(result) => ("200 OK", json!({
+                        "result": result, "error": null, "id": "slpagora",
+                    }).to_string()),
+                    Err((code, message)) => ("500 Internal Server Error", json!({
+                        "result": null, "error": {"code": code, "message": message},
+                        "id": "slpagora",
+                    }).to_string()),
+                }
+            };
+            requests.lock().unwrap().push((authorization, request));
+            write!(stream, "HTTP/1.1 {}\r\nContent-Type: application/json\r\n\
+                            Content-Length: {}\r\n\r\n{}", status, response.len(), response)?;
+        }
+    }
+
+    impl MockNode {
+        fn start(expected_auth: Option<&'static str>,
+                 handler: impl Fn(&str, &Value) -> Result<Value, (i64, &'static str)>
+                     + Send + Sync + 'static) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}/", listener.local_addr().unwrap());
+            let requests = Arc::new(Mutex::new(Vec::new()));
+            let handler: Arc<Handler> = Arc::new(handler);
+            let server_requests = requests.clone();
+            std::thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let handler = handler.clone();
+                    let requests = server_requests.clone();
+                    std::thread::spawn(move || {
+                        serve_connection(stream.unwrap(), expected_auth, &*handler, &requests)
+                            .ok();
+                    });
+                }
+            });
+            MockNode { url, requests }
+        }
+
+        fn backend(&self, auth: RpcAuth, utxo_lookup: UtxoLookup) -> RpcBackend {
+            RpcBackend::new(Network::Mainnet,
+                            self.url.clone(),
+                            auth,
+                            utxo_lookup,
+                            Box::new(RestBackend::new(Network::Mainnet)))
+        }
+
+        fn methods(&self) -> Vec<String> {
+            self.requests.lock().unwrap().iter()
+                .map(|(_, request)| request["method"].as_str().unwrap().to_string())
+                .collect()
+        }
+    }
+
+    fn test_address(byte: u8) -> Address {
+        Address::from_bytes(AddressType::P2PKH, [byte; 20])
+    }
+
+    fn rpc_utxo(address: &Address, txid: &str, amount: f64) -> Value {
+        json!({
+            "txid": txid,
+            "vout": 1,
+            "scriptPubKey": hex::encode(address_script(address).to_vec()),
+            "amount": amount,
+        })
+    }
+
+    #[test]
+    fn user_pass_auth() {
+        let node = MockNode::start(Some("Basic dXNlcjpwYXNz"), |_, _| Ok(Value::Null));
+        let backend = node.backend(RpcAuth::UserPass("user".to_string(), "pass".to_string()),
+                                   UtxoLookup::WatchOnly);
+        assert!(!backend.is_output_unspent(&"00".repeat(32), 0).unwrap());
+        let requests = node.requests.lock().unwrap();
+        assert_eq!(requests[0].0.as_deref(), Some("Basic dXNlcjpwYXNz"));
+        assert_eq!(requests[0].1["method"], "gettxout");
+        assert_eq!(requests[0].1["params"], json!(["00".repeat(32), 0, true]));
+        drop(requests);
+        let backend = node.backend(RpcAuth::UserPass("user".to_string(), "wrong".to_string()),
+                                   UtxoLookup::WatchOnly);
+        match backend.is_output_unspent(&"00".repeat(32), 0) {
+            Err(BackendError::InvalidResponse(_)) => {},
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn cookie_auth() {
+        let node = MockNode::start(Some("Basic X19jb29raWVfXzpzZWNyZXQ="),
+                                   |_, _| Ok(Value::Null));
+        let cookie_path = std::env::temp_dir()
+            .join(format!("slpagora-test-{}.cookie", std::process::id()));
+        std::fs::write(&cookie_path, "__cookie__:secret\n").unwrap();
+        let backend = node.backend(RpcAuth::Cookie(cookie_path.clone()), UtxoLookup::WatchOnly);
+        backend.is_output_unspent(&"00".repeat(32), 0).unwrap();
+        // the cookie changes when the node restarts
+        std::fs::write(&cookie_path, "wrong").unwrap();
+        match backend.is_output_unspent(&"00".repeat(32), 0) {
+            Err(BackendError::InvalidResponse(_)) => {},
+            result => panic!("unexpected result {:?}", result),
+        }
+        std::fs::remove_file(&cookie_path).unwrap();
+        match backend.is_output_unspent(&"00".repeat(32), 0) {
+            Err(BackendError::CookieError(_)) => {},
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn watch_only_utxos() {
+        let addresses = [test_address(1), test_address(2)];
+        let utxo = rpc_utxo(&addresses[1], &"ab".repeat(32), 0.0001_2345);
+        let node = MockNode::start(None, move |method, params| match method {
+            "importmulti" => Ok(params[0].as_array().unwrap().iter()
+                .map(|_| json!({"success": true}))
+                .collect()),
+            "listunspent" => Ok(json!([utxo])),
+            _ => Err((-32601, "Method not found")),
+        });
+        let backend = node.backend(RpcAuth::None, UtxoLookup::WatchOnly);
+        for _ in 0..2 {
+            let utxos = backend.get_utxos(&addresses).unwrap();
+            assert!(utxos[0].is_empty());
+            assert_eq!(utxos[1].len(), 1);
+            assert_eq!(utxos[1][0].txid, "ab".repeat(32));
+            assert_eq!(utxos[1][0].vout, 1);
+            assert_eq!(utxos[1][0].satoshis, 12345);
+        }
+        // addresses are only imported once
+        assert_eq!(node.methods(), ["importmulti", "listunspent", "listunspent"]);
+        let requests = node.requests.lock().unwrap();
+        let import_params = &requests[0].1["params"];
+        assert_eq!(import_params[0][1]["scriptPubKey"]["address"], addresses[1].cash_addr());
+        assert_eq!(import_params[0][1]["watchonly"], true);
+        assert_eq!(import_params[1]["rescan"], false);
+        assert_eq!(requests[1].1["params"][2],
+                   json!([addresses[0].cash_addr(), addresses[1].cash_addr()]));
+    }
+
+    #[test]
+    fn failed_import() {
+        let node = MockNode::start(None, |method, _| match method {
+            "importmulti" => Ok(json!([
+                {"success": false, "error": {"code": -4, "message": "Wallet is locked"}},
+            ])),
+            _ => Err((-32601, "Method not found")),
+        });
+        let backend = node.backend(RpcAuth::None, UtxoLookup::WatchOnly);
+        match backend.get_utxos(&[test_address(1)]) {
+            Err(BackendError::RpcError(-4, _)) => {},
+            result => panic!("unexpected result {:?}", result),
+        }
+        assert_eq!(node.methods(), ["importmulti"]);
+    }
+
+    #[test]
+    fn scantxoutset_utxos() {
+        let addresses = [test_address(1), test_address(2)];
+        let utxo = rpc_utxo(&addresses[0], &"cd".repeat(32), 1.5);
+        let node = MockNode::start(None, move |method, _| match method {
+            "scantxoutset" => Ok(json!({"success": true, "unspents": [utxo]})),
+            _ => Err((-32601, "Method not found")),
+        });
+        let backend = node.backend(RpcAuth::None, UtxoLookup::ScanTxOutSet);
+        let utxos = backend.get_utxos(&addresses).unwrap();
+        assert_eq!(utxos[0].len(), 1);
+        assert_eq!(utxos[0][0].satoshis, 150_000_000);
+        assert!(utxos[1].is_empty());
+        assert_eq!(backend.are_addresses_used(&addresses).unwrap(), [true, false]);
+        // no wallet is needed on the node
+        assert_eq!(node.methods(), ["scantxoutset", "scantxoutset"]);
+        let requests = node.requests.lock().unwrap();
+        assert_eq!(requests[0].1["params"], json!([
+            "start",
+            [format!("addr({})", addresses[0].cash_addr()),
+             format!("addr({})", addresses[1].cash_addr())],
+        ]));
+    }
+
+    #[test]
+    fn unknown_tx_maps_to_none() {
+        let known_tx_id = "ef".repeat(32);
+        let block_not_found_tx_id = "cd".repeat(32);
+        let node = MockNode::start(None, move |method, params| match method {
+            "gettxoutproof" if params[0][0] == known_tx_id =>
+                Err((-1, "Block not found")),
+            "gettxoutproof" if params[0][0] == block_not_found_tx_id =>
+                Err((RPC_INVALID_ADDRESS_OR_KEY, "Block not found")),
+            "gettxoutproof" =>
+                Err((RPC_INVALID_ADDRESS_OR_KEY, "Transaction not yet in block")),
+            "getrawtransaction" =>
+                Err((RPC_INVALID_ADDRESS_OR_KEY, "No such mempool or blockchain transaction")),
+            _ => Err((-32601, "Method not found")),
+        });
+        let backend = node.backend(RpcAuth::None, UtxoLookup::WatchOnly);
+        assert!(backend.get_tx_proof(&"00".repeat(32)).unwrap().is_none());
+        assert!(backend.get_txs(&["00".repeat(32)]).unwrap().is_empty());
+        // other errors aren't hidden
+        match backend.get_tx_proof(&"ef".repeat(32)) {
+            Err(BackendError::RpcError(-1, _)) => {},
+            result => panic!("unexpected result {:?}", result),
+        }
+        // only the not-in-block error of its code means there's no proof yet
+        match backend.get_tx_proof(&"cd".repeat(32)) {
+            Err(BackendError::RpcError(RPC_INVALID_ADDRESS_OR_KEY, _)) => {},
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+}
@@ -27,19 +27,21 @@ impl Op {
     }
 
     pub fn write_to_stream<W: io::Write>(&self, write: &mut W, is_minimal_push: bool) -> io::Result<()> {
-        write.write_u8(self.code())?;
-        if let Op::Push(vec) = self {
-            match vec.len() {
-                1 if is_minimal_push && vec[0] > 0 && vec[0] <= 16 => {
+        match self {
+            Op::Push(vec) => {
+                if is_minimal_push && vec.len() == 1 && vec[0] > 0 && vec[0] <= 16 {
                     return write.write_u8(vec[0] + 0x50)
-                },
-                0 ... 0x4b => {},
-                len @ (0 ... 0xff) => { write.write_u8(len as u8)? },
-                len @ (0 ... 0xffff) => { write.write_u16::<LittleEndian>(len as u16)? },
-                len @ (0 ... 0xffff_ffff) => { write.write_u32::<LittleEndian>(len as u32)? },
-                _ => {},
-            };
-            write.write(vec)?;
+                }
+                write.write_u8(self.code())?;
+                match vec.len() {
+                    0 ..= 0x4b => {},
+                    len @ (0 ..= 0xff) => { write.write_u8(len as u8)? },
+                    len @ (0 ..= 0xffff) => { write.write_u16::<LittleEndian>(len as u16)? },
+                    len => { write.write_u32::<LittleEndian>(len as u32)? },
+                };
+                write.write_all(vec)?;
+            },
+            Op::Code(code) => write.write_u8(*code as u8)?,
         }
         Ok(())
     }
@@ -54,54 +56,236 @@ impl std::fmt::Display for Op {
     }
 }
 
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ScriptParseError {
+    /// The length of the push at the given offset is cut off.
+    TruncatedPushLength(usize),
+    /// The push at the given offset announces more bytes than remaining.
+    TruncatedPushData(usize, usize),
+    /// The ASM token at the given index is neither an op code, a number nor hex.
+    InvalidAsmToken(usize, String),
+}
+
+impl std::fmt::Display for ScriptParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ScriptParseError::TruncatedPushLength(offset) =>
+                write!(f, "truncated push length at offset {}", offset),
+            ScriptParseError::TruncatedPushData(offset, len) =>
+                write!(f, "push of {} bytes at offset {} exceeds script", len, offset),
+            ScriptParseError::InvalidAsmToken(idx, token) =>
+                write!(f, "invalid ASM token {} at index {}", token, idx),
+        }
+    }
+}
+
+impl std::error::Error for ScriptParseError {
+
+}
+
 #[derive(Clone, Debug)]
 pub struct Script {
     ops: Vec<Op>,
     is_minimal_push: bool,
+    // original serialization, kept if re-serializing `ops` wouldn't reproduce it
+    serialized: Option<Vec<u8>>,
+    // why `serialized` couldn't be parsed completely, `ops` then ends before the error
+    parse_error: Option<ScriptParseError>,
 }
 
 impl Script {
     pub fn empty() -> Self {
-        Script { ops: vec![], is_minimal_push: true }
+        Script { ops: vec![], is_minimal_push: true, serialized: None, parse_error: None }
     }
 
     pub fn new(ops: Vec<Op>) -> Self {
-        Script { ops, is_minimal_push: true }
+        Script { ops, is_minimal_push: true, serialized: None, parse_error: None }
     }
 
     pub fn new_non_minimal_push(ops: Vec<Op>) -> Self {
         Script {
             ops,
             is_minimal_push: false,
+            serialized: None,
+            parse_error: None,
+        }
+    }
+
+    pub fn from_serialized(data: &[u8]) -> Result<Self, ScriptParseError> {
+        let script = Script::from_serialized_lenient(data);
+        match script.parse_error {
+            Some(err) => Err(err),
+            None => Ok(script),
         }
     }
 
-    pub fn from_serialized(data: &[u8]) -> Self {
+    /// Like `from_serialized`, but keeps scripts which don't parse, e.g. ending in a truncated
+    /// push. They're valid in transactions and only fail once they're run. Such scripts
+    /// serialize to `data` and have the ops before the error, see `parse_error`.
+    pub fn from_serialized_lenient(data: &[u8]) -> Self {
         let mut ops = Vec::new();
+        let mut is_minimal_push = true;
+        let parse_error = Script::parse_ops(data, &mut ops, &mut is_minimal_push).err();
+        let mut script = Script {
+            ops,
+            is_minimal_push,
+            serialized: None,
+            parse_error,
+        };
+        if script.parse_error.is_some() || script.to_vec() != data {
+            script.serialized = Some(data.to_vec());
+        }
+        script
+    }
+
+    fn parse_ops(data: &[u8], ops: &mut Vec<Op>, is_minimal_push: &mut bool)
+            -> Result<(), ScriptParseError> {
+        use byteorder::ReadBytesExt;
         let mut idx = 0;
         while idx < data.len() {
-            match data[idx] {
-                n_bytes @ (0 ... 0x4b) => {
-                    let n_bytes = n_bytes as usize;
-                    ops.push(Op::Push(data[idx + 1..idx + 1 + n_bytes].to_vec()));
-                    idx += n_bytes;
+            let offset = idx;
+            let code = data[idx];
+            idx += 1;
+            let n_bytes = match code {
+                n_bytes @ (0 ..= 0x4b) => n_bytes as usize,
+                0x4c ..= 0x4e => {
+                    let len_size = match code { 0x4c => 1, 0x4d => 2, _ => 4 };
+                    if data.len() < idx + len_size {
+                        return Err(ScriptParseError::TruncatedPushLength(offset));
+                    }
+                    let mut len_bytes = &data[idx..idx + len_size];
+                    idx += len_size;
+                    match len_size {
+                        1 => len_bytes.read_u8().unwrap() as usize,
+                        2 => len_bytes.read_u16::<LittleEndian>().unwrap() as usize,
+                        _ => len_bytes.read_u32::<LittleEndian>().unwrap() as usize,
+                    }
                 },
-                0x4c => {
-                    let n_bytes = data[idx + 1] as usize;
-                    ops.push(Op::Push(data[idx + 1..idx + 1 + n_bytes].to_vec()));
-                    idx += n_bytes;
+                code => {
+                    ops.push(Op::Code(
+                        num::FromPrimitive::from_u8(code).unwrap_or(OpCodeType::OpInvalidOpcode)
+                    ));
+                    continue;
                 },
-                code => ops.push(Op::Code(num::FromPrimitive::from_u8(code).unwrap_or(OpCodeType::OpInvalidOpcode))),
+            };
+            if data.len() - idx < n_bytes {
+                return Err(ScriptParseError::TruncatedPushData(offset, n_bytes));
             }
-            idx += 1;
+            let push = data[idx..idx + n_bytes].to_vec();
+            idx += n_bytes;
+            let is_minimal_encoding = match push.len() {
+                0 => code == 0,
+                // would have been OP_1 to OP_16 or OP_1NEGATE if pushed minimally
+                1 if (push[0] > 0 && push[0] <= 16) || push[0] == 0x81 => false,
+                len @ (1 ..= 0x4b) => code as usize == len,
+                0x4c ..= 0xff => code == 0x4c,
+                0x100 ..= 0xffff => code == 0x4d,
+                _ => true,
+            };
+            if !is_minimal_encoding {
+                *is_minimal_push = false;
+            }
+            ops.push(Op::Push(push));
         }
-        Script {
-            ops,
-            is_minimal_push: true,  // TODO: may need to figure this out
+        Ok(())
+    }
+
+    /// Parses the ASM format as produced by `to_asm`. Numbers are pushed as script numbers,
+    /// other data as hex. As in Bitcoin ABC's format, data of more than 4 bytes consisting only
+    /// of decimal digits is ambiguous; it is read as data, so numbers from 1000000000 on don't
+    /// round-trip.
+    pub fn from_asm(asm: &str) -> Result<Self, ScriptParseError> {
+        use crate::script_interpreter::encode_script_num;
+        let mut ops = Vec::new();
+        for (idx, token) in asm.split_whitespace().enumerate() {
+            let invalid_token = || ScriptParseError::InvalidAsmToken(idx, token.to_string());
+            let is_number = is_asm_number(token);
+            let op = match token.parse::<i64>() {
+                Ok(0) if is_number => Op::Push(vec![]),
+                Ok(-1) if is_number => Op::Code(Op1Negate),
+                Ok(num @ 1 ..= 16) if is_number =>
+                    Op::Code(num::FromPrimitive::from_i64(num + Op1 as i64 - 1).unwrap()),
+                Ok(num) if is_number && num.abs() <= 0x7fff_ffff => Op::Push(encode_script_num(num)),
+                _ => match OpCodeType::from_name(token) {
+                    Some(code) if token.starts_with("OP_") => Op::Code(code),
+                    _ => Op::Push(hex::decode(token).map_err(|_| invalid_token())?),
+                },
+            };
+            ops.push(op);
+        }
+        Ok(Script::new(ops))
+    }
+
+    /// Formats the script like Bitcoin ABC's ScriptToAsmStr, e.g. "OP_DUP OP_HASH160 <hex> ...".
+    pub fn to_asm(&self) -> String {
+        use crate::script_interpreter::decode_script_num_lenient;
+        self.ops.iter()
+            .map(|op| match op {
+                Op::Push(vec) if vec.len() <= 4 => decode_script_num_lenient(vec).to_string(),
+                Op::Push(vec) => hex::encode(vec),
+                Op::Code(code) => code.name().to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Matches the script op by op against `template`, in which OpPubKeyHash matches any 20 byte
+    /// push, OpPubkey any public key, OpPubKeys one or more public keys and OpSmallInteger
+    /// OP_0 to OP_16. Returns the data matched by those placeholders, in order.
+    pub fn match_template(&self, template: &Script) -> Option<Vec<Vec<u8>>> {
+        fn is_pub_key(data: &[u8]) -> bool {
+            match data.len() {
+                33 => data[0] == 0x02 || data[0] == 0x03,
+                65 => data[0] == 0x04,
+                _ => false,
+            }
+        }
+        let mut matches = Vec::new();
+        let mut ops = self.ops.iter().peekable();
+        for template_op in template.ops() {
+            match template_op {
+                Op::Code(OpSmallInteger) => match ops.next()? {
+                    Op::Push(vec) if vec.is_empty() => matches.push(vec![0]),
+                    Op::Code(code) if *code >= Op1 && *code <= Op16 =>
+                        matches.push(vec![*code as u8 - Op1 as u8 + 1]),
+                    _ => return None,
+                },
+                Op::Code(OpPubKeyHash) => match ops.next()? {
+                    Op::Push(vec) if vec.len() == 20 => matches.push(vec.clone()),
+                    _ => return None,
+                },
+                Op::Code(OpPubkey) => match ops.next()? {
+                    Op::Push(vec) if is_pub_key(vec) => matches.push(vec.clone()),
+                    _ => return None,
+                },
+                Op::Code(OpPubKeys) => {
+                    let n_matches = matches.len();
+                    while let Some(Op::Push(vec)) = ops.peek() {
+                        if !is_pub_key(vec) {
+                            break;
+                        }
+                        matches.push(vec.clone());
+                        ops.next();
+                    }
+                    if matches.len() == n_matches {
+                        return None;
+                    }
+                },
+                template_op => if ops.next()? != template_op {
+                    return None;
+                },
+            }
+        }
+        match ops.next() {
+            Some(_) => None,
+            None => Some(matches),
         }
     }
 
     pub fn to_vec(&self) -> Vec<u8> {
+        if let Some(serialized) = &self.serialized {
+            return serialized.clone();
+        }
         let mut vec = Vec::new();
         for op in self.ops.iter() {
             op.write_to_stream(&mut vec, self.is_minimal_push).unwrap();
@@ -110,6 +294,7 @@ impl Script {
     }
 
     pub fn add_op(&mut self, op: Op) -> &mut Self {
+        self.serialized = None;
         self.ops.push(op);
         self
     }
@@ -117,6 +302,28 @@ impl Script {
     pub fn ops(&self) -> &[Op] {
         &self.ops
     }
+
+    pub fn is_minimal_push(&self) -> bool {
+        self.is_minimal_push
+    }
+
+    /// Why the serialization couldn't be parsed completely, for `from_serialized_lenient`.
+    pub fn parse_error(&self) -> Option<&ScriptParseError> {
+        self.parse_error.as_ref()
+    }
+}
+
+/// Whether the token is a number the way `to_asm` formats them, i.e. decimal without leading
+/// zeros, and not possibly data of more than 4 bytes in hex.
+fn is_asm_number(token: &str) -> bool {
+    let digits = token.strip_prefix('-').unwrap_or(token);
+    let is_decimal = !digits.is_empty() &&
+        digits.chars().all(|c| c.is_ascii_digit()) &&
+        (digits == "0" || !digits.starts_with('0')) &&
+        token != "-0";
+    let could_be_data = token.len() >= 10 && token.len().is_multiple_of(2) &&
+        token.chars().all(|c| c.is_ascii_hexdigit());
+    is_decimal && !could_be_data
 }
 
 impl std::fmt::Display for Script {
@@ -285,3 +492,282 @@ pub enum OpCodeType {
 
     OpInvalidOpcode = 0xff,
 }
+
+impl OpCodeType {
+    /// Name of the op code as used by Bitcoin ABC, e.g. "OP_DUP", or "1" for OP_1.
+    pub fn name(self) -> &'static str {
+        match self {
+            Op0 => "0",
+            OpPushData1 => "OP_PUSHDATA1",
+            OpPushData2 => "OP_PUSHDATA2",
+            OpPushData4 => "OP_PUSHDATA4",
+            Op1Negate => "-1",
+            OpReserved => "OP_RESERVED",
+            Op1 => "1",
+            Op2 => "2",
+            Op3 => "3",
+            Op4 => "4",
+            Op5 => "5",
+            Op6 => "6",
+            Op7 => "7",
+            Op8 => "8",
+            Op9 => "9",
+            Op10 => "10",
+            Op11 => "11",
+            Op12 => "12",
+            Op13 => "13",
+            Op14 => "14",
+            Op15 => "15",
+            Op16 => "16",
+            OpNop => "OP_NOP",
+            OpVer => "OP_VER",
+            OpIf => "OP_IF",
+            OpNotIf => "OP_NOTIF",
+            OpVerIf => "OP_VERIF",
+            OpVerNotIf => "OP_VERNOTIF",
+            OpElse => "OP_ELSE",
+            OpEndIf => "OP_ENDIF",
+            OpVerify => "OP_VERIFY",
+            OpReturn => "OP_RETURN",
+            OpToAltStack => "OP_TOALTSTACK",
+            OpFromAltStack => "OP_FROMALTSTACK",
+            Op2Drop => "OP_2DROP",
+            Op2Dup => "OP_2DUP",
+            Op3Dup => "OP_3DUP",
+            Op2Over => "OP_2OVER",
+            Op2Rot => "OP_2ROT",
+            Op2Swap => "OP_2SWAP",
+            OpIfDup => "OP_IFDUP",
+            OpDepth => "OP_DEPTH",
+            OpDrop => "OP_DROP",
+            OpDup => "OP_DUP",
+            OpNip => "OP_NIP",
+            OpOver => "OP_OVER",
+            OpPick => "OP_PICK",
+            OpRoll => "OP_ROLL",
+            OpRot => "OP_ROT",
+            OpSwap => "OP_SWAP",
+            OpTuck => "OP_TUCK",
+            OpCat => "OP_CAT",
+            OpSplit => "OP_SPLIT",
+            OpNum2Bin => "OP_NUM2BIN",
+            OpBin2Num => "OP_BIN2NUM",
+            OpSize => "OP_SIZE",
+            OpInvert => "OP_INVERT",
+            OpAnd => "OP_AND",
+            OpOr => "OP_OR",
+            OpXor => "OP_XOR",
+            OpEqual => "OP_EQUAL",
+            OpEqualVerify => "OP_EQUALVERIFY",
+            OpReserved1 => "OP_RESERVED1",
+            OpReserved2 => "OP_RESERVED2",
+            Op1Add => "OP_1ADD",
+            Op1Sub => "OP_1SUB",
+            Op2Mul => "OP_2MUL",
+            Op2Div => "OP_2DIV",
+            OpNegate => "OP_NEGATE",
+            OpAbs => "OP_ABS",
+            OpNot => "OP_NOT",
+            Op0NotEqual => "OP_0NOTEQUAL",
+            OpAdd => "OP_ADD",
+            OpSub => "OP_SUB",
+            OpMul => "OP_MUL",
+            OpDiv => "OP_DIV",
+            OpMod => "OP_MOD",
+            OpLShift => "OP_LSHIFT",
+            OpRShift => "OP_RSHIFT",
+            OpBoolAnd => "OP_BOOLAND",
+            OpBoolOr => "OP_BOOLOR",
+            OpNumEqual => "OP_NUMEQUAL",
+            OpNumEqualVerify => "OP_NUMEQUALVERIFY",
+            OpNumNotEqual => "OP_NUMNOTEQUAL",
+            OpLessThan => "OP_LESSTHAN",
+            OpGreaterThan => "OP_GREATERTHAN",
+            OpLessThanOrEqual => "OP_LESSTHANOREQUAL",
+            OpGreaterThanOrEqual => "OP_GREATERTHANOREQUAL",
+            OpMin => "OP_MIN",
+            OpMax => "OP_MAX",
+            OpWithin => "OP_WITHIN",
+            OpRipemd160 => "OP_RIPEMD160",
+            OpSha1 => "OP_SHA1",
+            OpSha256 => "OP_SHA256",
+            OpHash160 => "OP_HASH160",
+            OpHash256 => "OP_HASH256",
+            OpCodeSeparator => "OP_CODESEPARATOR",
+            OpCheckSig => "OP_CHECKSIG",
+            OpCheckSigVerify => "OP_CHECKSIGVERIFY",
+            OpCheckMultiSig => "OP_CHECKMULTISIG",
+            OpCheckMultiSigVerify => "OP_CHECKMULTISIGVERIFY",
+            OpNop1 => "OP_NOP1",
+            OpCheckLockTimeVerify => "OP_CHECKLOCKTIMEVERIFY",
+            OpCheckSequenceVerify => "OP_CHECKSEQUENCEVERIFY",
+            OpNop4 => "OP_NOP4",
+            OpNop5 => "OP_NOP5",
+            OpNop6 => "OP_NOP6",
+            OpNop7 => "OP_NOP7",
+            OpNop8 => "OP_NOP8",
+            OpNop9 => "OP_NOP9",
+            OpNop10 => "OP_NOP10",
+            OpCheckDataSig => "OP_CHECKDATASIG",
+            OpCheckDataSigVerify => "OP_CHECKDATASIGVERIFY",
+            FirstUndefinedOpCode => "OP_UNKNOWN",
+            OpPrefixBegin => "OP_PREFIX_BEGIN",
+            OpPrefixEnd => "OP_PREFIX_END",
+            OpSmallInteger => "OP_SMALLINTEGER",
+            OpPubKeys => "OP_PUBKEYS",
+            OpPubKeyHash => "OP_PUBKEYHASH",
+            OpPubkey => "OP_PUBKEY",
+            OpInvalidOpcode => "OP_INVALIDOPCODE",
+        }
+    }
+
+    /// Looks up an op code by name, accepting names with and without the "OP_" prefix, as well
+    /// as the aliases OP_FALSE, OP_TRUE, OP_NOP2 and OP_NOP3.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.trim_start_matches("OP_");
+        match name {
+            "FALSE" => return Some(Op0),
+            "TRUE" => return Some(Op1),
+            "NOP2" => return Some(OpCheckLockTimeVerify),
+            "NOP3" => return Some(OpCheckSequenceVerify),
+            "1NEGATE" => return Some(Op1Negate),
+            _ => {},
+        }
+        (0..=0xffu8)
+            .filter_map(num::FromPrimitive::from_u8)
+            .filter(|code: &OpCodeType| *code != FirstUndefinedOpCode)
+            .find(|code| code.name().trim_start_matches("OP_") == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script_interpreter::{ScriptError, ScriptInterpreter};
+    use crate::tx::Tx;
+
+    fn round_trip(script_hex: &str) -> Script {
+        let script = Script::from_serialized(&hex::decode(script_hex).unwrap()).unwrap();
+        assert_eq!(hex::encode(script.to_vec()), script_hex);
+        script
+    }
+
+    #[test]
+    fn push_encodings() {
+        let cases: &[(String, usize)] = &[
+            (format!("01{}", "ab"), 1),
+            (format!("4b{}", "ab".repeat(0x4b)), 0x4b),
+            (format!("4c4c{}", "ab".repeat(0x4c)), 0x4c),
+            (format!("4cff{}", "ab".repeat(0xff)), 0xff),
+            (format!("4d0001{}", "ab".repeat(0x100)), 0x100),
+            (format!("4e00000100{}", "ab".repeat(0x10000)), 0x10000),
+        ];
+        for (script_hex, len) in cases {
+            let script = round_trip(script_hex);
+            assert_eq!(script.ops(), &[Op::Push(vec![0xab; *len])]);
+            assert!(script.is_minimal_push());
+            // scripts built from ops serialize the same way
+            assert_eq!(&hex::encode(Script::new(script.ops().to_vec()).to_vec()), script_hex);
+        }
+    }
+
+    #[test]
+    fn non_minimal_pushes() {
+        for script_hex in &["0101", "0181", "4c01ab", "4c00", "4d0100ab", "4e01000000ab"] {
+            let script = round_trip(script_hex);
+            assert!(!script.is_minimal_push(), "{}", script_hex);
+        }
+        // OP_1 and OP_1NEGATE are op codes, not pushes
+        assert!(round_trip("514f").is_minimal_push());
+        assert!(round_trip("0120").is_minimal_push());
+    }
+
+    #[test]
+    fn truncated_pushes() {
+        for (script_hex, err) in &[
+            ("76a914abab", ScriptParseError::TruncatedPushData(2, 20)),
+            ("4c", ScriptParseError::TruncatedPushLength(0)),
+            ("6a4d01", ScriptParseError::TruncatedPushLength(1)),
+            ("4e05000000ab", ScriptParseError::TruncatedPushData(0, 5)),
+        ] {
+            let data = hex::decode(script_hex).unwrap();
+            assert_eq!(Script::from_serialized(&data).unwrap_err(), *err);
+            let script = Script::from_serialized_lenient(&data);
+            assert_eq!(script.parse_error(), Some(err));
+            assert_eq!(&hex::encode(script.to_vec()), script_hex);
+        }
+    }
+
+    #[test]
+    fn tx_with_truncated_push() {
+        // an OP_RETURN output ending in a truncated push, valid as it's never run
+        let tx_hex = "\
+            0100000001c997a5e56e104102fa209c6a852dd90660a20b2d9c352423edce25857fcd370400000000\
+            00ffffffff010000000000000000036a4c0500000000";
+        let tx = Tx::from_hex(tx_hex).unwrap();
+        let script = &tx.outputs()[0].script;
+        assert_eq!(script.ops(), &[Op::Code(OpReturn)]);
+        assert!(script.parse_error().is_some());
+        let mut tx_ser = Vec::new();
+        tx.write_to_stream(&mut tx_ser).unwrap();
+        assert_eq!(hex::encode(tx_ser), tx_hex);
+
+        let truncated = Script::from_serialized_lenient(&hex::decode("514c02ab").unwrap());
+        let mut interpreter = ScriptInterpreter::new(vec![]);
+        assert!(matches!(interpreter.run_script(&truncated), Err(ScriptError::TruncatedPush)));
+        // the ops before the truncated push were run
+        assert_eq!(interpreter.stack(), &[vec![1]]);
+    }
+
+    fn asm_round_trip(asm: &str, script_hex: &str) {
+        let script = Script::from_asm(asm).unwrap();
+        assert_eq!(hex::encode(script.to_vec()), script_hex, "{}", asm);
+        let script = Script::from_serialized(&hex::decode(script_hex).unwrap()).unwrap();
+        assert_eq!(script.to_asm(), asm);
+    }
+
+    #[test]
+    fn asm_round_trips() {
+        asm_round_trip("OP_DUP OP_HASH160 1d0f172a0ecb48aee1be1f2687d2963ae33f71a1 \
+                        OP_EQUALVERIFY OP_CHECKSIG",
+                       "76a9141d0f172a0ecb48aee1be1f2687d2963ae33f71a188ac");
+        asm_round_trip("OP_HASH160 1d0f172a0ecb48aee1be1f2687d2963ae33f71a1 OP_EQUAL",
+                       "a9141d0f172a0ecb48aee1be1f2687d2963ae33f71a187");
+        // small ints are op codes
+        asm_round_trip("0 -1 1 16", "004f5160");
+        // other pushes of up to 4 bytes are shown as script numbers
+        asm_round_trip("17 -17 1000 -1000 8388608", "0111019102e80302e8830400008000");
+        asm_round_trip("0102030405 OP_CHECKDATASIGVERIFY", "050102030405bb");
+        asm_round_trip("OP_CHECKLOCKTIMEVERIFY OP_DROP", "b175");
+    }
+
+    #[test]
+    fn asm_of_slp_send() {
+        // SLP pushes its token type with a non-minimal push, which is shown like OP_1
+        let token_id = "ab".repeat(32);
+        let script = Script::from_serialized(&hex::decode(format!(
+            "6a04534c500001010453454e4420{}080000000000000001", token_id,
+        )).unwrap()).unwrap();
+        assert_eq!(script.to_asm(),
+                   format!("OP_RETURN 5262419 1 1145980243 {} 0000000000000001", token_id));
+    }
+
+    #[test]
+    fn asm_aliases() {
+        assert_eq!(Script::from_asm("OP_FALSE OP_TRUE OP_NOP2 OP_NOP3").unwrap().to_vec(),
+                   hex::decode("0051b1b2").unwrap());
+    }
+
+    #[test]
+    fn asm_rejects_unknown_tokens() {
+        for (asm, err) in &[
+            ("OP_DUP OP_FOO", ScriptParseError::InvalidAsmToken(1, "OP_FOO".to_string())),
+            ("OP_UNKNOWN", ScriptParseError::InvalidAsmToken(0, "OP_UNKNOWN".to_string())),
+            ("abc", ScriptParseError::InvalidAsmToken(0, "abc".to_string())),
+            ("1 DUP", ScriptParseError::InvalidAsmToken(1, "DUP".to_string())),
+            ("-0", ScriptParseError::InvalidAsmToken(0, "-0".to_string())),
+        ] {
+            assert_eq!(Script::from_asm(asm).unwrap_err(), *err);
+        }
+    }
+}
@@ -1,114 +1,1260 @@
-use crate::script::{Op, OpCodeType};
-use crate::hash::{single_sha256, double_sha256};
+use crate::script::{Op, OpCodeType, Script};
+use crate::hash::{single_sha256, double_sha256, hash160, single_sha1, single_ripemd160};
+use crate::tx::{Tx, TxOutput, SIGHASH_FORKID};
 use secp256k1::{Secp256k1, All, PublicKey, Signature, Message};
 
+const MAX_NUM_SIZE: usize = 4;
+const MAX_PUB_KEYS_PER_MULTISIG: i64 = 20;
+pub const MAX_SCRIPT_ELEMENT_SIZE: usize = 520;
+pub const MAX_STACK_SIZE: usize = 1000;
+pub const MAX_OPS_PER_SCRIPT: usize = 201;
+pub const MAX_SCRIPT_SIZE: usize = 10_000;
+const LOCK_TIME_THRESHOLD: i64 = 500_000_000;
+const SEQUENCE_LOCK_TIME_DISABLE_FLAG: i64 = 1 << 31;
+const SEQUENCE_LOCK_TIME_TYPE_FLAG: i64 = 1 << 22;
+const SEQUENCE_LOCK_TIME_MASK: i64 = 0x0000_ffff;
+
 pub struct ScriptInterpreter {
     stack: Vec<Vec<u8>>,
+    alt_stack: Vec<Vec<u8>>,
+    exec_stack: Vec<bool>,
+    op_count: usize,
+    script_idx: usize,
+    trace: Option<Vec<TraceStep>>,
     curve: Secp256k1<All>,
-    pre_image_serialized: Vec<u8>,
+    sig_context: SigContext,
+}
+
+/// State of the interpreter after running an op.
+#[derive(Clone, Debug)]
+pub struct TraceStep {
+    /// Counts the scripts run by the interpreter, e.g. 0: sig script, 1: pub key script.
+    pub script_idx: usize,
+    pub op: Op,
+    /// Whether the op was in an executed branch.
+    pub is_executed: bool,
+    pub stack: Vec<Vec<u8>>,
+    pub alt_stack: Vec<Vec<u8>>,
+    pub exec_stack: Vec<bool>,
+    pub error: Option<ScriptError>,
+}
+
+impl std::fmt::Display for TraceStep {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.op)?;
+        if !self.is_executed {
+            write!(f, " (skipped)")?;
+        }
+        if !self.exec_stack.is_empty() {
+            write!(f, " [branches: {}]",
+                   self.exec_stack.iter()
+                       .map(|is_executed| if *is_executed { "1" } else { "0" })
+                       .collect::<Vec<_>>()
+                       .join(" "))?;
+        }
+        writeln!(f)?;
+        if let Some(err) = &self.error {
+            writeln!(f, "    error: {}", err)?;
+        }
+        for (i, item) in self.stack.iter().rev().enumerate() {
+            writeln!(f, "    {:5}: {}", i, hex::encode(item))?;
+        }
+        for (i, item) in self.alt_stack.iter().rev().enumerate() {
+            writeln!(f, "    alt {:1}: {}", i, hex::encode(item))?;
+        }
+        Ok(())
+    }
+}
+
+enum SigContext {
+    PreImage(Vec<u8>),
+    Input {
+        tx: Tx,
+        input_idx: usize,
+        value: u64,
+        script_code: Script,
+    },
 }
 
 #[derive(Clone, Copy, Debug)]
 pub enum ScriptError {
     InvalidPubKey,
     InvalidSignatureFormat,
-    InvalidSignature,
+    /// A signature check failed with a non-empty signature, which NULLFAIL forbids.
+    NullFail,
     NotImplemented,
+    VerifyFailed(OpCodeType),
+    DisabledOpCode(OpCodeType),
+    BadOpCode(OpCodeType),
+    InvalidStackOperation,
+    NumberOverflow,
+    NonMinimalNumber,
+    DivisionByZero,
+    InvalidOperandSize,
+    InvalidSplitRange,
+    ImpossibleEncoding,
+    PubKeyCount,
+    SigCount,
+    SigNullDummy,
+    UnbalancedConditional,
+    EarlyReturn,
+    InvalidAltStackOperation,
+    StackUnderflow,
+    PushSize,
+    StackSize,
+    OpCount,
+    ScriptSize,
+    SigPushOnly,
+    EvalFalse,
+    CleanStack,
+    /// The script ends in a push which is cut off.
+    TruncatedPush,
+    SigHashType,
+    NegativeLockTime,
+    UnsatisfiedLockTime,
+    InputIndex,
+}
+
+impl std::fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for ScriptError {
+
+}
+
+/// Verifies that input `input_idx` of `tx` is allowed to spend `prev_output`, by running the
+/// sig script, the pub key script and, for P2SH, the redeem script.
+pub fn verify_input(tx: &Tx, input_idx: usize, prev_output: &TxOutput) -> Result<(), ScriptError> {
+    ScriptInterpreter::new_for_input(tx.clone(), input_idx, prev_output.value)
+        .run_input(prev_output)
+}
+
+/// Like `verify_input`, but also returns every executed step.
+pub fn trace_input(tx: &Tx,
+                   input_idx: usize,
+                   prev_output: &TxOutput) -> (Vec<TraceStep>, Result<(), ScriptError>) {
+    let mut interpreter = ScriptInterpreter::new_for_input(tx.clone(), input_idx, prev_output.value);
+    interpreter.enable_trace();
+    let result = interpreter.run_input(prev_output);
+    (interpreter.trace.unwrap_or_default(), result)
+}
+
+/// Encodes a number as minimal little endian sign-magnitude, as used by script.
+pub fn encode_script_num(num: i64) -> Vec<u8> {
+    let mut vec = Vec::new();
+    let is_negative = num < 0;
+    let mut abs = num.unsigned_abs();
+    while abs > 0 {
+        vec.push((abs & 0xff) as u8);
+        abs >>= 8;
+    }
+    if let Some(last) = vec.last_mut() {
+        if *last & 0x80 != 0 {
+            vec.push(if is_negative { 0x80 } else { 0x00 });
+        } else if is_negative {
+            *last |= 0x80;
+        }
+    }
+    vec
+}
+
+pub fn decode_script_num(data: &[u8], max_size: usize) -> Result<i64, ScriptError> {
+    if data.len() > max_size {
+        return Err(ScriptError::NumberOverflow);
+    }
+    if !is_minimally_encoded(data) {
+        return Err(ScriptError::NonMinimalNumber);
+    }
+    Ok(decode_script_num_lenient(data))
+}
+
+/// Decodes a script number of at most 8 bytes without checking its encoding.
+pub fn decode_script_num_lenient(data: &[u8]) -> i64 {
+    let mut num = 0i64;
+    for (i, byte) in data.iter().enumerate() {
+        num |= (*byte as i64) << (8 * i);
+    }
+    match data.last() {
+        Some(last) if last & 0x80 != 0 =>
+            -(num & !(0x80i64 << (8 * (data.len() - 1)))),
+        _ => num,
+    }
+}
+
+fn is_minimally_encoded(data: &[u8]) -> bool {
+    match data {
+        [] => true,
+        [.., last] if last & 0x7f != 0 => true,
+        [_] => false,
+        [.., second_last, _] => second_last & 0x80 != 0,
+    }
+}
+
+/// Minimally encodes the number in `data`, keeping its sign.
+fn minimally_encode(data: &[u8]) -> Vec<u8> {
+    let mut vec = data.to_vec();
+    let sign_bit = match vec.last() {
+        Some(last) => last & 0x80,
+        None => return vec,
+    };
+    let last_idx = vec.len() - 1;
+    vec[last_idx] &= 0x7f;
+    while let Some(0) = vec.last() {
+        vec.pop();
+    }
+    match vec.last_mut() {
+        None => {},
+        Some(last) if *last & 0x80 != 0 => vec.push(sign_bit),
+        Some(last) => *last |= sign_bit,
+    }
+    vec
+}
+
+pub fn cast_to_bool(data: &[u8]) -> bool {
+    match data.split_last() {
+        None => false,
+        Some((last, rest)) =>
+            rest.iter().any(|byte| *byte != 0) || (*last != 0 && *last != 0x80),
+    }
 }
 
 impl ScriptInterpreter {
     pub fn new(pre_image_serialized: Vec<u8>) -> Self {
         ScriptInterpreter {
             stack: Vec::new(),
+            alt_stack: Vec::new(),
+            exec_stack: Vec::new(),
+            op_count: 0,
+            script_idx: 0,
+            trace: None,
             curve: Secp256k1::new(),
-            pre_image_serialized,
+            sig_context: SigContext::PreImage(pre_image_serialized),
         }
     }
 
+    /// Creates an interpreter which checks signatures against input `input_idx` of `tx`,
+    /// spending an output of the given value.
+    pub fn new_for_input(tx: Tx, input_idx: usize, value: u64) -> Self {
+        ScriptInterpreter {
+            stack: Vec::new(),
+            alt_stack: Vec::new(),
+            exec_stack: Vec::new(),
+            op_count: 0,
+            script_idx: 0,
+            trace: None,
+            curve: Secp256k1::new(),
+            sig_context: SigContext::Input {
+                tx,
+                input_idx,
+                value,
+                script_code: Script::empty(),
+            },
+        }
+    }
+
+    /// Records a `TraceStep` for every op run from now on.
+    pub fn enable_trace(&mut self) {
+        self.trace = Some(Vec::new());
+    }
+
+    pub fn trace(&self) -> Option<&[TraceStep]> {
+        self.trace.as_deref()
+    }
+
     pub fn run_op(&mut self, op: &Op) -> Result<(), ScriptError> {
+        let is_executed = self.is_executing();
+        let result = self.run_op_checked(op);
+        if let Some(trace) = &mut self.trace {
+            trace.push(TraceStep {
+                script_idx: self.script_idx,
+                op: op.clone(),
+                is_executed,
+                stack: self.stack.clone(),
+                alt_stack: self.alt_stack.clone(),
+                exec_stack: self.exec_stack.clone(),
+                error: result.err(),
+            });
+        }
+        result
+    }
+
+    /// Runs the sig script of the input, the pub key script of `prev_output` and, for P2SH, the
+    /// redeem script. Only works for interpreters created with `new_for_input`.
+    pub fn run_input(&mut self, prev_output: &TxOutput) -> Result<(), ScriptError> {
+        use crate::script::OpCodeType::*;
+        let sig_script = {
+            let (tx, input_idx) = self.tx_input()?;
+            tx.inputs().get(input_idx).ok_or(ScriptError::InputIndex)?.script.clone()
+        };
+        let is_push_only = sig_script.ops().iter().all(|op| match op {
+            Op::Push(_) => true,
+            Op::Code(code) => *code <= Op16,
+        });
+        if !is_push_only {
+            return Err(ScriptError::SigPushOnly);
+        }
+        self.run_script(&sig_script)?;
+        let stack_after_sig_script = self.stack.clone();
+        self.run_script(&prev_output.script)?;
+        if !self.is_success() {
+            return Err(ScriptError::EvalFalse);
+        }
+        let is_p2sh = match prev_output.script.ops() {
+            [Op::Code(OpHash160), Op::Push(hash), Op::Code(OpEqual)] => hash.len() == 20,
+            _ => false,
+        };
+        if is_p2sh {
+            self.stack = stack_after_sig_script;
+            let redeem_script = Script::from_serialized_lenient(&self.pop()?);
+            self.run_script(&redeem_script)?;
+            if !self.is_success() {
+                return Err(ScriptError::EvalFalse);
+            }
+        }
+        if self.stack.len() != 1 {
+            return Err(ScriptError::CleanStack);
+        }
+        Ok(())
+    }
+
+    fn run_op_checked(&mut self, op: &Op) -> Result<(), ScriptError> {
+        use crate::script::OpCodeType::*;
+        match op {
+            Op::Push(data) if data.len() > MAX_SCRIPT_ELEMENT_SIZE => {
+                return Err(ScriptError::PushSize)
+            },
+            Op::Code(code) if *code > Op16 => {
+                self.op_count += 1;
+                if self.op_count > MAX_OPS_PER_SCRIPT {
+                    return Err(ScriptError::OpCount)
+                }
+            },
+            _ => {},
+        }
+        self.run_op_unchecked(op)?;
+        if self.stack.len() + self.alt_stack.len() > MAX_STACK_SIZE {
+            return Err(ScriptError::StackSize);
+        }
+        Ok(())
+    }
+
+    fn run_op_unchecked(&mut self, op: &Op) -> Result<(), ScriptError> {
+        use crate::script::OpCodeType::*;
         match op {
             Op::Push(data) => {
-                self.stack.push(data.clone());
+                if self.is_executing() {
+                    self.stack.push(data.clone());
+                }
                 Ok(())
             },
-            Op::Code(code) => self.run_op_code(*code),
+            // conditionals have to be tracked in non-executed branches as well
+            Op::Code(code @ OpIf) | Op::Code(code @ OpNotIf) |
+            Op::Code(code @ OpElse) | Op::Code(code @ OpEndIf) => self.run_op_code(*code),
+            // disabled op codes fail the script even if not executed
+            Op::Code(code @ OpInvert) | Op::Code(code @ Op2Mul) | Op::Code(code @ Op2Div) |
+            Op::Code(code @ OpMul) | Op::Code(code @ OpLShift) | Op::Code(code @ OpRShift) |
+            Op::Code(code @ OpVerIf) | Op::Code(code @ OpVerNotIf) => self.run_op_code(*code),
+            Op::Code(code) => if self.is_executing() {
+                self.run_op_code(*code)
+            } else {
+                Ok(())
+            },
+        }
+    }
+
+    /// Runs all ops of the script and checks that all conditionals have been closed.
+    pub fn run_script(&mut self, script: &Script) -> Result<(), ScriptError> {
+        if script.to_vec().len() > MAX_SCRIPT_SIZE {
+            return Err(ScriptError::ScriptSize);
         }
+        self.op_count = 0;
+        self.alt_stack.clear();
+        self.set_script_code(script.clone());
+        for (idx, op) in script.ops().iter().enumerate() {
+            self.run_op(op)?;
+            if *op == Op::Code(OpCodeType::OpCodeSeparator) && self.is_executing() {
+                self.set_script_code(Script::new(script.ops()[idx + 1..].to_vec()));
+            }
+        }
+        // the ops before the unparseable part run first, as their errors take precedence
+        if script.parse_error().is_some() {
+            return Err(ScriptError::TruncatedPush);
+        }
+        self.script_idx += 1;
+        if !self.exec_stack.is_empty() {
+            return Err(ScriptError::UnbalancedConditional);
+        }
+        Ok(())
+    }
+
+    /// Whether the top of the stack is truthy, i.e. whether the script succeeded.
+    pub fn is_success(&self) -> bool {
+        self.stack.last().map(|top| cast_to_bool(top)).unwrap_or(false)
     }
 
     pub fn stack(&self) -> &[Vec<u8>] {
         &self.stack
     }
 
+    pub fn alt_stack(&self) -> &[Vec<u8>] {
+        &self.alt_stack
+    }
+
+    /// For each open OP_IF/OP_NOTIF, whether its current branch is executed.
+    pub fn exec_stack(&self) -> &[bool] {
+        &self.exec_stack
+    }
+
+    pub fn is_executing(&self) -> bool {
+        self.exec_stack.iter().all(|is_executed| *is_executed)
+    }
+
     pub fn print_stack(&self) {
         for (i, item) in self.stack.iter().rev().enumerate() {
             println!("{:5}: {}", i, hex::encode(item));
         }
     }
 
+    fn set_script_code(&mut self, script: Script) {
+        if let SigContext::Input { script_code, .. } = &mut self.sig_context {
+            *script_code = script;
+        }
+    }
+
+    fn tx_input(&self) -> Result<(&Tx, usize), ScriptError> {
+        match &self.sig_context {
+            SigContext::Input { tx, input_idx, .. } => Ok((tx, *input_idx)),
+            SigContext::PreImage(_) => Err(ScriptError::NotImplemented),
+        }
+    }
+
+    fn require(&self, n_items: usize) -> Result<(), ScriptError> {
+        if self.stack.len() < n_items {
+            return Err(ScriptError::StackUnderflow);
+        }
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Result<Vec<u8>, ScriptError> {
+        self.stack.pop().ok_or(ScriptError::StackUnderflow)
+    }
+
+    fn top(&self, depth: usize) -> Result<&[u8], ScriptError> {
+        self.require(depth + 1)?;
+        Ok(&self.stack[self.stack.len() - 1 - depth])
+    }
+
+    fn pop_num(&mut self) -> Result<i64, ScriptError> {
+        decode_script_num(&self.pop()?, MAX_NUM_SIZE)
+    }
+
+    fn pop_bool(&mut self) -> Result<bool, ScriptError> {
+        Ok(cast_to_bool(&self.pop()?))
+    }
+
+    fn push_num(&mut self, num: i64) {
+        self.stack.push(encode_script_num(num));
+    }
+
+    fn push_bool(&mut self, value: bool) {
+        self.stack.push(if value { vec![1] } else { vec![] });
+    }
+
+    fn verify(&mut self, op_code: OpCodeType) -> Result<(), ScriptError> {
+        if self.pop_bool()? {
+            Ok(())
+        } else {
+            Err(ScriptError::VerifyFailed(op_code))
+        }
+    }
+
+    /// Whether the signature (with sighash type) is valid. Errors for invalid encodings, an
+    /// empty signature is just invalid.
+    fn check_sig(&self, sig_ser: &[u8], pub_key_ser: &[u8]) -> Result<bool, ScriptError> {
+        use crate::script_interpreter::ScriptError::*;
+        if sig_ser.is_empty() {
+            return Ok(false);
+        }
+        let pub_key = PublicKey::from_slice(pub_key_ser).map_err(|_| InvalidPubKey)?;
+        let (sighash_type, sig_der) = sig_ser.split_last().unwrap();
+        let sig = Signature::from_der(sig_der).map_err(|_| InvalidSignatureFormat)?;
+        let pre_image_serialized = match &self.sig_context {
+            SigContext::PreImage(pre_image_serialized) => pre_image_serialized.clone(),
+            SigContext::Input { tx, input_idx, value, script_code } => {
+                let sighash_type = *sighash_type as u32;
+                if sighash_type & SIGHASH_FORKID == 0 {
+                    return Err(SigHashType);
+                }
+                let pre_image = tx.pre_image(*input_idx, script_code, *value, sighash_type);
+                let mut pre_image_serialized = Vec::new();
+                pre_image.write_to_stream(&mut pre_image_serialized).unwrap();
+                pre_image_serialized
+            },
+        };
+        let msg = Message::from_slice(&double_sha256(&pre_image_serialized))
+            .expect("Invalid message (this is a bug)");
+        Ok(self.curve.verify(&msg, &sig, &pub_key).is_ok())
+    }
+
+    /// Like `check_sig`, for a signature of the SHA256 of `msg` without sighash type.
+    fn check_data_sig(&self, sig_ser: &[u8], msg: &[u8], pub_key_ser: &[u8])
+            -> Result<bool, ScriptError> {
+        use crate::script_interpreter::ScriptError::*;
+        if sig_ser.is_empty() {
+            return Ok(false);
+        }
+        let pub_key = PublicKey::from_slice(pub_key_ser).map_err(|_| InvalidPubKey)?;
+        let msg = Message::from_slice(&single_sha256(msg))
+            .expect("Invalid message (this is a bug)");
+        let sig = Signature::from_der(sig_ser).map_err(|_| InvalidSignatureFormat)?;
+        Ok(self.curve.verify(&msg, &sig, &pub_key).is_ok())
+    }
+
     fn run_op_code(&mut self, op_code: OpCodeType) -> Result<(), ScriptError> {
         use crate::script::OpCodeType::*;
         use crate::script_interpreter::ScriptError::*;
         match op_code {
+            // push value
+            Op0 => self.stack.push(vec![]),
+            Op1Negate => self.push_num(-1),
+            Op1 | Op2 | Op3 | Op4 | Op5 | Op6 | Op7 | Op8 | Op9 | Op10 | Op11 | Op12 | Op13 |
+            Op14 | Op15 | Op16 => self.push_num(op_code as i64 - Op1 as i64 + 1),
+
+            // control
+            OpCheckLockTimeVerify => {
+                let lock_time = decode_script_num(self.top(0)?, 5)?;
+                if lock_time < 0 {
+                    return Err(NegativeLockTime);
+                }
+                let (tx, input_idx) = self.tx_input()?;
+                let tx_lock_time = tx.lock_time() as i64;
+                if (tx_lock_time < LOCK_TIME_THRESHOLD) != (lock_time < LOCK_TIME_THRESHOLD) ||
+                        lock_time > tx_lock_time ||
+                        tx.inputs()[input_idx].sequence == 0xffff_ffff {
+                    return Err(UnsatisfiedLockTime);
+                }
+            },
+            OpCheckSequenceVerify => {
+                let sequence = decode_script_num(self.top(0)?, 5)?;
+                if sequence < 0 {
+                    return Err(NegativeLockTime);
+                }
+                if sequence & SEQUENCE_LOCK_TIME_DISABLE_FLAG == 0 {
+                    let (tx, input_idx) = self.tx_input()?;
+                    let tx_sequence = tx.inputs()[input_idx].sequence as i64;
+                    let mask = SEQUENCE_LOCK_TIME_TYPE_FLAG | SEQUENCE_LOCK_TIME_MASK;
+                    let (sequence, masked_tx_sequence) = (sequence & mask, tx_sequence & mask);
+                    if tx.version() < 2 ||
+                            tx_sequence & SEQUENCE_LOCK_TIME_DISABLE_FLAG != 0 ||
+                            (masked_tx_sequence < SEQUENCE_LOCK_TIME_TYPE_FLAG) !=
+                                (sequence < SEQUENCE_LOCK_TIME_TYPE_FLAG) ||
+                            sequence > masked_tx_sequence {
+                        return Err(UnsatisfiedLockTime);
+                    }
+                }
+            },
+            OpNop | OpNop1 | OpNop4 | OpNop5 | OpNop6 | OpNop7 | OpNop8 | OpNop9 | OpNop10 => {},
+            OpIf | OpNotIf => {
+                let mut value = false;
+                if self.is_executing() {
+                    value = self.pop_bool()?;
+                    if op_code == OpNotIf {
+                        value = !value;
+                    }
+                }
+                self.exec_stack.push(value);
+            },
+            OpElse => {
+                let is_executed = self.exec_stack.last_mut().ok_or(UnbalancedConditional)?;
+                *is_executed = !*is_executed;
+            },
+            OpEndIf => {
+                self.exec_stack.pop().ok_or(UnbalancedConditional)?;
+            },
+            OpVerify => self.verify(op_code)?,
+            OpReturn => return Err(EarlyReturn),
+
+            // stack ops
+            OpToAltStack => {
+                let top = self.pop()?;
+                self.alt_stack.push(top);
+            },
+            OpFromAltStack => {
+                let top = self.alt_stack.pop().ok_or(InvalidAltStackOperation)?;
+                self.stack.push(top);
+            },
+            Op2Drop => {
+                self.pop()?;
+                self.pop()?;
+            },
+            Op2Dup => {
+                self.require(2)?;
+                let items = self.stack[self.stack.len() - 2..].to_vec();
+                self.stack.extend(items);
+            },
+            Op3Dup => {
+                self.require(3)?;
+                let items = self.stack[self.stack.len() - 3..].to_vec();
+                self.stack.extend(items);
+            },
+            Op2Over => {
+                self.require(4)?;
+                let items = self.stack[self.stack.len() - 4..self.stack.len() - 2].to_vec();
+                self.stack.extend(items);
+            },
+            Op2Rot => {
+                self.require(6)?;
+                let first = self.stack.remove(self.stack.len() - 6);
+                let second = self.stack.remove(self.stack.len() - 5);
+                self.stack.push(first);
+                self.stack.push(second);
+            },
+            Op2Swap => {
+                self.require(4)?;
+                let first = self.stack.remove(self.stack.len() - 4);
+                let second = self.stack.remove(self.stack.len() - 3);
+                self.stack.push(first);
+                self.stack.push(second);
+            },
+            OpIfDup => {
+                if cast_to_bool(self.top(0)?) {
+                    let top = self.top(0)?.to_vec();
+                    self.stack.push(top);
+                }
+            },
+            OpDepth => self.push_num(self.stack.len() as i64),
+            OpDrop => {
+                self.pop()?;
+            },
+            OpDup => {
+                let top = self.top(0)?.to_vec();
+                self.stack.push(top);
+            },
+            OpNip => {
+                self.require(2)?;
+                self.stack.remove(self.stack.len() - 2);
+            },
+            OpOver => {
+                let second = self.top(1)?.to_vec();
+                self.stack.push(second);
+            },
+            OpPick | OpRoll => {
+                let n = self.pop_num()?;
+                if n < 0 {
+                    return Err(InvalidStackOperation);
+                }
+                self.require(n as usize + 1)?;
+                let item = if op_code == OpPick {
+                    self.top(n as usize)?.to_vec()
+                } else {
+                    self.stack.remove(self.stack.len() - 1 - n as usize)
+                };
+                self.stack.push(item);
+            },
+            OpRot => {
+                self.require(3)?;
+                let third = self.stack.remove(self.stack.len() - 3);
+                self.stack.push(third);
+            },
             OpSwap => {
-                let top = self.stack.remove(self.stack.len() - 1);
+                self.require(2)?;
+                let top = self.pop()?;
                 self.stack.insert(self.stack.len() - 1, top);
             },
+            OpTuck => {
+                self.require(2)?;
+                let top = self.top(0)?.to_vec();
+                self.stack.insert(self.stack.len() - 2, top);
+            },
+
+            // splice ops
             OpCat => {
-                let mut first = self.stack.remove(self.stack.len() - 1);
-                let mut second = self.stack.remove(self.stack.len() - 1);
+                let mut first = self.pop()?;
+                let mut second = self.pop()?;
                 second.append(&mut first);
+                if second.len() > MAX_SCRIPT_ELEMENT_SIZE {
+                    return Err(PushSize);
+                }
                 self.stack.push(second);
             },
-            OpHash256 => {
-                let top = self.stack.remove(self.stack.len() - 1);
-                self.stack.push(double_sha256(&top).to_vec());
+            OpSplit => {
+                let position = self.pop_num()?;
+                let mut data = self.pop()?;
+                if position < 0 || position as usize > data.len() {
+                    return Err(InvalidSplitRange);
+                }
+                let second = data.split_off(position as usize);
+                self.stack.push(data);
+                self.stack.push(second);
+            },
+            OpNum2Bin => {
+                let size = self.pop_num()?;
+                if size > MAX_SCRIPT_ELEMENT_SIZE as i64 {
+                    return Err(PushSize);
+                }
+                let mut num = minimally_encode(&self.pop()?);
+                if size < 0 || (size as usize) < num.len() {
+                    return Err(ImpossibleEncoding);
+                }
+                let sign_bit = match num.last_mut() {
+                    Some(last) => {
+                        let sign_bit = *last & 0x80;
+                        *last &= 0x7f;
+                        sign_bit
+                    },
+                    None => 0,
+                };
+                num.resize(size as usize, 0);
+                if let Some(last) = num.last_mut() {
+                    *last |= sign_bit;
+                }
+                self.stack.push(num);
+            },
+            OpBin2Num => {
+                let num = minimally_encode(&self.pop()?);
+                if num.len() > MAX_NUM_SIZE {
+                    return Err(NumberOverflow);
+                }
+                self.stack.push(num);
+            },
+            OpSize => self.push_num(self.top(0)?.len() as i64),
+
+            // bit logic
+            OpAnd | OpOr | OpXor => {
+                let first = self.pop()?;
+                let second = self.pop()?;
+                if first.len() != second.len() {
+                    return Err(InvalidOperandSize);
+                }
+                self.stack.push(
+                    first.iter().zip(second.iter()).map(|(a, b)| match op_code {
+                        OpAnd => a & b,
+                        OpOr => a | b,
+                        _ => a ^ b,
+                    }).collect()
+                );
+            },
+            OpEqual | OpEqualVerify => {
+                let first = self.pop()?;
+                let second = self.pop()?;
+                self.push_bool(first == second);
+                if op_code == OpEqualVerify {
+                    self.verify(op_code)?;
+                }
+            },
+
+            // numeric
+            Op1Add | Op1Sub | OpNegate | OpAbs | OpNot | Op0NotEqual => {
+                let num = self.pop_num()?;
+                self.push_num(match op_code {
+                    Op1Add => num + 1,
+                    Op1Sub => num - 1,
+                    OpNegate => -num,
+                    OpAbs => num.abs(),
+                    OpNot => (num == 0) as i64,
+                    _ => (num != 0) as i64,
+                });
+            },
+            OpAdd | OpSub | OpDiv | OpMod | OpBoolAnd | OpBoolOr | OpNumEqual |
+            OpNumEqualVerify | OpNumNotEqual | OpLessThan | OpGreaterThan | OpLessThanOrEqual |
+            OpGreaterThanOrEqual | OpMin | OpMax => {
+                let b = self.pop_num()?;
+                let a = self.pop_num()?;
+                if (op_code == OpDiv || op_code == OpMod) && b == 0 {
+                    return Err(DivisionByZero);
+                }
+                self.push_num(match op_code {
+                    OpAdd => a + b,
+                    OpSub => a - b,
+                    OpDiv => a / b,
+                    OpMod => a % b,
+                    OpBoolAnd => (a != 0 && b != 0) as i64,
+                    OpBoolOr => (a != 0 || b != 0) as i64,
+                    OpNumEqual | OpNumEqualVerify => (a == b) as i64,
+                    OpNumNotEqual => (a != b) as i64,
+                    OpLessThan => (a < b) as i64,
+                    OpGreaterThan => (a > b) as i64,
+                    OpLessThanOrEqual => (a <= b) as i64,
+                    OpGreaterThanOrEqual => (a >= b) as i64,
+                    OpMin => a.min(b),
+                    _ => a.max(b),
+                });
+                if op_code == OpNumEqualVerify {
+                    self.verify(op_code)?;
+                }
+            },
+            OpWithin => {
+                let max = self.pop_num()?;
+                let min = self.pop_num()?;
+                let num = self.pop_num()?;
+                self.push_bool(min <= num && num < max);
+            },
+
+            // crypto
+            OpRipemd160 => {
+                let top = self.pop()?;
+                self.stack.push(single_ripemd160(&top).to_vec());
+            },
+            OpSha1 => {
+                let top = self.pop()?;
+                self.stack.push(single_sha1(&top).to_vec());
             },
             OpSha256 => {
-                let top = self.stack.remove(self.stack.len() - 1);
+                let top = self.pop()?;
                 self.stack.push(single_sha256(&top).to_vec());
             },
-            Op3Dup => {
-                self.stack.extend(
-                    self.stack[self.stack.len() - 3..].iter().cloned().collect::<Vec<_>>()
-                );
+            OpHash160 => {
+                let top = self.pop()?;
+                self.stack.push(hash160(&top).to_vec());
             },
-            OpDrop => {
-                self.stack.remove(self.stack.len() - 1);
-            },
-            OpCheckSigVerify => {
-                let pub_key = PublicKey::from_slice(
-                    &self.stack.remove(self.stack.len() - 1)
-                ).map_err(|_| InvalidPubKey)?;
-                let mut sig_ser = self.stack.remove(self.stack.len() - 1);
-                sig_ser.remove(sig_ser.len() - 1);
-                let sig = Signature::from_der(&sig_ser)
-                    .map_err(|_| InvalidSignatureFormat)?;
-                let msg = Message::from_slice(&double_sha256(&self.pre_image_serialized))
-                    .expect("Invalid message (this is a bug)");
-                self.curve.verify(&msg, &sig, &pub_key).map_err(|_| InvalidSignature)?;
+            OpHash256 => {
+                let top = self.pop()?;
+                self.stack.push(double_sha256(&top).to_vec());
             },
-            OpRot => {
-                let third = self.stack.remove(self.stack.len() - 3);
-                self.stack.push(third);
+            OpCodeSeparator => {},
+            OpCheckSig | OpCheckSigVerify => {
+                let pub_key = self.pop()?;
+                let sig_ser = self.pop()?;
+                let is_valid = self.check_sig(&sig_ser, &pub_key)?;
+                if !is_valid && !sig_ser.is_empty() {
+                    return Err(NullFail);
+                }
+                self.push_bool(is_valid);
+                if op_code == OpCheckSigVerify {
+                    self.verify(op_code)?;
+                }
             },
-            OpCheckDataSig => {
-                let pub_key = PublicKey::from_slice(
-                    &self.stack.remove(self.stack.len() - 1)
-                ).map_err(|_| InvalidPubKey)?;
-                let msg = Message::from_slice(
-                    &single_sha256(&self.stack.remove(self.stack.len() - 1))
-                ).expect("Invalid message (this is a bug)");
-                let sig = Signature::from_der(&self.stack.remove(self.stack.len() - 1))
-                    .map_err(|_| InvalidSignatureFormat)?;
-                if let Ok(_) = self.curve.verify(&msg, &sig, &pub_key) {
-                    self.stack.push(vec![1])
-                } else {
-                    println!("Note: OP_CHECKDATASIG failed");
-                    self.stack.push(vec![0]);
+            OpCheckMultiSig | OpCheckMultiSigVerify => {
+                let n_pub_keys = self.pop_num()?;
+                if !(0..=MAX_PUB_KEYS_PER_MULTISIG).contains(&n_pub_keys) {
+                    return Err(PubKeyCount);
+                }
+                self.op_count += n_pub_keys as usize;
+                if self.op_count > MAX_OPS_PER_SCRIPT {
+                    return Err(OpCount);
+                }
+                let pub_keys = (0..n_pub_keys).map(|_| self.pop()).collect::<Result<Vec<_>, _>>()?;
+                let n_sigs = self.pop_num()?;
+                if n_sigs < 0 || n_sigs > n_pub_keys {
+                    return Err(SigCount);
+                }
+                let sigs = (0..n_sigs).map(|_| self.pop()).collect::<Result<Vec<_>, _>>()?;
+                if !self.pop()?.is_empty() {
+                    return Err(SigNullDummy);
+                }
+                // signatures have to be in the same order as the public keys
+                let mut pub_keys = pub_keys.iter();
+                let mut is_valid = true;
+                for sig in sigs.iter() {
+                    if sig.is_empty() {
+                        is_valid = false;
+                        break;
+                    }
+                    let found = loop {
+                        match pub_keys.next() {
+                            Some(pub_key) => if self.check_sig(sig, pub_key)? {
+                                break true;
+                            },
+                            None => break false,
+                        }
+                    };
+                    if !found {
+                        is_valid = false;
+                        break;
+                    }
+                }
+                if !is_valid && sigs.iter().any(|sig| !sig.is_empty()) {
+                    return Err(NullFail);
+                }
+                self.push_bool(is_valid);
+                if op_code == OpCheckMultiSigVerify {
+                    self.verify(op_code)?;
                 }
             },
-            _ => return Err(NotImplemented),
+            OpCheckDataSig | OpCheckDataSigVerify => {
+                let pub_key = self.pop()?;
+                let msg = self.pop()?;
+                let sig = self.pop()?;
+                let is_valid = self.check_data_sig(&sig, &msg, &pub_key)?;
+                if !is_valid && !sig.is_empty() {
+                    return Err(NullFail);
+                }
+                self.push_bool(is_valid);
+                if op_code == OpCheckDataSigVerify {
+                    self.verify(op_code)?;
+                }
+            },
+
+            // disabled since the genesis of Bitcoin Cash
+            OpInvert | Op2Mul | Op2Div | OpMul | OpLShift | OpRShift => {
+                return Err(DisabledOpCode(op_code))
+            },
+            OpReserved | OpVer | OpVerIf | OpVerNotIf | OpReserved1 | OpReserved2 => {
+                return Err(BadOpCode(op_code))
+            },
+            _ => return Err(BadOpCode(op_code)),
         };
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::{Address, AddressType};
+    use crate::incomplete_tx::{IncompleteTx, Output, Utxo};
+    use crate::outputs::{P2PKHOutput, P2SHOutput};
+    use crate::tx::TxOutpoint;
+    use secp256k1::SecretKey;
+
+    const PRE_IMAGE: &[u8] = b"pre image";
+
+    fn run_ops(ops: Vec<Op>) -> Result<Vec<Vec<u8>>, ScriptError> {
+        let mut interpreter = ScriptInterpreter::new(PRE_IMAGE.to_vec());
+        interpreter.run_script(&Script::new(ops))?;
+        Ok(interpreter.stack().to_vec())
+    }
+
+    fn run(asm: &str) -> Result<Vec<Vec<u8>>, ScriptError> {
+        run_ops(Script::from_asm(asm).unwrap().ops().to_vec())
+    }
+
+    fn stack(items: &[&str]) -> Vec<Vec<u8>> {
+        items.iter().map(|item| hex::decode(item).unwrap()).collect()
+    }
+
+    fn keys() -> Vec<(SecretKey, Vec<u8>)> {
+        let curve = Secp256k1::new();
+        (1..=3u8)
+            .map(|i| {
+                let key = SecretKey::from_slice(&[i; 32]).unwrap();
+                let pub_key = PublicKey::from_secret_key(&curve, &key).serialize().to_vec();
+                (key, pub_key)
+            })
+            .collect()
+    }
+
+    /// Signs `PRE_IMAGE` like a transaction signature, with sighash type.
+    fn sign(key: &SecretKey) -> Vec<u8> {
+        let msg = Message::from_slice(&double_sha256(PRE_IMAGE)).unwrap();
+        let mut sig = Secp256k1::new().sign(&msg, key).serialize_der().to_vec();
+        sig.push(0x41);
+        sig
+    }
+
+    fn sign_data(key: &SecretKey, data: &[u8]) -> Vec<u8> {
+        let msg = Message::from_slice(&single_sha256(data)).unwrap();
+        Secp256k1::new().sign(&msg, key).serialize_der().to_vec()
+    }
+
+    #[test]
+    fn stack_ops() {
+        assert_eq!(run("1 2 OP_SWAP").unwrap(), stack(&["02", "01"]));
+        assert_eq!(run("1 2 3 OP_ROT").unwrap(), stack(&["02", "03", "01"]));
+        assert_eq!(run("1 2 3 4 OP_2SWAP").unwrap(), stack(&["03", "04", "01", "02"]));
+        assert_eq!(run("1 2 3 4 5 6 OP_2ROT").unwrap(),
+                   stack(&["03", "04", "05", "06", "01", "02"]));
+        assert_eq!(run("1 2 3 2 OP_PICK").unwrap(), stack(&["01", "02", "03", "01"]));
+        assert_eq!(run("1 2 3 2 OP_ROLL").unwrap(), stack(&["02", "03", "01"]));
+        assert_eq!(run("1 2 OP_TUCK").unwrap(), stack(&["02", "01", "02"]));
+        assert_eq!(run("1 2 OP_OVER").unwrap(), stack(&["01", "02", "01"]));
+        assert_eq!(run("1 2 OP_NIP OP_DEPTH").unwrap(), stack(&["02", "01"]));
+        assert_eq!(run("0 OP_IFDUP 1 OP_IFDUP").unwrap(), stack(&["", "01", "01"]));
+        assert_eq!(run("1 2 3 OP_3DUP").unwrap(),
+                   stack(&["01", "02", "03", "01", "02", "03"]));
+        assert!(matches!(run("1 1 OP_PICK"), Err(ScriptError::StackUnderflow)));
+        assert!(matches!(run("1 -1 OP_ROLL"), Err(ScriptError::InvalidStackOperation)));
+    }
+
+    #[test]
+    fn splice_ops() {
+        assert_eq!(run("abcd ef OP_CAT").unwrap(), stack(&["abcdef"]));
+        assert_eq!(run("abcdef 1 OP_SPLIT").unwrap(), stack(&["ab", "cdef"]));
+        assert_eq!(run("abcdef 0 OP_SPLIT").unwrap(), stack(&["", "abcdef"]));
+        assert_eq!(run("abcdef 3 OP_SPLIT").unwrap(), stack(&["abcdef", ""]));
+        assert!(matches!(run("abcdef 4 OP_SPLIT"), Err(ScriptError::InvalidSplitRange)));
+        assert!(matches!(run("abcdef -1 OP_SPLIT"), Err(ScriptError::InvalidSplitRange)));
+        assert_eq!(run("abcdef OP_SIZE").unwrap(), stack(&["abcdef", "03"]));
+        assert_eq!(run("-5 4 OP_NUM2BIN").unwrap(), stack(&["05000080"]));
+        assert_eq!(run("0 3 OP_NUM2BIN").unwrap(), stack(&["000000"]));
+        assert!(matches!(run("abcd 1 OP_NUM2BIN"), Err(ScriptError::ImpossibleEncoding)));
+        assert!(matches!(run("ab 521 OP_NUM2BIN"), Err(ScriptError::PushSize)));
+        assert_eq!(run("05000080 OP_BIN2NUM").unwrap(), stack(&["85"]));
+        assert_eq!(run("0000000080 OP_BIN2NUM").unwrap(), stack(&[""]));
+        assert_eq!(run("ab00000000 OP_BIN2NUM").unwrap(), stack(&["ab00"]));
+        assert!(matches!(run("0102030405 OP_BIN2NUM"), Err(ScriptError::NumberOverflow)));
+        let long = "ab".repeat(MAX_SCRIPT_ELEMENT_SIZE);
+        assert!(matches!(run(&format!("{} ab OP_CAT", long)), Err(ScriptError::PushSize)));
+    }
+
+    #[test]
+    fn script_numbers() {
+        for (num, encoded) in &[(0, ""), (1, "01"), (-1, "81"), (127, "7f"), (128, "8000"),
+                                (-128, "8080"), (255, "ff00"), (0x7fff_ffff, "ffffff7f"),
+                                (-0x7fff_ffff, "ffffffff"), (0x8000_0000, "0000008000")] {
+            assert_eq!(hex::encode(encode_script_num(*num)), *encoded);
+            let data = hex::decode(encoded).unwrap();
+            assert_eq!(decode_script_num(&data, 5).unwrap(), *num);
+        }
+        // negative zero is false, but not a valid number
+        assert!(!cast_to_bool(&[0x80]));
+        assert!(!cast_to_bool(&[0x00, 0x00, 0x80]));
+        assert!(cast_to_bool(&[0x80, 0x00]));
+        assert!(matches!(decode_script_num(&[0x80], 4), Err(ScriptError::NonMinimalNumber)));
+        assert!(matches!(run_ops(vec![Op::Push(vec![0x80]), Op::Code(OpCodeType::Op1Add)]),
+                         Err(ScriptError::NonMinimalNumber)));
+        assert!(matches!(decode_script_num(&[0x01, 0x00], 4), Err(ScriptError::NonMinimalNumber)));
+        // results may exceed 4 bytes, but can't be used as operands
+        assert_eq!(run("ffffff7f 1 OP_ADD").unwrap(), stack(&["0000008000"]));
+        assert!(matches!(run("ffffff7f 1 OP_ADD OP_1ADD"), Err(ScriptError::NumberOverflow)));
+        assert!(matches!(decode_script_num(&[0, 0, 0, 0x80, 0], 4),
+                         Err(ScriptError::NumberOverflow)));
+    }
+
+    #[test]
+    fn multisig_order() {
+        use crate::script::OpCodeType::*;
+        let keys = keys();
+        let sigs = keys.iter().map(|(key, _)| sign(key)).collect::<Vec<_>>();
+        let multisig = |sigs: &[&[u8]]| {
+            let mut ops = vec![Op::Push(vec![])];
+            ops.extend(sigs.iter().map(|sig| Op::Push(sig.to_vec())));
+            ops.push(Op::Code(Op2));
+            ops.extend(keys.iter().map(|(_, pub_key)| Op::Push(pub_key.clone())));
+            ops.push(Op::Code(Op3));
+            ops.push(Op::Code(OpCheckMultiSig));
+            run_ops(ops)
+        };
+        assert_eq!(multisig(&[&sigs[0], &sigs[1]]).unwrap(), stack(&["01"]));
+        assert_eq!(multisig(&[&sigs[0], &sigs[2]]).unwrap(), stack(&["01"]));
+        assert_eq!(multisig(&[&sigs[1], &sigs[2]]).unwrap(), stack(&["01"]));
+        // signatures in a different order than their keys fail
+        assert!(matches!(multisig(&[&sigs[1], &sigs[0]]), Err(ScriptError::NullFail)));
+        assert!(matches!(multisig(&[&sigs[0], &sigs[0]]), Err(ScriptError::NullFail)));
+        assert!(matches!(multisig(&[&sigs[0], &[]]), Err(ScriptError::NullFail)));
+        assert_eq!(multisig(&[&[], &[]]).unwrap(), stack(&[""]));
+        // badly encoded signatures fail the script instead of being skipped
+        assert!(matches!(multisig(&[&sigs[0], &[0x30, 0x00, 0x41]]),
+                         Err(ScriptError::InvalidSignatureFormat)));
+
+        let mut ops = Script::from_asm("1 0 0").unwrap().ops().to_vec();
+        ops.push(Op::Code(OpCheckMultiSig));
+        assert!(matches!(run_ops(ops), Err(ScriptError::SigNullDummy)));
+    }
+
+    #[test]
+    fn null_fail() {
+        use crate::script::OpCodeType::*;
+        let keys = keys();
+        let check_sig = |sig: Vec<u8>, pub_key: &[u8], op_code| {
+            run_ops(vec![Op::Push(sig), Op::Push(pub_key.to_vec()), Op::Code(op_code)])
+        };
+        assert_eq!(check_sig(sign(&keys[0].0), &keys[0].1, OpCheckSig).unwrap(), stack(&["01"]));
+        assert_eq!(check_sig(vec![], &keys[0].1, OpCheckSig).unwrap(), stack(&[""]));
+        assert!(matches!(check_sig(sign(&keys[1].0), &keys[0].1, OpCheckSig),
+                         Err(ScriptError::NullFail)));
+        assert!(matches!(check_sig(vec![], &keys[0].1, OpCheckSigVerify),
+                         Err(ScriptError::VerifyFailed(OpCheckSigVerify))));
+
+        let msg = b"message".to_vec();
+        let check_data_sig = |sig: Vec<u8>, pub_key: &[u8], op_code| {
+            run_ops(vec![Op::Push(sig), Op::Push(msg.clone()), Op::Push(pub_key.to_vec()),
+                         Op::Code(op_code)])
+        };
+        let data_sig = sign_data(&keys[0].0, &msg);
+        assert_eq!(check_data_sig(data_sig.clone(), &keys[0].1, OpCheckDataSig).unwrap(),
+                   stack(&["01"]));
+        assert_eq!(check_data_sig(data_sig.clone(), &keys[0].1, OpCheckDataSigVerify).unwrap(),
+                   stack(&[]));
+        assert_eq!(check_data_sig(vec![], &keys[0].1, OpCheckDataSig).unwrap(), stack(&[""]));
+        assert!(matches!(check_data_sig(vec![], &keys[0].1, OpCheckDataSigVerify),
+                         Err(ScriptError::VerifyFailed(OpCheckDataSigVerify))));
+        assert!(matches!(check_data_sig(data_sig, &keys[1].1, OpCheckDataSig),
+                         Err(ScriptError::NullFail)));
+        assert!(matches!(check_data_sig(vec![0x30, 0x00], &keys[0].1, OpCheckDataSig),
+                         Err(ScriptError::InvalidSignatureFormat)));
+    }
+
+    #[test]
+    fn conditionals() {
+        use crate::script::OpCodeType::*;
+        assert_eq!(run("1 OP_IF 2 OP_ELSE 3 OP_ENDIF").unwrap(), stack(&["02"]));
+        assert_eq!(run("0 OP_IF 2 OP_ELSE 3 OP_ENDIF").unwrap(), stack(&["03"]));
+        assert_eq!(run("1 OP_NOTIF 2 OP_ELSE 3 OP_ENDIF").unwrap(), stack(&["03"]));
+        assert_eq!(run("0 OP_NOTIF 2 OP_ENDIF").unwrap(), stack(&["02"]));
+        assert_eq!(run("1 OP_IF 0 OP_IF 2 OP_ELSE 3 OP_ENDIF OP_ELSE 4 OP_ENDIF").unwrap(),
+                   stack(&["03"]));
+        assert_eq!(run("0 OP_IF 0 OP_IF 2 OP_ELSE 3 OP_ENDIF OP_ELSE 4 OP_ENDIF").unwrap(),
+                   stack(&["04"]));
+        // each OP_ELSE flips the branch
+        assert_eq!(run("1 OP_IF 2 OP_ELSE 3 OP_ELSE 4 OP_ENDIF").unwrap(), stack(&["02", "04"]));
+        // ops in skipped branches are not run, except for disabled op codes
+        assert_eq!(run("0 OP_IF OP_RETURN OP_ENDIF 1").unwrap(), stack(&["01"]));
+        assert!(matches!(run("0 OP_IF OP_MUL OP_ENDIF 1"),
+                         Err(ScriptError::DisabledOpCode(OpMul))));
+        assert!(matches!(run("0 OP_IF OP_VERIF OP_ENDIF 1"),
+                         Err(ScriptError::BadOpCode(OpVerIf))));
+
+        let mut interpreter = ScriptInterpreter::new(PRE_IMAGE.to_vec());
+        for op in Script::from_asm("1 OP_IF 0 OP_IF").unwrap().ops() {
+            interpreter.run_op(op).unwrap();
+        }
+        assert_eq!(interpreter.exec_stack(), &[true, false]);
+        assert!(!interpreter.is_executing());
+    }
+
+    #[test]
+    fn unbalanced_conditionals() {
+        for asm in &["1 OP_IF 2", "1 OP_IF 2 OP_ELSE 3", "OP_ENDIF", "OP_ELSE",
+                     "1 OP_IF OP_ENDIF OP_ENDIF", "0 OP_IF 1 OP_IF OP_ENDIF"] {
+            assert!(matches!(run(asm), Err(ScriptError::UnbalancedConditional)), "{}", asm);
+        }
+        // conditionals can't span scripts
+        let mut interpreter = ScriptInterpreter::new(PRE_IMAGE.to_vec());
+        let result = interpreter.run_script(&Script::from_asm("1 OP_IF").unwrap());
+        assert!(matches!(result, Err(ScriptError::UnbalancedConditional)));
+    }
+
+    #[test]
+    fn alt_stack() {
+        let mut interpreter = ScriptInterpreter::new(PRE_IMAGE.to_vec());
+        for op in Script::from_asm("1 2 OP_TOALTSTACK 3").unwrap().ops() {
+            interpreter.run_op(op).unwrap();
+        }
+        assert_eq!(interpreter.stack(), stack(&["01", "03"]).as_slice());
+        assert_eq!(interpreter.alt_stack(), stack(&["02"]).as_slice());
+        assert_eq!(run("1 2 OP_TOALTSTACK 3 OP_FROMALTSTACK").unwrap(), stack(&["01", "03", "02"]));
+        assert!(matches!(run("1 OP_FROMALTSTACK"), Err(ScriptError::InvalidAltStackOperation)));
+        assert!(matches!(run("OP_TOALTSTACK"), Err(ScriptError::StackUnderflow)));
+
+        // the alt stack is cleared between scripts
+        let mut interpreter = ScriptInterpreter::new(PRE_IMAGE.to_vec());
+        interpreter.run_script(&Script::from_asm("1 OP_TOALTSTACK").unwrap()).unwrap();
+        let result = interpreter.run_script(&Script::from_asm("OP_FROMALTSTACK").unwrap());
+        assert!(matches!(result, Err(ScriptError::InvalidAltStackOperation)));
+    }
+
+    #[test]
+    fn stack_underflow() {
+        for asm in &["OP_DROP", "OP_DUP", "1 OP_SWAP", "1 2 OP_ROT", "1 2 3 OP_2SWAP", "1 OP_2DUP",
+                     "1 OP_EQUAL", "OP_SIZE", "1 OP_ADD", "abcd OP_CAT", "1 OP_CHECKSIG",
+                     "1 2 OP_CHECKDATASIG", "1 2 OP_WITHIN", "OP_1ADD"] {
+            assert!(matches!(run(asm), Err(ScriptError::StackUnderflow)), "{}", asm);
+        }
+    }
+
+    #[test]
+    fn push_size_limit() {
+        let at_limit = run_ops(vec![Op::Push(vec![0xab; MAX_SCRIPT_ELEMENT_SIZE])]).unwrap();
+        assert_eq!(at_limit[0].len(), MAX_SCRIPT_ELEMENT_SIZE);
+        let above_limit = run_ops(vec![Op::Push(vec![0xab; MAX_SCRIPT_ELEMENT_SIZE + 1])]);
+        assert!(matches!(above_limit, Err(ScriptError::PushSize)));
+    }
+
+    #[test]
+    fn stack_size_limit() {
+        let mut ops = vec![Op::Code(OpCodeType::Op1); MAX_STACK_SIZE];
+        assert_eq!(run_ops(ops.clone()).unwrap().len(), MAX_STACK_SIZE);
+        ops.push(Op::Code(OpCodeType::Op1));
+        assert!(matches!(run_ops(ops.clone()), Err(ScriptError::StackSize)));
+        // items on the alt stack count as well
+        ops.pop();
+        ops.push(Op::Code(OpCodeType::OpToAltStack));
+        assert_eq!(run_ops(ops.clone()).unwrap().len(), MAX_STACK_SIZE - 1);
+        ops.push(Op::Code(OpCodeType::Op1));
+        assert!(matches!(run_ops(ops), Err(ScriptError::StackSize)));
+    }
+
+    #[test]
+    fn op_count_limit() {
+        let nops = |n| vec![Op::Code(OpCodeType::OpNop); n];
+        assert!(run_ops(nops(MAX_OPS_PER_SCRIPT)).is_ok());
+        assert!(matches!(run_ops(nops(MAX_OPS_PER_SCRIPT + 1)), Err(ScriptError::OpCount)));
+        // pushes don't count
+        let mut ops = nops(MAX_OPS_PER_SCRIPT);
+        ops.extend(Script::from_asm("1 abcd 16").unwrap().ops().iter().cloned());
+        assert!(run_ops(ops).is_ok());
+        // each public key of a multisig counts as an op, in addition to the op code itself
+        let multisig = |n_nops| {
+            let mut ops = nops(n_nops);
+            let multisig = Script::from_asm("0 0 abcd 1 OP_CHECKMULTISIG").unwrap();
+            ops.extend(multisig.ops().iter().cloned());
+            run_ops(ops)
+        };
+        assert_eq!(multisig(MAX_OPS_PER_SCRIPT - 2).unwrap(), stack(&["01"]));
+        assert!(matches!(multisig(MAX_OPS_PER_SCRIPT - 1), Err(ScriptError::OpCount)));
+    }
+
+    #[test]
+    fn script_size_limit() {
+        // 19 pushes of 3 + 520 bytes and one of 1 + 62 bytes
+        let mut ops = vec![Op::Push(vec![0xab; MAX_SCRIPT_ELEMENT_SIZE]); 19];
+        ops.push(Op::Push(vec![0xab; 62]));
+        assert_eq!(Script::new(ops.clone()).to_vec().len(), MAX_SCRIPT_SIZE);
+        assert_eq!(run_ops(ops.clone()).unwrap().len(), 20);
+        ops.push(Op::Code(OpCodeType::OpNop));
+        assert!(matches!(run_ops(ops), Err(ScriptError::ScriptSize)));
+    }
+
+    /// A tx spending `output` with the first key of `keys`.
+    fn signed_tx<O: Output + 'static>(output: O) -> (Tx, TxOutput) {
+        let prev_output = TxOutput::new(output.value(), output.script());
+        let mut tx_build = IncompleteTx::new_simple();
+        tx_build.add_utxo(Utxo {
+            outpoint: TxOutpoint { tx_hash: [0x56; 32], output_idx: 0 },
+            sequence: 0xffff_ffff,
+            output: Box::new(output),
+            key: keys()[0].0,
+        });
+        tx_build.add_output(&P2PKHOutput {
+            value: 9000,
+            address: Address::from_bytes(AddressType::P2PKH, [3; 20]),
+        });
+        (tx_build.sign(), prev_output)
+    }
+
+    fn p2pkh_output() -> P2PKHOutput {
+        let pub_key = PublicKey::from_slice(&keys()[0].1).unwrap();
+        P2PKHOutput {
+            value: 10_000,
+            address: Address::from_pub_key("bitcoincash", &pub_key),
+        }
+    }
+
+    #[test]
+    fn verify_p2pkh() {
+        let (tx, prev_output) = signed_tx(p2pkh_output());
+        verify_input(&tx, 0, &prev_output).unwrap();
+        assert!(matches!(verify_input(&tx, 1, &prev_output), Err(ScriptError::InputIndex)));
+        // the signature commits to the value of the spent output
+        let wrong_value = TxOutput::new(prev_output.value + 1, prev_output.script.clone());
+        assert!(matches!(verify_input(&tx, 0, &wrong_value), Err(ScriptError::NullFail)));
+    }
+
+    #[test]
+    fn verify_p2sh() {
+        let (tx, prev_output) = signed_tx(P2SHOutput { output: p2pkh_output() });
+        verify_input(&tx, 0, &prev_output).unwrap();
+        // the redeem script has to match the script hash
+        let other_script = P2SHOutput {
+            output: P2PKHOutput {
+                value: 10_000,
+                address: Address::from_bytes(AddressType::P2PKH, [4; 20]),
+            },
+        }.script();
+        let other_output = TxOutput::new(prev_output.value, other_script);
+        assert!(matches!(verify_input(&tx, 0, &other_output), Err(ScriptError::EvalFalse)));
+    }
+
+    #[test]
+    fn verify_tampered_output() {
+        let (tx, prev_output) = signed_tx(p2pkh_output());
+        let mut outputs = tx.outputs().to_vec();
+        outputs[0].value += 1;
+        let tampered = Tx::new(tx.version(), tx.inputs().to_vec(), outputs, tx.lock_time());
+        assert!(matches!(verify_input(&tampered, 0, &prev_output), Err(ScriptError::NullFail)));
+    }
+}
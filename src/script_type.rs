@@ -0,0 +1,275 @@
+use crate::script::{Script, Op, OpCodeType::*};
+use crate::outputs::{SLPSendOutput, TradeOfferOutput};
+use crate::address::{Address, AddressType};
+use crate::network::Network;
+
+use std::io::Cursor;
+use byteorder::{BigEndian, ReadBytesExt};
+
+
+#[derive(Clone, Debug)]
+pub enum ScriptType {
+    P2PKH {
+        pub_key_hash: [u8; 20],
+    },
+    P2SH {
+        script_hash: [u8; 20],
+    },
+    P2PK {
+        pub_key: Vec<u8>,
+    },
+    MultiSig {
+        n_required: usize,
+        pub_keys: Vec<Vec<u8>>,
+    },
+    SLPSend(SLPSendOutput),
+    /// Any other SLP message, e.g. GENESIS or MINT.
+    SLPMessage {
+        token_type: Vec<u8>,
+        transaction_type: Vec<u8>,
+        pushes: Vec<Vec<u8>>,
+    },
+    ExchOffer(TradeOfferOutput),
+    OpReturn {
+        pushes: Vec<Vec<u8>>,
+    },
+    Unknown,
+}
+
+fn bytes_20(vec: &[u8]) -> [u8; 20] {
+    let mut bytes = [0; 20];
+    bytes.copy_from_slice(vec);
+    bytes
+}
+
+fn op_return_pushes(script: &Script) -> Option<Vec<Vec<u8>>> {
+    let (first, rest) = script.ops().split_first()?;
+    if *first != Op::Code(OpReturn) {
+        return None;
+    }
+    rest.iter().map(|op| match op {
+        Op::Push(vec) => Some(vec.clone()),
+        Op::Code(Op1Negate) => Some(vec![0x81]),
+        Op::Code(code) if *code >= Op1 && *code <= Op16 => Some(vec![*code as u8 - Op1 as u8 + 1]),
+        _ => None,
+    }).collect()
+}
+
+fn parse_slp(pushes: &[Vec<u8>]) -> Option<ScriptType> {
+    if pushes.len() < 3 || pushes[0] != b"SLP\0" {
+        return None;
+    }
+    let token_type = &pushes[1];
+    let transaction_type = &pushes[2];
+    if transaction_type.as_slice() == b"SEND" && token_type.len() == 1 && pushes.len() >= 5 &&
+            pushes[3].len() == 32 && pushes[4..].iter().all(|quantity| quantity.len() == 8) {
+        let mut token_id = [0; 32];
+        token_id.copy_from_slice(&pushes[3]);
+        return Some(ScriptType::SLPSend(SLPSendOutput {
+            token_type: token_type[0],
+            token_id,
+            output_quantities: pushes[4..].iter()
+                .map(|quantity| Cursor::new(quantity).read_u64::<BigEndian>().unwrap())
+                .collect(),
+        }));
+    }
+    Some(ScriptType::SLPMessage {
+        token_type: token_type.clone(),
+        transaction_type: transaction_type.clone(),
+        pushes: pushes[3..].to_vec(),
+    })
+}
+
+fn parse_exch(pushes: &[Vec<u8>], network: Network) -> Option<ScriptType> {
+    let expected_lens = [4, 1, 4, 32, 4, 8, 8, 20, 20];
+    if pushes.len() != expected_lens.len() ||
+            pushes.iter().zip(expected_lens.iter()).any(|(push, len)| push.len() != *len) ||
+            pushes[0] != b"EXCH" || pushes[1] != b"\x01" || pushes[2] != b"SELL" {
+        return None;
+    }
+    let mut tx_id = [0; 32];
+    tx_id.copy_from_slice(&pushes[3]);
+    let prefix = network.bch_prefix();
+    Some(ScriptType::ExchOffer(TradeOfferOutput {
+        tx_id,
+        output_idx: Cursor::new(&pushes[4]).read_u32::<BigEndian>().unwrap(),
+        sell_amount: Cursor::new(&pushes[5]).read_u64::<BigEndian>().unwrap(),
+        buy_amount: Cursor::new(&pushes[6]).read_u64::<BigEndian>().unwrap(),
+        receiving_address: Address::from_bytes_prefix(prefix, AddressType::P2PKH,
+                                                      bytes_20(&pushes[7])),
+        cancel_address: Address::from_bytes_prefix(prefix, AddressType::P2PKH,
+                                                   bytes_20(&pushes[8])),
+    }))
+}
+
+impl ScriptType {
+    /// Classifies the script, with addresses in EXCH offers on `network`.
+    pub fn classify(script: &Script, network: Network) -> ScriptType {
+        let p2pkh = Script::new(vec![
+            Op::Code(OpDup),
+            Op::Code(OpHash160),
+            Op::Code(OpPubKeyHash),
+            Op::Code(OpEqualVerify),
+            Op::Code(OpCheckSig),
+        ]);
+        let p2sh = Script::new(vec![
+            Op::Code(OpHash160),
+            Op::Code(OpPubKeyHash),
+            Op::Code(OpEqual),
+        ]);
+        let p2pk = Script::new(vec![
+            Op::Code(OpPubkey),
+            Op::Code(OpCheckSig),
+        ]);
+        let multi_sig = Script::new(vec![
+            Op::Code(OpSmallInteger),
+            Op::Code(OpPubKeys),
+            Op::Code(OpSmallInteger),
+            Op::Code(OpCheckMultiSig),
+        ]);
+        if let Some(matches) = script.match_template(&p2pkh) {
+            return ScriptType::P2PKH { pub_key_hash: bytes_20(&matches[0]) };
+        }
+        if let Some(matches) = script.match_template(&p2sh) {
+            return ScriptType::P2SH { script_hash: bytes_20(&matches[0]) };
+        }
+        if let Some(mut matches) = script.match_template(&p2pk) {
+            return ScriptType::P2PK { pub_key: matches.remove(0) };
+        }
+        if let Some(mut matches) = script.match_template(&multi_sig) {
+            let n_keys = matches.pop().unwrap()[0] as usize;
+            let n_required = matches.remove(0)[0] as usize;
+            if n_keys == matches.len() && n_required <= n_keys {
+                return ScriptType::MultiSig { n_required, pub_keys: matches };
+            }
+        }
+        if let Some(pushes) = op_return_pushes(script) {
+            return parse_slp(&pushes)
+                .or_else(|| parse_exch(&pushes, network))
+                .unwrap_or(ScriptType::OpReturn { pushes });
+        }
+        ScriptType::Unknown
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::incomplete_tx::Output;
+
+    fn classify(script_hex: &str) -> ScriptType {
+        let script = Script::from_serialized(&hex::decode(script_hex).unwrap()).unwrap();
+        ScriptType::classify(&script, Network::Mainnet)
+    }
+
+    fn slp_send(token_id: &str, quantities: &[&str]) -> ScriptType {
+        let quantities = quantities.iter()
+            .map(|quantity| format!("{:02x}{}", quantity.len() / 2, quantity))
+            .collect::<String>();
+        classify(&format!("6a04534c500001010453454e44{:02x}{}{}",
+                          token_id.len() / 2, token_id, quantities))
+    }
+
+    #[test]
+    fn classify_p2pkh() {
+        let hash = "1d0f172a0ecb48aee1be1f2687d2963ae33f71a1";
+        match classify(&format!("76a914{}88ac", hash)) {
+            ScriptType::P2PKH { pub_key_hash } => assert_eq!(hex::encode(pub_key_hash), hash),
+            other => panic!("{:?}", other),
+        }
+        // wrong hash size, or anything appended, isn't P2PKH
+        assert!(matches!(classify(&format!("76a915{}ab88ac", hash)), ScriptType::Unknown));
+        assert!(matches!(classify(&format!("76a914{}88ac75", hash)), ScriptType::Unknown));
+    }
+
+    #[test]
+    fn classify_p2sh() {
+        let hash = "748284390f9e263a4b766a75d0633c50426eb875";
+        match classify(&format!("a914{}87", hash)) {
+            ScriptType::P2SH { script_hash } => assert_eq!(hex::encode(script_hash), hash),
+            other => panic!("{:?}", other),
+        }
+        assert!(matches!(classify(&format!("a914{}88", hash)), ScriptType::Unknown));
+    }
+
+    #[test]
+    fn classify_p2pk_and_multisig() {
+        let pub_key = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+        match classify(&format!("21{}ac", pub_key)) {
+            ScriptType::P2PK { pub_key: key } => assert_eq!(hex::encode(key), pub_key),
+            other => panic!("{:?}", other),
+        }
+        match classify(&format!("5121{}21{}52ae", pub_key, pub_key)) {
+            ScriptType::MultiSig { n_required, pub_keys } => {
+                assert_eq!(n_required, 1);
+                assert_eq!(pub_keys.len(), 2);
+            },
+            other => panic!("{:?}", other),
+        }
+        // the key count has to match the keys
+        assert!(matches!(classify(&format!("5121{}53ae", pub_key)), ScriptType::Unknown));
+    }
+
+    #[test]
+    fn classify_slp_send() {
+        let token_id = "ab".repeat(32);
+        match slp_send(&token_id, &["0000000000000000", "00000000000003e8"]) {
+            ScriptType::SLPSend(send) => {
+                assert_eq!(send.token_type, 1);
+                assert_eq!(hex::encode(send.token_id), token_id);
+                assert_eq!(send.output_quantities, vec![0, 1000]);
+            },
+            other => panic!("{:?}", other),
+        }
+        // the output built for sending is classified as what it was built from
+        let output = SLPSendOutput {
+            token_type: 1,
+            token_id: [0x12; 32],
+            output_quantities: vec![5],
+        };
+        match ScriptType::classify(&output.script(), Network::Mainnet) {
+            ScriptType::SLPSend(send) => assert_eq!(send.output_quantities, vec![5]),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn classify_malformed_slp_send() {
+        let token_id = "ab".repeat(32);
+        let malformed = [
+            slp_send(&token_id, &[]),
+            slp_send(&token_id, &["00000000000003"]),
+            slp_send(&token_id[2..], &["00000000000003e8"]),
+        ];
+        for script_type in malformed.iter() {
+            match script_type {
+                ScriptType::SLPMessage { transaction_type, .. } =>
+                    assert_eq!(transaction_type, b"SEND"),
+                other => panic!("{:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn classify_op_return() {
+        match classify("6a0461626364") {
+            ScriptType::OpReturn { pushes } => assert_eq!(pushes, vec![b"abcd".to_vec()]),
+            other => panic!("{:?}", other),
+        }
+        match classify("6a") {
+            ScriptType::OpReturn { pushes } => assert!(pushes.is_empty()),
+            other => panic!("{:?}", other),
+        }
+        match classify("6a4f60") {
+            ScriptType::OpReturn { pushes } => assert_eq!(pushes, vec![vec![0x81], vec![16]]),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn classify_non_standard() {
+        for script_hex in &["", "51", "6a76", "76a988ac", "ac"] {
+            assert!(matches!(classify(script_hex), ScriptType::Unknown), "{}", script_hex);
+        }
+    }
+}
@@ -38,7 +38,13 @@ pub fn read_var_int<R: io::Read>(read: &mut R) -> io::Result<u64> {
 }
 
 pub fn read_var_str<R: io::Read>(read: &mut R) -> io::Result<Vec<u8>> {
-    let mut vec = vec![0; read_var_int(read)? as usize];
-    read.read_exact(&mut vec)?;
+    use std::io::Read;
+    // don't trust the length before the data actually arrived, it might come from a peer
+    let len = read_var_int(read)?;
+    let mut vec = Vec::new();
+    read.take(len).read_to_end(&mut vec)?;
+    if (vec.len() as u64) < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
     Ok(vec)
 }
@@ -4,100 +4,25 @@ use crate::address::{Address, AddressType};
 use crate::hash::hash160;
 use crate::incomplete_tx::{IncompleteTx, Output, Utxo};
 use crate::tx::{tx_hex_to_hash, TxOutpoint};
-use crate::script::{Script, Op, OpCodeType};
-use std::io::{self, Write, Cursor};
-use byteorder::{BigEndian, ReadBytesExt};
+use crate::script_type::ScriptType;
+use crate::backend::{TokenEntry, UtxoEntry, UtxoVerification};
+use crate::tx_store::StoredTrade;
+use crate::tx_watcher::WatchError;
+use std::io::{self, Write};
 use text_io::{read, try_read, try_scan};
-use serde::{Serialize, Deserialize};
 use std::collections::{HashMap, HashSet};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 
-#[derive(Deserialize, Serialize, Debug)]
-pub struct TokenEntry {
-    id: String,
-    timestamp: String,
-    symbol: Option<String>,
-    name: Option<String>,
-    #[serde(alias = "documentUri")]
-    document_uri: Option<String>,
-    #[serde(alias = "documentHash")]
-    document_hash: Option<String>,
-    decimals: u64,
-    #[serde(alias = "initialTokenQty")]
-    initial_token_qty: f64,
-}
-
-#[derive(Deserialize, Serialize, Debug)]
-struct TradeEntryTx {
-    h: String,
-}
-
-#[derive(Deserialize, Serialize, Debug)]
-struct TradeEntryOut {
-    h1: Option<String>,
-    h2: Option<String>,
-    h3: Option<String>,
-    h4: Option<String>,
-    h5: Option<String>,
-    h6: Option<String>,
-    h7: Option<String>,
-    h8: Option<String>,
-    h9: Option<String>,
-}
-
-#[derive(Deserialize, Serialize, Debug)]
-struct SlpTxValidity {
-    txid: String,
-    valid: bool,
-}
-
-#[derive(Deserialize, Serialize, Debug)]
-struct TxDetails {
-    txid: String,
-    vout: Vec<TxDetailsVout>,
-}
-
-#[derive(Deserialize, Serialize, Debug)]
-struct TxDetailsVout {
-    value: String,
-    #[serde(alias = "scriptPubKey")]
-    script_pub_key: TxDetailsScriptPubKey,
-    #[serde(alias = "spentTxId")]
-    spent_tx_id: Option<String>,
-}
-
-#[derive(Deserialize, Serialize, Debug)]
-struct TxDetailsScriptPubKey {
-    hex: String,
-    r#type: Option<String>,
-}
-
-#[derive(Deserialize, Serialize, Debug)]
-pub struct TradeEntry {
-    tx: TradeEntryTx,
-    out: Vec<TradeEntryOut>,
-}
-
-#[derive(Deserialize, Serialize, Debug)]
-pub struct TradesResult {
-    c: Vec<TradeEntry>,
-}
-
-fn fetch_tokens(name: Option<&str>) -> Result<Vec<TokenEntry>, Box<std::error::Error>> {
-    let url = format!("https://rest.bitcoin.com/v2/slp/list/{}", name.unwrap_or(""));
-    if name.and_then(|s| if s == "" {None} else {Some(s)}).is_some() {
-        Ok(reqwest::get(&url)?.json().map(|x| vec![x]).unwrap_or(vec![]))
-    } else {
-        Ok(reqwest::get(&url)?.json()?)
-    }
-}
+/// How long to wait for the tokens to arrive before asking whether to keep waiting.
+const PAYMENT_TIMEOUT: Duration = Duration::from_secs(10 * 60);
 
 fn option_str(s: &Option<String>) -> &str {
     s.as_ref().map(|x| x.as_str()).unwrap_or("<empty>")
 }
 
 pub fn create_trade_interactive(wallet: &Wallet) -> Result<(), Box<std::error::Error>> {
-    let (tx_build, balance) = wallet.init_transaction();
+    let (tx_build, balance) = wallet.init_transaction()?;
     if balance < wallet.dust_amount() {
         println!("Your balance ({}) isn't sufficient to broadcast a transaction. Please fund some \
                   BCH to your wallet's address: {}", balance, wallet.address().cash_addr());
@@ -108,9 +33,14 @@ pub fn create_trade_interactive(wallet: &Wallet) -> Result<(), Box<std::error::E
     let token_str: String = read!("{}\n");
     let token_str = token_str.trim().to_string();
 
-    let mut tokens_found = fetch_tokens(Some(&token_str))?;
+    let is_token_id = token_str.len() == 64 && hex::decode(&token_str).is_ok();
+    let mut tokens_found = if is_token_id {
+        wallet.backend().token_info(std::slice::from_ref(&token_str)).unwrap_or_default()
+    } else {
+        vec![]
+    };
     if tokens_found.len() == 0 {
-        let all_tokens = fetch_tokens(None)?;
+        let all_tokens = wallet.backend().list_tokens()?;
         let mut tokens_found_name = all_tokens.into_iter().filter(|token| {
             token.name.as_ref() == Some(&token_str) || token.symbol.as_ref() == Some(&token_str)
         }).collect::<Vec<_>>();
@@ -207,7 +137,7 @@ pub fn create_trade_interactive(wallet: &Wallet) -> Result<(), Box<std::error::E
 }
 
 fn confirm_trade_interactive(wallet: &Wallet,
-                             mut tx_build: IncompleteTx,
+                             tx_build: IncompleteTx,
                              balance: u64,
                              token: &TokenEntry,
                              sell_amount: u64,
@@ -215,38 +145,29 @@ fn confirm_trade_interactive(wallet: &Wallet,
                              buy_amount: u64) -> Result<(), Box<std::error::Error>> {
     let mut token_id = [0; 32];
     token_id.copy_from_slice(&hex::decode(&token.id)?);
-    let receiving_address = wallet.address().clone();
-    let cancel_address = wallet.address().clone();
-    let output = EnforceOutputsOutput {
-        value: 0,  // ignored for script hash generation
-        enforced_outputs: vec![
-            Box::new(SLPSendOutput {
-                token_type: 1,
-                token_id,
-                output_quantities: vec![0, sell_amount],
-            }),
-            Box::new(P2PKHOutput {
-                value: buy_amount,
-                address: receiving_address.clone(),
-            }),
-        ],
-        cancel_address: cancel_address.clone(),
-        is_cancel: None,
-    };
-    let pkh = hash160(&output.script().to_vec());
-    let addr_slp = Address::from_bytes_prefix(
-        "simpleledger",
-        AddressType::P2SH,
-        pkh.clone(),
-    );
-    let addr_bch = Address::from_bytes_prefix(
-        "bitcoincash",
-        AddressType::P2SH,
-        pkh,
-    );
-    let already_existing = wallet.get_utxos(&addr_bch).into_iter()
+    let receiving_address = wallet.fresh_receive_address();
+    let cancel_address = receiving_address.clone();
+    let output = trade_contract(token_id, sell_amount, buy_amount, &receiving_address,
+                                &cancel_address);
+    let addr_bch = contract_address(wallet, &output);
+    let addr_slp = addr_bch.with_prefix(wallet.network().slp_prefix());
+    let already_existing = wallet.get_utxos(&addr_bch)?.into_iter()
         .map(|utxo| utxo.txid)
         .collect::<HashSet<_>>();
+    // record the contract before showing its address, so its details aren't lost on exit
+    let mut stored_trade = StoredTrade {
+        contract_address: addr_slp.cash_addr().to_string(),
+        token_id: token.id.clone(),
+        sell_amount,
+        buy_amount,
+        receiving_address: receiving_address.cash_addr().to_string(),
+        cancel_address: cancel_address.cash_addr().to_string(),
+        funding_txid: None,
+        listing_txid: None,
+        cancel_txid: None,
+        timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+    };
+    wallet.record_trade(stored_trade.clone());
     println!("--------------------------------------------------");
     crate::display_qr::display(addr_slp.cash_addr().as_bytes());
     println!("Please send EXACTLY {} {} to the following address:",
@@ -260,160 +181,338 @@ fn confirm_trade_interactive(wallet: &Wallet,
 
     println!("Waiting for transaction...");
 
-    let utxo = wallet.wait_for_transaction(&addr_bch, &already_existing);
+    let mut watcher = wallet.watch_address(&addr_bch, already_existing);
+    watcher.set_timeout(Some(PAYMENT_TIMEOUT));
+    watcher.on_seen_in_mempool(|utxo| println!("Received tx: {} (unconfirmed)", utxo.txid));
+    watcher.on_confirmed(|utxo| println!("Received tx: {} (confirmed)", utxo.txid));
+    let utxo = loop {
+        match watcher.run() {
+            Ok(utxo) => break utxo,
+            Err(WatchError::Timeout) => {
+                print!("No payment arrived yet. If you already sent it, it might still be on its \
+                        way. Type \"wait\" (without quotes) to keep waiting: ");
+                io::stdout().flush()?;
+                let answer: String = read!("{}\n");
+                if !answer.trim().eq_ignore_ascii_case("wait") {
+                    println!("Stopped waiting. DO NOT send the tokens to the address above \
+                              anymore, they couldn't be recovered.");
+                    return Ok(());
+                }
+                println!("Waiting for transaction...");
+            },
+            Err(err) => return Err(err.into()),
+        }
+    };
+    stored_trade.funding_txid = Some(utxo.txid.clone());
+    wallet.record_trade(stored_trade.clone());
+
+    list_trade(wallet, tx_build, balance, &utxo, stored_trade)
+}
+
+/// The contract holding the tokens of a trade offer, with value and is_cancel left to set for
+/// spending it.
+fn trade_contract(token_id: [u8; 32],
+                  sell_amount: u64,
+                  buy_amount: u64,
+                  receiving_address: &Address,
+                  cancel_address: &Address) -> EnforceOutputsOutput {
+    EnforceOutputsOutput {
+        value: 0,  // ignored for script hash generation
+        enforced_outputs: vec![
+            Box::new(SLPSendOutput {
+                token_type: 1,
+                token_id,
+                output_quantities: vec![0, sell_amount],
+            }),
+            Box::new(P2PKHOutput {
+                value: buy_amount,
+                address: receiving_address.clone(),
+            }),
+        ],
+        cancel_address: cancel_address.clone(),
+        is_cancel: None,
+    }
+}
 
-    println!("Received tx: {}", utxo.txid);
+fn contract_address(wallet: &Wallet, contract: &EnforceOutputsOutput) -> Address {
+    Address::from_bytes_prefix(
+        wallet.network().bch_prefix(),
+        AddressType::P2SH,
+        hash160(&contract.script().to_vec()),
+    )
+}
 
+/// Broadcasts the transaction announcing the trade whose contract holds the tokens in `utxo`.
+fn list_trade(wallet: &Wallet,
+              mut tx_build: IncompleteTx,
+              balance: u64,
+              utxo: &UtxoEntry,
+              mut stored_trade: StoredTrade) -> Result<(), Box<dyn std::error::Error>> {
+    let network = wallet.network();
     tx_build.add_output(&TradeOfferOutput {
         tx_id: tx_hex_to_hash(&utxo.txid),
         output_idx: utxo.vout,
-        sell_amount,
-        buy_amount,
-        receiving_address: receiving_address.clone(),
-        cancel_address: cancel_address.clone(),
+        sell_amount: stored_trade.sell_amount,
+        buy_amount: stored_trade.buy_amount,
+        receiving_address: Address::from_cash_addr_network(&stored_trade.receiving_address,
+                                                           network)?,
+        cancel_address: Address::from_cash_addr_network(&stored_trade.cancel_address, network)?,
     }.into_output());
     let size_so_far = tx_build.estimate_size();
     let mut send_output = P2PKHOutput {
         value: 0,
-        address: wallet.address().clone(),
+        address: wallet.change_address(),
     };
     let size_output = send_output.script().to_vec().len() as u64;
     let total_spent = size_so_far + size_output + 20;
     if total_spent > balance {
         println!("The broadcast transaction cannot be sent due to insufficient funds");
+        return Ok(());
     }
     send_output.value = balance - total_spent;
     tx_build.add_output(&send_output);
 
     let tx = tx_build.sign();
+    tx_build.verify(&tx)?;
     let result = wallet.send_tx(&tx)?;
     println!("The trade listing transaction ID is: {}", result);
+    stored_trade.listing_txid = Some(result);
+    wallet.record_trade(stored_trade);
 
     Ok(())
 }
 
+/// Continues a trade created by the wallet: lists it if creating it was interrupted after
+/// showing the contract address, or sends its tokens back using the cancel address's key.
+pub fn resume_trade_interactive(wallet: &Wallet) -> Result<(), Box<dyn std::error::Error>> {
+    let trades = match wallet.tx_store() {
+        Some(tx_store) => tx_store.trades().to_vec(),
+        None => {
+            println!("There's no local transaction store, so there are no stored trades.");
+            return Ok(());
+        },
+    };
+    if trades.is_empty() {
+        println!("This wallet didn't create any trades yet.");
+        return Ok(());
+    }
+    println!("Trades created by this wallet:");
+    for (idx, trade) in trades.iter().enumerate() {
+        println!("{:3} | {} selling {} of token {}... for {} sats, {}",
+                 idx, trade.contract_address, trade.sell_amount, &trade.token_id[..8],
+                 trade.buy_amount, trade.status());
+    }
+    print!("Enter the trade number to resume or cancel (0-{}): ", trades.len() - 1);
+    io::stdout().flush()?;
+    let trade_idx_str: String = read!("{}\n");
+    let trade_idx_str = trade_idx_str.trim();
+    if trade_idx_str.is_empty() {
+        return Ok(());
+    }
+    let mut stored_trade = match trade_idx_str.parse::<usize>().ok()
+            .and_then(|trade_idx| trades.get(trade_idx)) {
+        Some(stored_trade) => stored_trade.clone(),
+        None => {
+            println!("Invalid number");
+            println!("Exit.");
+            return Ok(());
+        },
+    };
+    if let Some(cancel_txid) = &stored_trade.cancel_txid {
+        println!("The trade was already cancelled in {}.", cancel_txid);
+        return Ok(());
+    }
+
+    let network = wallet.network();
+    let receiving_address = Address::from_cash_addr_network(&stored_trade.receiving_address,
+                                                            network)?;
+    let cancel_address = Address::from_cash_addr_network(&stored_trade.cancel_address, network)?;
+    let mut token_id = [0; 32];
+    token_id.copy_from_slice(&hex::decode(&stored_trade.token_id)?);
+    let contract = trade_contract(token_id, stored_trade.sell_amount, stored_trade.buy_amount,
+                                  &receiving_address, &cancel_address);
+    let addr_bch = contract_address(wallet, &contract);
+    if addr_bch.bytes() !=
+            Address::from_cash_addr_network(&stored_trade.contract_address, network)?.bytes() {
+        println!("The stored trade doesn't match its contract address. Exit.");
+        return Ok(());
+    }
+    let utxos = wallet.get_utxos(&addr_bch)?;
+    let utxo = match &stored_trade.funding_txid {
+        Some(funding_txid) => utxos.into_iter().find(|utxo| &utxo.txid == funding_txid),
+        None => utxos.into_iter().next(),
+    };
+    let utxo = match utxo {
+        Some(utxo) => utxo,
+        None if stored_trade.funding_txid.is_some() => {
+            println!("The contract's tokens were already spent, the trade was either accepted \
+                      or cancelled.");
+            return Ok(());
+        },
+        None => {
+            println!("No tokens arrived at the contract address {} yet.",
+                     stored_trade.contract_address);
+            return Ok(());
+        },
+    };
+    if stored_trade.funding_txid.is_none() {
+        println!("Received tx: {}", utxo.txid);
+        stored_trade.funding_txid = Some(utxo.txid.clone());
+        wallet.record_trade(stored_trade.clone());
+    }
+
+    match &stored_trade.listing_txid {
+        Some(listing_txid) => {
+            println!("The trade is listed in {}.", listing_txid);
+            print!("Type \"cancel\" (without quotes) to send the tokens back to this wallet: ");
+        },
+        None => print!("The trade isn't listed yet. Type \"list\" (without quotes) to list it, \
+                        or \"cancel\" to send the tokens back to this wallet: "),
+    }
+    io::stdout().flush()?;
+    let answer: String = read!("{}\n");
+    match answer.to_ascii_lowercase().trim() {
+        "list" if stored_trade.listing_txid.is_none() => {
+            let (tx_build, balance) = wallet.init_transaction()?;
+            list_trade(wallet, tx_build, balance, &utxo, stored_trade)
+        },
+        "cancel" => cancel_trade(wallet, contract, token_id, &utxo, stored_trade),
+        _ => Ok(()),
+    }
+}
+
+/// Spends the contract holding the tokens in `utxo` with the cancel address's key. The tokens
+/// go to the wallet's token address rather than the cancel address, whose outputs the wallet
+/// would spend as plain BCH.
+fn cancel_trade(wallet: &Wallet,
+                contract: EnforceOutputsOutput,
+                token_id: [u8; 32],
+                utxo: &UtxoEntry,
+                mut stored_trade: StoredTrade) -> Result<(), Box<dyn std::error::Error>> {
+    let key = match wallet.secret_key_for(&contract.cancel_address) {
+        Some(key) => key,
+        None => {
+            println!("The cancel address {} doesn't belong to this wallet.",
+                     contract.cancel_address.cash_addr());
+            return Ok(());
+        },
+    };
+    let (mut tx_build, balance) = wallet.init_transaction()?;
+    let output_slp = SLPSendOutput {
+        token_type: 1,
+        token_id,
+        output_quantities: vec![stored_trade.sell_amount],
+    };
+    let output_tokens = P2PKHOutput {
+        value: wallet.dust_amount(),
+        address: wallet.slp_address(),
+    };
+    let mut output_back_to_wallet = P2PKHOutput {
+        value: 0,  // for generating tx size
+        address: wallet.change_address(),
+    };
+    tx_build.add_utxo(Utxo {
+        outpoint: TxOutpoint {
+            tx_hash: tx_hex_to_hash(&utxo.txid),
+            output_idx: utxo.vout,
+        },
+        sequence: 0xffff_ffff,
+        output: Box::new(P2SHOutput {
+            output: EnforceOutputsOutput {
+                value: utxo.satoshis,
+                is_cancel: Some(true),
+                ..contract
+            },
+        }),
+        key,
+    });
+    tx_build.add_output(&output_slp);
+    tx_build.add_output(&output_tokens);
+    let back_to_wallet_idx = tx_build.add_output(&output_back_to_wallet);
+
+    let estimated_size = {
+        let mut tx_ser = Vec::new();
+        tx_build.sign().write_to_stream(&mut tx_ser)?;
+        tx_ser.len() as u64
+    };
+    let fee = estimated_size + 21;
+    let total_spent = output_tokens.value() + fee;
+    let available = balance + utxo.satoshis;
+    if total_spent + wallet.dust_amount() > available {
+        println!("Including fees, cancelling the trade spends {} sats, but your wallet's balance \
+                  is only {} sats. Please fund some BCH to your wallet's address: {}",
+                 total_spent, balance, wallet.address().cash_addr());
+        return Ok(());
+    }
+    output_back_to_wallet.value = available - total_spent;
+    tx_build.replace_output(back_to_wallet_idx, &output_back_to_wallet);
+    let tx = tx_build.sign();
+    tx_build.verify(&tx)?;
+
+    println!("The tokens will be sent to {}.", output_tokens.address.cash_addr());
+    print!("Should the trade be cancelled now? Type \"yes\" (without quotes): ");
+    io::stdout().flush()?;
+    let confirm_send: String = read!("{}\n");
+    if confirm_send.to_ascii_lowercase().trim() != "yes" {
+        return Ok(());
+    }
+    let result = wallet.send_tx(&tx)?;
+    println!("Cancelled the trade. Transaction ID is: {}", result);
+    stored_trade.cancel_txid = Some(result);
+    wallet.record_trade(stored_trade);
+    Ok(())
+}
+
 pub fn accept_trades_interactive(wallet: &Wallet) -> Result<(), Box<std::error::Error>> {
     println!("Loading trades... (Note: this might take a few seconds and a trade might need to be \
               confirmed to show up due to bitdb)");
 
-    let trades_result: TradesResult = reqwest::get(
-        "https://bitdb.bitcoin.com/q/ewogICJ2IjogMywKICAicSI6IHsKICAgICJmaW5kIjogewogICAgICAib3V0Ln\
-         MxIjogIkVYQ0giLAogICAgICAib3V0LmgyIjogIjAxIiwKICAgICAgIm91dC5zMyI6ICJTRUxMIgogICAgfQogIH0K\
-         fQ=="
-    )?.json()?;
-
-    let mut trades = Vec::new();
-    trades_result.c.iter().for_each(|tx| tx.out.iter().for_each(|out| {
-        (|| -> Option<()> {
-            if out.h1.as_ref() != Some(&hex::encode(b"EXCH")) {
-                return None;
-            }
-            trades.push(TradeOfferOutput {
-                tx_id: {
-                    let mut tx_id = [0; 32];
-                    tx_id.copy_from_slice(&hex::decode(out.h4.as_ref()?).unwrap());
-                    tx_id
-                },
-                output_idx: Cursor::new(hex::decode(out.h5.as_ref()?).unwrap()).read_u32::<BigEndian>().unwrap(),
-                sell_amount: Cursor::new(hex::decode(out.h6.as_ref()?).unwrap()).read_u64::<BigEndian>().unwrap(),
-                buy_amount: Cursor::new(hex::decode(out.h7.as_ref()?).unwrap()).read_u64::<BigEndian>().unwrap(),
-                receiving_address: Address::from_bytes(AddressType::P2PKH, {
-                    let mut addr = [0; 20];
-                    addr.copy_from_slice(&hex::decode(out.h8.as_ref()?).unwrap());
-                    addr
-                }),
-                cancel_address: Address::from_bytes(AddressType::P2PKH, {
-                    let mut addr = [0; 20];
-                    addr.copy_from_slice(&hex::decode(out.h9.as_ref()?).unwrap());
-                    addr
-                }),
-            });
-            None
-        })();
-    }));
+    let network = wallet.network();
+    let backend = wallet.backend();
+    let trades = backend.find_trade_offers()?;
 
     let tx_hashes = trades.iter().map(|trade| {
         hex::encode(&trade.tx_id.iter().cloned().rev().collect::<Vec<_>>())
     }).collect::<Vec<_>>();
 
-    let trades_validity = tx_hashes.chunks(20).flat_map(|chunk| {
-        reqwest::Client::new()
-            .post("https://rest.bitcoin.com/v2/slp/validateTxid")
-            .json(&vec![("txids", chunk)].into_iter().collect::<HashMap<_, _>>())
-            .send().unwrap()
-            .json::<Vec<SlpTxValidity>>().unwrap()
-    }).collect::<Vec<_>>();
-
-    let valid_txs = trades_validity.into_iter()
-        .filter(|validity| validity.valid)
-        .map(|validity| validity.txid)
+    let valid_txs = backend.validate_slp_txids(&tx_hashes)?
+        .into_iter()
         .collect::<HashSet<_>>();
 
-    let tx_details = valid_txs
-        .iter()
-        .collect::<Vec<_>>()
-        .chunks(20)
-        .flat_map(|chunk| {
-            reqwest::Client::new()
-                .post("https://rest.bitcoin.com/v2/transaction/details")
-                .json(&vec![("txids", chunk)].into_iter().collect::<HashMap<_, _>>())
-                .send().unwrap()
-                .json::<Vec<TxDetails>>().unwrap()
-        })
+    let txs = backend.get_txs(&valid_txs.iter().cloned().collect::<Vec<_>>())?
+        .into_iter()
+        .filter(|tx| valid_txs.contains(&tx.txid()))
         .collect::<Vec<_>>();
 
-    let token_ids = tx_details.into_iter().filter_map(|tx| {
+    let token_ids = txs.into_iter().filter_map(|tx| {
+        let tx_id = tx.txid();
         let mut p2sh_amount = None;
-        let mut tx_id = None;
         let mut token_id = None;
-        for (i, out) in tx.vout.into_iter().enumerate() {
-            if option_str(&out.script_pub_key.r#type) == "scripthash" && i == 1 { // enforced position
-                p2sh_amount = Some((out.value.parse::<f64>().unwrap() * 100_000_000.0) as u64);
-                if out.spent_tx_id.is_some() {
-                    return None;
-                }
-                break;
-            }
-            if option_str(&out.script_pub_key.r#type) == "pubkeyhash" {
-                continue;
-            }
-            let script = Script::from_serialized(
-                &hex::decode(&out.script_pub_key.hex).unwrap()
-            );
-
-            if script.ops().len() < 7 || // op_return + SLP\0 + version + SEND + token_id + v1 + v2
-                    script.ops()[0] != Op::Code(OpCodeType::OpReturn) ||
-                    script.ops()[1] != Op::Push(b"SLP\0".to_vec()) ||
-                    script.ops()[2] != Op::Push(vec![0x01]) ||
-                    script.ops()[3] != Op::Push(b"SEND".to_vec()) {
-                continue;
-            }
-
-            if let Op::Push(vec) = &script.ops()[4] {
-                tx_id = Some(tx.txid.clone());
-                token_id = Some(hex::encode(vec));
+        for (i, out) in tx.outputs().iter().enumerate() {
+            match ScriptType::classify(&out.script, network) {
+                ScriptType::P2SH { .. } if i == 1 => { // enforced position
+                    if !backend.is_output_unspent(&tx_id, i as u32).ok()? {
+                        return None;
+                    }
+                    p2sh_amount = Some(out.value);
+                    break;
+                },
+                // token_type 1, at least two quantities (sent to the contract at index 1)
+                ScriptType::SLPSend(slp_send)
+                        if slp_send.token_type == 1 && slp_send.output_quantities.len() >= 2 => {
+                    token_id = Some(hex::encode(slp_send.token_id));
+                },
+                _ => {},
             }
         }
-        Some((tx_id?, (token_id?, p2sh_amount?)))
+        Some((tx_id, (token_id?, p2sh_amount?)))
     }).collect::<HashMap<_, _>>();
 
     let token_id_set = token_ids.values().map(|(x, _)| x).collect::<HashSet<_>>();
-    let token_details = token_id_set
+    let token_id_list = token_id_set.into_iter().cloned().collect::<Vec<_>>();
+    let token_details = backend.token_info(&token_id_list)?
         .into_iter()
-        .collect::<Vec<_>>()
-        .chunks(20)
-        .flat_map(|chunk| {
-            reqwest::Client::new()
-                .post("https://rest.bitcoin.com/v2/slp/list")
-                .json(&vec![(
-                    "tokenIds",
-                    chunk,
-                )].into_iter().collect::<HashMap<_, _>>())
-                .send().unwrap()
-                .json::<Vec<TokenEntry>>().unwrap()
-                .into_iter()
-                .map(|token_details| (token_details.id.clone(), token_details))
-        })
+        .map(|token_details| (token_details.id.clone(), token_details))
         .collect::<HashMap<_, _>>();
 
     let valid_trades = trades.into_iter()
@@ -429,7 +528,7 @@ pub fn accept_trades_interactive(wallet: &Wallet) -> Result<(), Box<std::error::
         })
         .collect::<Vec<_>>();
 
-    let (mut tx_build, balance) = wallet.init_transaction();
+    let (mut tx_build, balance) = wallet.init_transaction()?;
     println!("Your balance: {} sats", balance);
     println!("Current trade offers:");
     println!("{:^3} | {:^15} | {:^14} | {:^10} | {:^11} |",
@@ -496,6 +595,17 @@ pub fn accept_trades_interactive(wallet: &Wallet) -> Result<(), Box<std::error::
     println!("{:20}{}", "Token name:", option_str(&trade_token_details.name));
     println!("{:20}{}", "Token timestamp:", trade_token_details.timestamp);
     println!("{:20}{}", "Token document URI:", option_str(&trade_token_details.document_uri));
+    match wallet.verify_tx(tx_id)? {
+        (UtxoVerification::Verified, Some(height)) => {
+            println!("{:20}verified in block {}", "Offer transaction:", height);
+        },
+        (UtxoVerification::Invalid, _) => {
+            println!("The offer transaction's merkle proof is invalid, it might not exist.");
+            println!("Exit.");
+            return Ok(());
+        },
+        (verification, _) => println!("{:20}{:?}", "Offer transaction:", verification),
+    }
     println!("------------------------------------");
     if balance < trade.buy_amount {
         println!(
@@ -515,15 +625,16 @@ pub fn accept_trades_interactive(wallet: &Wallet) -> Result<(), Box<std::error::
             println!("Bye!");
             return Ok(());
         }
-        let addr = match Address::from_cash_addr(receiving_addr_str.to_string()) {
+        let addr = match Address::from_cash_addr_network(receiving_addr_str, network) {
             Ok(addr) => addr,
             Err(err) => {
                 println!("Please enter a valid address: {:?}", err);
                 continue;
             }
         };
-        if addr.prefix() != "simpleledger" {
-            println!("Please enter a simple ledger address, it starts with 'simpleledger'.");
+        if !addr.is_slp() || addr.network() != Some(network) {
+            println!("Please enter a simple ledger address, it starts with '{}'.",
+                     network.slp_prefix());
             continue;
         }
         break addr;
@@ -555,7 +666,7 @@ pub fn accept_trades_interactive(wallet: &Wallet) -> Result<(), Box<std::error::
     };
     let mut output_back_to_wallet = P2PKHOutput {
         value: 0,  // for generating tx size
-        address: wallet.address().clone(),
+        address: wallet.change_address(),
     };
 
     tx_build.add_utxo(Utxo {
@@ -596,6 +707,7 @@ pub fn accept_trades_interactive(wallet: &Wallet) -> Result<(), Box<std::error::
     output_back_to_wallet.value = balance - total_spent;
     tx_build.replace_output(back_to_wallet_idx, &output_back_to_wallet);
     let tx = tx_build.sign();
+    tx_build.verify(&tx)?;
 
     let mut tx_ser = Vec::new();
     tx.write_to_stream(&mut tx_ser)?;
@@ -1,10 +1,19 @@
-use crate::serialize::write_var_int;
+use crate::serialize::{write_var_int, read_var_int, read_var_str};
 use crate::script::Script;
+use crate::hash::double_sha256;
+use crate::incomplete_tx::PreImage;
 
 use std::io;
-use byteorder::{LittleEndian, WriteBytesExt};
+use std::io::Write;
+use byteorder::{LittleEndian, WriteBytesExt, ReadBytesExt};
 
 
+pub const SIGHASH_ALL: u32 = 0x01;
+pub const SIGHASH_NONE: u32 = 0x02;
+pub const SIGHASH_SINGLE: u32 = 0x03;
+pub const SIGHASH_FORKID: u32 = 0x40;
+pub const SIGHASH_ANYONECANPAY: u32 = 0x80;
+
 #[derive(Clone, Debug)]
 pub struct TxOutpoint {
     pub tx_hash: [u8; 32],
@@ -38,6 +47,10 @@ pub fn tx_hex_to_hash(s: &str) -> [u8; 32] {
     tx_hash
 }
 
+pub fn tx_hash_to_hex(tx_hash: &[u8; 32]) -> String {
+    hex::encode(tx_hash.iter().rev().cloned().collect::<Vec<_>>())
+}
+
 impl TxInput {
     pub fn new(outpoint: TxOutpoint,
                script: Script,
@@ -54,6 +67,19 @@ impl TxInput {
         write.write_u32::<LittleEndian>(self.sequence)?;
         Ok(())
     }
+
+    pub fn from_stream<R: io::Read>(read: &mut R) -> io::Result<Self> {
+        let mut tx_hash = [0; 32];
+        read.read_exact(&mut tx_hash)?;
+        let output_idx = read.read_u32::<LittleEndian>()?;
+        let script = Script::from_serialized_lenient(&read_var_str(read)?);
+        let sequence = read.read_u32::<LittleEndian>()?;
+        Ok(TxInput {
+            outpoint: TxOutpoint { tx_hash, output_idx },
+            script,
+            sequence,
+        })
+    }
 }
 
 impl TxOutput {
@@ -69,6 +95,12 @@ impl TxOutput {
         write.write(&script)?;
         Ok(())
     }
+
+    pub fn from_stream<R: io::Read>(read: &mut R) -> io::Result<Self> {
+        let value = read.read_u64::<LittleEndian>()?;
+        let script = Script::from_serialized_lenient(&read_var_str(read)?);
+        Ok(TxOutput { value, script })
+    }
 }
 
 impl Tx {
@@ -93,6 +125,98 @@ impl Tx {
         Ok(())
     }
 
+    pub fn from_stream<R: io::Read>(read: &mut R) -> io::Result<Self> {
+        let version = read.read_i32::<LittleEndian>()?;
+        let n_inputs = read_var_int(read)?;
+        let mut inputs = Vec::new();
+        for _ in 0..n_inputs {
+            inputs.push(TxInput::from_stream(read)?);
+        }
+        let n_outputs = read_var_int(read)?;
+        let mut outputs = Vec::new();
+        for _ in 0..n_outputs {
+            outputs.push(TxOutput::from_stream(read)?);
+        }
+        let lock_time = read.read_u32::<LittleEndian>()?;
+        Ok(Tx { version, inputs, outputs, lock_time })
+    }
+
+    pub fn from_hex(tx_hex: &str) -> io::Result<Self> {
+        let tx_ser = hex::decode(tx_hex)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        Self::from_stream(&mut io::Cursor::new(tx_ser))
+    }
+
+    pub fn hash(&self) -> [u8; 32] {
+        let mut tx_ser = Vec::new();
+        self.write_to_stream(&mut tx_ser).unwrap();
+        double_sha256(&tx_ser)
+    }
+
+    pub fn txid(&self) -> String {
+        tx_hash_to_hex(&self.hash())
+    }
+
+    /// Builds the BIP143 pre-image of the given input, which is hashed and signed.
+    pub fn pre_image(&self,
+                     input_idx: usize,
+                     script_code: &Script,
+                     value: u64,
+                     sighash_type: u32) -> PreImage {
+        let input = &self.inputs[input_idx];
+        let base_type = sighash_type & 0x1f;
+        let is_anyone_can_pay = sighash_type & SIGHASH_ANYONECANPAY != 0;
+        let mut hash_prevouts = [0u8; 32];
+        let mut hash_sequence = [0u8; 32];
+        let mut hash_outputs = [0u8; 32];
+        if !is_anyone_can_pay {
+            let mut outpoints_serialized = Vec::new();
+            for input in self.inputs.iter() {
+                outpoints_serialized.write_all(&input.outpoint.tx_hash).unwrap();
+                outpoints_serialized.write_u32::<LittleEndian>(input.outpoint.output_idx).unwrap();
+            }
+            hash_prevouts = double_sha256(&outpoints_serialized);
+        }
+        if !is_anyone_can_pay && base_type != SIGHASH_SINGLE && base_type != SIGHASH_NONE {
+            let mut sequence_serialized = Vec::new();
+            for input in self.inputs.iter() {
+                sequence_serialized.write_u32::<LittleEndian>(input.sequence).unwrap();
+            }
+            hash_sequence = double_sha256(&sequence_serialized);
+        }
+        if base_type != SIGHASH_SINGLE && base_type != SIGHASH_NONE {
+            let mut outputs_serialized = Vec::new();
+            for output in self.outputs.iter() {
+                output.write_to_stream(&mut outputs_serialized).unwrap();
+            }
+            hash_outputs = double_sha256(&outputs_serialized);
+        } else if base_type == SIGHASH_SINGLE && input_idx < self.outputs.len() {
+            let mut output_serialized = Vec::new();
+            self.outputs[input_idx].write_to_stream(&mut output_serialized).unwrap();
+            hash_outputs = double_sha256(&output_serialized);
+        }
+        PreImage {
+            version: self.version,
+            hash_prevouts,
+            hash_sequence,
+            outpoint: input.outpoint.clone(),
+            script_code: script_code.clone(),
+            value,
+            sequence: input.sequence,
+            hash_outputs,
+            lock_time: self.lock_time,
+            sighash_type,
+        }
+    }
+
+    pub fn version(&self) -> i32 {
+        self.version
+    }
+
+    pub fn lock_time(&self) -> u32 {
+        self.lock_time
+    }
+
     pub fn inputs(&self) -> &[TxInput] {
         &self.inputs
     }
@@ -101,3 +225,85 @@ impl Tx {
         &self.outputs
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The first transaction between two addresses, in block 170.
+    const BLOCK_170_TX: &str = "\
+        0100000001c997a5e56e104102fa209c6a852dd90660a20b2d9c352423edce25857fcd3704000000004847\
+        304402204e45e16932b8af514961a1d3a1a25fdf3f4f7732e9d624c6c61548ab5fb8cd410220181522ec8e\
+        ca07de4860a4acdd12909d831cc56cbbac4622082221a8768d1d0901ffffffff0200ca9a3b000000004341\
+        04ae1a62fe09c5f51b13905f07f06b99a2f7159b2225f374cd378d71302fa28414e7aab37397f554a7df5f\
+        142c21c1b7303b8a0626f1baded5c72a704f7e6cd84cac00286bee0000000043410411db93e1dcdb8a016b\
+        49840f8c53bc1eb68a382e97b1482ecad7b148a6909a5cb2e0eaddfb84ccf9744464f82e160bfa9b8b64f9\
+        d4c03f999b8643f656b412a3ac00000000";
+    /// The coinbase of the genesis block.
+    const GENESIS_COINBASE: &str = "\
+        01000000010000000000000000000000000000000000000000000000000000000000000000ffffffff4d04\
+        ffff001d0104455468652054696d65732030332f4a616e2f32303039204368616e63656c6c6f72206f6e20\
+        6272696e6b206f66207365636f6e64206261696c6f757420666f722062616e6b73ffffffff0100f2052a01\
+        000000434104678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4c\
+        ef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5fac00000000";
+
+    fn serialize(tx: &Tx) -> String {
+        let mut tx_ser = Vec::new();
+        tx.write_to_stream(&mut tx_ser).unwrap();
+        hex::encode(tx_ser)
+    }
+
+    #[test]
+    fn mainnet_tx_round_trip() {
+        let tx = Tx::from_hex(BLOCK_170_TX).unwrap();
+        assert_eq!(tx.txid(), "f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16");
+        assert_eq!(tx.version(), 1);
+        assert_eq!(tx.lock_time(), 0);
+        assert_eq!(tx.inputs().len(), 1);
+        assert_eq!(tx_hash_to_hex(&tx.inputs()[0].outpoint.tx_hash),
+                   "0437cd7f8525ceed2324359c2d0ba26006d92d856a9c20fa0241106ee5a597c9");
+        assert_eq!(tx.inputs()[0].outpoint.output_idx, 0);
+        assert_eq!(tx.inputs()[0].sequence, 0xffff_ffff);
+        let values = tx.outputs().iter().map(|output| output.value).collect::<Vec<_>>();
+        assert_eq!(values, vec![1_000_000_000, 4_000_000_000]);
+        assert_eq!(serialize(&tx), BLOCK_170_TX);
+    }
+
+    #[test]
+    fn coinbase_round_trip() {
+        let tx = Tx::from_hex(GENESIS_COINBASE).unwrap();
+        assert_eq!(tx.txid(), "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b");
+        assert_eq!(tx.inputs()[0].outpoint.tx_hash, [0; 32]);
+        assert_eq!(tx.outputs()[0].value, 5_000_000_000);
+        assert_eq!(serialize(&tx), GENESIS_COINBASE);
+    }
+
+    #[test]
+    fn rejects_truncated_tx() {
+        assert!(Tx::from_hex(&BLOCK_170_TX[..BLOCK_170_TX.len() - 2]).is_err());
+    }
+
+    #[test]
+    fn bip143_pre_image() {
+        // native P2WPKH example of BIP143, whose digest algorithm BCH uses with SIGHASH_FORKID
+        let tx = Tx::from_hex("\
+            0100000002fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f0000000000\
+            eeffffffef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a0100000000ff\
+            ffffff02202cb206000000001976a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac9093510d\
+            000000001976a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac11000000").unwrap();
+        let script_code = Script::from_serialized(
+            &hex::decode("76a9141d0f172a0ecb48aee1be1f2687d2963ae33f71a188ac").unwrap(),
+        ).unwrap();
+        let pre_image = tx.pre_image(1, &script_code, 600_000_000, SIGHASH_ALL);
+        assert_eq!(hex::encode(pre_image.hash_prevouts),
+                   "96b827c8483d4e9b96712b6713a7b68d6e8003a781feba36c31143470b4efd37");
+        assert_eq!(hex::encode(pre_image.hash_sequence),
+                   "52b0a642eea2fb7ae638c36f6252b6750293dbe574a806984b8e4d8548339a3b");
+        assert_eq!(hex::encode(pre_image.hash_outputs),
+                   "863ef3e1a92afbfdb97f31ad0fc7683ee943e9abcf2501590ff8f6551f47e5e5");
+        let mut pre_image_ser = Vec::new();
+        pre_image.write_to_stream(&mut pre_image_ser).unwrap();
+        assert_eq!(hex::encode(double_sha256(&pre_image_ser)),
+                   "c37af31116d1b27caf68aae9e3ac82f1477929014d5b917657d0eb49478cb670");
+    }
+}
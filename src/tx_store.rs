@@ -0,0 +1,376 @@
+use crate::address::Address;
+use crate::backend::UtxoEntry;
+use crate::hash::single_sha256;
+use crate::network::Network;
+use crate::tx::{Tx, tx_hash_to_hex};
+use crate::wallet_file::write_atomic;
+use serde::{Serialize, Deserialize};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+
+/// Outputs spent by our transactions stay reserved for this long if the backend keeps listing
+/// them as unspent, in case the transaction got dropped.
+const PENDING_SPEND_EXPIRY: u64 = 24 * 60 * 60;
+
+#[derive(Debug)]
+pub enum TxStoreError {
+    IoError(io::Error),
+    JsonError(serde_json::Error),
+}
+
+impl From<io::Error> for TxStoreError {
+    fn from(err: io::Error) -> Self {
+        TxStoreError::IoError(err)
+    }
+}
+
+impl From<serde_json::Error> for TxStoreError {
+    fn from(err: serde_json::Error) -> Self {
+        TxStoreError::JsonError(err)
+    }
+}
+
+impl std::fmt::Display for TxStoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for TxStoreError {
+
+}
+
+/// An output paying to one of the wallet's addresses.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct StoredOutput {
+    pub txid: String,
+    pub vout: u32,
+    /// The address in CashAddr format.
+    pub address: String,
+    pub satoshis: u64,
+    /// Txid of our transaction spending the output, if we sent one.
+    pub spent_by: Option<String>,
+    /// Whether the backend stopped listing the output as unspent.
+    pub is_spent: bool,
+}
+
+/// A transaction broadcast by the wallet.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct StoredTx {
+    pub txid: String,
+    pub tx_hex: String,
+    /// UNIX timestamp of the broadcast.
+    pub timestamp: u64,
+}
+
+/// A trade offer contract created by the wallet, from showing its address on.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct StoredTrade {
+    /// P2SH address of the contract in simpleledger format.
+    pub contract_address: String,
+    pub token_id: String,
+    pub sell_amount: u64,
+    pub buy_amount: u64,
+    pub receiving_address: String,
+    pub cancel_address: String,
+    /// Transaction sending the tokens to the contract, once it arrived.
+    pub funding_txid: Option<String>,
+    /// Transaction announcing the offer, once it's broadcast.
+    pub listing_txid: Option<String>,
+    /// Transaction sending the tokens back, if the trade was cancelled.
+    pub cancel_txid: Option<String>,
+    pub timestamp: u64,
+}
+
+impl StoredTrade {
+    /// How far the trade got, e.g. "listed in <txid>".
+    pub fn status(&self) -> String {
+        match (&self.funding_txid, &self.listing_txid, &self.cancel_txid) {
+            (_, _, Some(cancel_txid)) => format!("cancelled in {}", cancel_txid),
+            (_, Some(listing_txid), None) => format!("listed in {}", listing_txid),
+            (Some(funding_txid), None, None) => format!("funded by {}, not listed", funding_txid),
+            (None, None, None) => "waiting for tokens".to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Default, Debug)]
+struct StoreData {
+    outputs: Vec<StoredOutput>,
+    transactions: Vec<StoredTx>,
+    trades: Vec<StoredTrade>,
+    /// UNIX timestamp of the last UTXO update from the backend.
+    last_update: Option<u64>,
+}
+
+/// Local record of a wallet's outputs, transactions and trade contracts, kept as JSON. It
+/// remembers outputs spent by unconfirmed transactions and allows read-only views offline.
+pub struct TxStore {
+    path: PathBuf,
+    data: StoreData,
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+impl TxStore {
+    /// Opens the store at `path`, or creates an empty one if the file doesn't exist.
+    pub fn open(path: PathBuf) -> Result<Self, TxStoreError> {
+        let data = match std::fs::read(&path) {
+            Ok(file_bytes) => serde_json::from_slice(&file_bytes)?,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => StoreData::default(),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(TxStore { path, data })
+    }
+
+    pub fn save(&self) -> Result<(), TxStoreError> {
+        write_atomic(&self.path, &serde_json::to_vec_pretty(&self.data)?)?;
+        Ok(())
+    }
+
+    pub fn outputs(&self) -> &[StoredOutput] {
+        &self.data.outputs
+    }
+
+    pub fn transactions(&self) -> &[StoredTx] {
+        &self.data.transactions
+    }
+
+    pub fn trades(&self) -> &[StoredTrade] {
+        &self.data.trades
+    }
+
+    /// UNIX timestamp of the last UTXO update, None if there never was one.
+    pub fn last_update(&self) -> Option<u64> {
+        self.data.last_update
+    }
+
+    /// Updates the outputs of the addresses from the backend's UTXOs, in the same order.
+    /// Outputs the backend doesn't list anymore are marked as spent.
+    pub fn update_utxos(&mut self, addresses: &[Address], utxos: &[Vec<UtxoEntry>]) {
+        for (address, utxos) in addresses.iter().zip(utxos) {
+            let cash_addr = address.cash_addr();
+            let address_outputs = self.data.outputs.iter_mut()
+                .filter(|output| output.address == cash_addr);
+            for output in address_outputs {
+                output.is_spent = !utxos.iter()
+                    .any(|utxo| utxo.txid == output.txid && utxo.vout == output.vout);
+            }
+            for utxo in utxos {
+                let is_known = self.data.outputs.iter()
+                    .any(|output| output.txid == utxo.txid && output.vout == utxo.vout);
+                if !is_known {
+                    self.data.outputs.push(StoredOutput {
+                        txid: utxo.txid.clone(),
+                        vout: utxo.vout,
+                        address: cash_addr.to_string(),
+                        satoshis: utxo.satoshis,
+                        spent_by: None,
+                        is_spent: false,
+                    });
+                }
+            }
+        }
+        self.data.last_update = Some(now());
+    }
+
+    /// Records a transaction we broadcast, reserving the outputs it spends.
+    pub fn record_tx(&mut self, tx: &Tx) {
+        let txid = tx.txid();
+        for input in tx.inputs() {
+            let spent_txid = tx_hash_to_hex(&input.outpoint.tx_hash);
+            if let Some(output) = self.data.outputs.iter_mut().find(|output| {
+                output.txid == spent_txid && output.vout == input.outpoint.output_idx
+            }) {
+                output.spent_by = Some(txid.clone());
+            }
+        }
+        if self.data.transactions.iter().any(|stored_tx| stored_tx.txid == txid) {
+            return;
+        }
+        let mut tx_ser = Vec::new();
+        tx.write_to_stream(&mut tx_ser).unwrap();
+        self.data.transactions.push(StoredTx {
+            txid,
+            tx_hex: hex::encode(&tx_ser),
+            timestamp: now(),
+        });
+    }
+
+    /// Whether the output is spent by one of our transactions which the backend doesn't know
+    /// about yet, e.g. because it was just broadcast.
+    pub fn is_pending_spend(&self, txid: &str, vout: u32) -> bool {
+        let output = match self.data.outputs.iter()
+            .find(|output| output.txid == txid && output.vout == vout) {
+            Some(output) => output,
+            None => return false,
+        };
+        let spent_by = match &output.spent_by {
+            Some(spent_by) if !output.is_spent => spent_by,
+            _ => return false,
+        };
+        self.data.transactions.iter()
+            .find(|stored_tx| &stored_tx.txid == spent_by)
+            .map(|stored_tx| stored_tx.timestamp + PENDING_SPEND_EXPIRY > now())
+            .unwrap_or(false)
+    }
+
+    /// Sum of the unspent outputs as of the last update, minus pending spends.
+    pub fn cached_balance(&self) -> u64 {
+        self.data.outputs.iter()
+            .filter(|output| !output.is_spent && !self.is_pending_spend(&output.txid, output.vout))
+            .map(|output| output.satoshis)
+            .sum()
+    }
+
+    /// Adds the trade, or replaces the one with the same contract address.
+    pub fn record_trade(&mut self, trade: StoredTrade) {
+        match self.data.trades.iter_mut()
+            .find(|stored_trade| stored_trade.contract_address == trade.contract_address) {
+            Some(stored_trade) => *stored_trade = trade,
+            None => self.data.trades.push(trade),
+        }
+    }
+}
+
+/// Store of the wallet identified by `wallet_id` in `dir`, e.g.
+/// ~/.slpagora/txs-mainnet-1a2b3c4d.json.
+pub fn tx_store_path(dir: &Path, network: Network, wallet_id: &str) -> PathBuf {
+    let id_hash = hex::encode(&single_sha256(wallet_id.as_bytes())[..4]);
+    dir.join(format!("txs-{}-{}.json", network.name(), id_hash))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::AddressType;
+    use crate::backend::UtxoVerification;
+    use crate::script::Script;
+    use crate::tx::{TxInput, TxOutpoint, TxOutput};
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("slpagora-test-{}-{}.json", std::process::id(), name))
+    }
+
+    fn utxo(tx_byte: u8, vout: u32, satoshis: u64) -> UtxoEntry {
+        UtxoEntry {
+            txid: hex::encode([tx_byte; 32]),
+            vout,
+            amount: satoshis as f64 / 100_000_000.0,
+            satoshis,
+            confirmation_height: None,
+            verification: UtxoVerification::Unverified,
+        }
+    }
+
+    fn spending_tx(tx_byte: u8, vout: u32) -> Tx {
+        let outpoint = TxOutpoint { tx_hash: [tx_byte; 32], output_idx: vout };
+        Tx::new(
+            1,
+            vec![TxInput::new(outpoint, Script::new(vec![]), 0xffff_ffff)],
+            vec![TxOutput::new(5_000, Script::new(vec![]))],
+            0,
+        )
+    }
+
+    #[test]
+    fn update_utxos() {
+        let addresses = [
+            Address::from_bytes(AddressType::P2PKH, [1; 20]),
+            Address::from_bytes(AddressType::P2PKH, [2; 20]),
+        ];
+        let mut store = TxStore::open(temp_path("update-utxos")).unwrap();
+        assert_eq!(store.last_update(), None);
+        store.update_utxos(&addresses, &[
+            vec![utxo(1, 0, 10_000), utxo(1, 1, 20_000)],
+            vec![utxo(2, 0, 30_000)],
+        ]);
+        assert!(store.last_update().is_some());
+        assert_eq!(store.outputs().len(), 3);
+        assert_eq!(store.outputs()[2].address, addresses[1].cash_addr());
+        assert!(store.outputs().iter().all(|output| !output.is_spent));
+        assert_eq!(store.cached_balance(), 60_000);
+
+        store.update_utxos(&addresses, &[vec![utxo(1, 1, 20_000)], vec![utxo(2, 0, 30_000)]]);
+        assert_eq!(store.outputs().len(), 3);
+        let spent = store.outputs().iter()
+            .map(|output| output.is_spent)
+            .collect::<Vec<_>>();
+        assert_eq!(spent, vec![true, false, false]);
+        assert_eq!(store.cached_balance(), 50_000);
+    }
+
+    #[test]
+    fn record_tx() {
+        let addresses = [Address::from_bytes(AddressType::P2PKH, [1; 20])];
+        let mut store = TxStore::open(temp_path("record-tx")).unwrap();
+        store.update_utxos(&addresses, &[vec![utxo(1, 0, 10_000), utxo(1, 1, 20_000)]]);
+        let tx = spending_tx(1, 1);
+        store.record_tx(&tx);
+        store.record_tx(&tx);
+        assert_eq!(store.transactions().len(), 1);
+        let mut tx_ser = Vec::new();
+        tx.write_to_stream(&mut tx_ser).unwrap();
+        assert_eq!(store.transactions()[0].txid, tx.txid());
+        assert_eq!(store.transactions()[0].tx_hex, hex::encode(&tx_ser));
+        assert_eq!(store.outputs()[0].spent_by, None);
+        assert_eq!(store.outputs()[1].spent_by, Some(tx.txid()));
+    }
+
+    #[test]
+    fn pending_spends() {
+        let addresses = [Address::from_bytes(AddressType::P2PKH, [1; 20])];
+        let utxos = vec![utxo(1, 0, 10_000), utxo(1, 1, 20_000)];
+        let mut store = TxStore::open(temp_path("pending-spends")).unwrap();
+        store.update_utxos(&addresses, std::slice::from_ref(&utxos));
+        store.record_tx(&spending_tx(1, 1));
+        let txid = hex::encode([1; 32]);
+        assert!(!store.is_pending_spend(&txid, 0));
+        assert!(store.is_pending_spend(&txid, 1));
+        assert!(!store.is_pending_spend(&hex::encode([2; 32]), 1));
+        assert_eq!(store.cached_balance(), 10_000);
+
+        // the reservation ends once the backend doesn't list the output anymore
+        store.update_utxos(&addresses, &[vec![utxos[0].clone()]]);
+        assert!(!store.is_pending_spend(&txid, 1));
+        assert_eq!(store.cached_balance(), 10_000);
+
+        // or when the transaction seems to have been dropped
+        store.update_utxos(&addresses, &[utxos]);
+        assert!(store.is_pending_spend(&txid, 1));
+        store.data.transactions[0].timestamp = now() - PENDING_SPEND_EXPIRY;
+        assert!(!store.is_pending_spend(&txid, 1));
+        assert_eq!(store.cached_balance(), 30_000);
+    }
+
+    #[test]
+    fn save_and_open() {
+        let path = temp_path("save");
+        let addresses = [Address::from_bytes(AddressType::P2PKH, [1; 20])];
+        let mut store = TxStore::open(path.clone()).unwrap();
+        store.update_utxos(&addresses, &[vec![utxo(1, 0, 10_000), utxo(1, 1, 20_000)]]);
+        store.record_tx(&spending_tx(1, 1));
+        store.save().unwrap();
+        let reopened = TxStore::open(path.clone()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(reopened.outputs().len(), 2);
+        assert_eq!(reopened.transactions()[0].txid, store.transactions()[0].txid);
+        assert_eq!(reopened.last_update(), store.last_update());
+        assert!(reopened.is_pending_spend(&hex::encode([1; 32]), 1));
+    }
+
+    #[test]
+    fn save_to_unwritable_path() {
+        let path = temp_path("missing-dir").join("txs.json");
+        let store = TxStore::open(path.clone()).unwrap();
+        match store.save() {
+            Err(TxStoreError::IoError(err)) => assert_eq!(err.kind(), io::ErrorKind::NotFound),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,392 @@
+use crate::address::Address;
+use crate::backend::{BackendError, UtxoEntry, UtxoVerification};
+use crate::wallet::Wallet;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+
+/// Longest time to wait for the backend at once, i.e. how quickly a cancellation takes effect.
+const CANCEL_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+pub enum WatchError {
+    Timeout,
+    Cancelled,
+    BackendError(BackendError),
+}
+
+impl From<BackendError> for WatchError {
+    fn from(err: BackendError) -> Self {
+        WatchError::BackendError(err)
+    }
+}
+
+impl std::fmt::Display for WatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for WatchError {
+
+}
+
+type UtxoCallback<'a> = Box<dyn FnMut(&UtxoEntry) + 'a>;
+
+/// Stops a running TxWatcher, also from another thread.
+#[derive(Clone, Debug, Default)]
+pub struct CancelHandle(Arc<AtomicBool>);
+
+impl CancelHandle {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Waits for a new transaction paying to an address and optionally for its confirmation. It's
+/// notified by backends with subscriptions or P2P connections, and polls the others.
+pub struct TxWatcher<'a> {
+    wallet: &'a Wallet,
+    address: Address,
+    /// Txids of UTXOs which don't count as new, e.g. the ones before watching started.
+    ignored_txids: HashSet<String>,
+    timeout: Option<Duration>,
+    wait_for_confirmation: bool,
+    cancel_handle: CancelHandle,
+    on_seen_in_mempool: Option<UtxoCallback<'a>>,
+    on_confirmed: Option<UtxoCallback<'a>>,
+    /// The new UTXO once it's found, so resumed runs continue with it.
+    found_utxo: Option<UtxoEntry>,
+}
+
+impl<'a> TxWatcher<'a> {
+    pub fn new(wallet: &'a Wallet, address: Address, ignored_txids: HashSet<String>) -> Self {
+        TxWatcher {
+            wallet,
+            address,
+            ignored_txids,
+            timeout: None,
+            wait_for_confirmation: false,
+            cancel_handle: CancelHandle::default(),
+            on_seen_in_mempool: None,
+            on_confirmed: None,
+            found_utxo: None,
+        }
+    }
+
+    /// Makes `run` fail with WatchError::Timeout after the duration, None to wait forever.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Makes `run` wait for the transaction to be confirmed instead of returning once it's seen.
+    pub fn set_wait_for_confirmation(&mut self, wait_for_confirmation: bool) {
+        self.wait_for_confirmation = wait_for_confirmation;
+    }
+
+    /// Called once the transaction is seen unconfirmed.
+    pub fn on_seen_in_mempool(&mut self, callback: impl FnMut(&UtxoEntry) + 'a) {
+        self.on_seen_in_mempool = Some(Box::new(callback));
+    }
+
+    /// Called once the transaction is seen in a block, possibly without seeing it unconfirmed.
+    pub fn on_confirmed(&mut self, callback: impl FnMut(&UtxoEntry) + 'a) {
+        self.on_confirmed = Some(Box::new(callback));
+    }
+
+    /// Handle to stop `run` with WatchError::Cancelled.
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel_handle.clone()
+    }
+
+    /// Waits until a new UTXO arrives at the address, or until it's confirmed with
+    /// `set_wait_for_confirmation`. After a timeout or cancellation, calling it again resumes
+    /// watching without calling callbacks twice.
+    pub fn run(&mut self) -> Result<UtxoEntry, WatchError> {
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        let mut might_have_changed = true;
+        loop {
+            if might_have_changed {
+                if let Some(utxo) = self.check()? {
+                    return Ok(utxo);
+                }
+            }
+            if self.cancel_handle.0.swap(false, Ordering::SeqCst) {
+                return Err(WatchError::Cancelled);
+            }
+            let mut wait_duration = CANCEL_CHECK_INTERVAL;
+            if let Some(deadline) = deadline {
+                let now = Instant::now();
+                if now >= deadline {
+                    return Err(WatchError::Timeout);
+                }
+                wait_duration = wait_duration.min(deadline - now);
+            }
+            might_have_changed = self.wallet.backend()
+                .wait_for_address_change(&self.address, wait_duration)?;
+        }
+    }
+
+    /// Whether the verification status means the transaction is in a block. Without a header
+    /// chain, the backend's proof has to be trusted, with one it has to be verified. A proof of
+    /// a block the chain doesn't know yet keeps the watch going.
+    fn is_confirmed(&self, verification: UtxoVerification) -> bool {
+        match verification {
+            UtxoVerification::Verified => true,
+            UtxoVerification::Unverified => self.wallet.header_chain().is_none(),
+            UtxoVerification::Unconfirmed |
+            UtxoVerification::UnknownBlock |
+            UtxoVerification::Invalid => false,
+        }
+    }
+
+    /// Looks for the new UTXO and its confirmation, calling the callbacks. Returns the UTXO if
+    /// the watch is done.
+    fn check(&mut self) -> Result<Option<UtxoEntry>, BackendError> {
+        if let Some(utxo) = &self.found_utxo {
+            if self.is_confirmed(utxo.verification) {
+                return Ok(Some(utxo.clone()));
+            }
+        }
+        let is_new = self.found_utxo.is_none();
+        let mut utxo = match self.found_utxo.take() {
+            Some(utxo) => utxo,
+            None => {
+                let address = std::slice::from_ref(&self.address);
+                match self.wallet.backend().get_utxos(address)?.into_iter()
+                    .flatten()
+                    .find(|utxo| !self.ignored_txids.contains(&utxo.txid)) {
+                    Some(utxo) => utxo,
+                    None => return Ok(None),
+                }
+            },
+        };
+        let (verification, confirmation_height) = self.wallet.verify_tx(&utxo.txid)?;
+        utxo.verification = verification;
+        utxo.confirmation_height = confirmation_height;
+        self.found_utxo = Some(utxo.clone());
+        if self.is_confirmed(verification) {
+            if let Some(on_confirmed) = self.on_confirmed.as_mut() {
+                on_confirmed(&utxo);
+            }
+            return Ok(Some(utxo));
+        }
+        if is_new {
+            if let Some(on_seen_in_mempool) = self.on_seen_in_mempool.as_mut() {
+                on_seen_in_mempool(&utxo);
+            }
+        }
+        if !self.wait_for_confirmation {
+            return Ok(Some(utxo));
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{Backend, TokenEntry};
+    use crate::header_chain::HeaderChain;
+    use crate::merkle_block::{MerkleBranch, TxProof};
+    use crate::network::Network;
+    use crate::outputs::TradeOfferOutput;
+    use crate::tx::Tx;
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+
+    enum Event {
+        Receive(String),
+        Confirm(String),
+    }
+
+    /// Backend of a single address, which applies one event per wait for an address change.
+    struct MockBackend {
+        utxos: RefCell<Vec<UtxoEntry>>,
+        confirmed_txids: RefCell<HashSet<String>>,
+        events: RefCell<VecDeque<Event>>,
+    }
+
+    impl MockBackend {
+        fn new(utxos: Vec<UtxoEntry>, events: Vec<Event>) -> Self {
+            MockBackend {
+                utxos: RefCell::new(utxos),
+                confirmed_txids: RefCell::new(HashSet::new()),
+                events: RefCell::new(events.into_iter().collect()),
+            }
+        }
+    }
+
+    impl Backend for MockBackend {
+        fn get_utxos(&self, _addresses: &[Address])
+                -> Result<Vec<Vec<UtxoEntry>>, BackendError> {
+            Ok(vec![self.utxos.borrow().clone()])
+        }
+
+        fn are_addresses_used(&self, _addresses: &[Address]) -> Result<Vec<bool>, BackendError> {
+            unimplemented!()
+        }
+
+        fn broadcast(&self, _tx: &Tx) -> Result<String, BackendError> {
+            unimplemented!()
+        }
+
+        fn get_txs(&self, _tx_ids: &[String]) -> Result<Vec<Tx>, BackendError> {
+            unimplemented!()
+        }
+
+        fn is_output_unspent(&self, _tx_id: &str, _output_idx: u32) -> Result<bool, BackendError> {
+            unimplemented!()
+        }
+
+        fn get_tx_proof(&self, tx_id: &str) -> Result<Option<TxProof>, BackendError> {
+            if !self.confirmed_txids.borrow().contains(tx_id) {
+                return Ok(None);
+            }
+            let proof = MerkleBranch { block_height: 1, pos: 0, hashes: vec![] };
+            Ok(Some(TxProof::MerkleBranch(proof)))
+        }
+
+        fn validate_slp_txids(&self, _tx_ids: &[String]) -> Result<Vec<String>, BackendError> {
+            unimplemented!()
+        }
+
+        fn token_info(&self, _token_ids: &[String]) -> Result<Vec<TokenEntry>, BackendError> {
+            unimplemented!()
+        }
+
+        fn list_tokens(&self) -> Result<Vec<TokenEntry>, BackendError> {
+            unimplemented!()
+        }
+
+        fn find_trade_offers(&self) -> Result<Vec<TradeOfferOutput>, BackendError> {
+            unimplemented!()
+        }
+
+        fn wait_for_address_change(&self, _address: &Address, timeout: Duration)
+                -> Result<bool, BackendError> {
+            match self.events.borrow_mut().pop_front() {
+                Some(Event::Receive(txid)) => self.utxos.borrow_mut().push(utxo(&txid)),
+                Some(Event::Confirm(txid)) => {
+                    self.confirmed_txids.borrow_mut().insert(txid);
+                },
+                None => {
+                    std::thread::sleep(timeout.min(Duration::from_millis(5)));
+                    return Ok(false);
+                },
+            }
+            Ok(true)
+        }
+    }
+
+    fn txid(byte: u8) -> String {
+        hex::encode([byte; 32])
+    }
+
+    fn utxo(txid: &str) -> UtxoEntry {
+        UtxoEntry {
+            txid: txid.to_string(),
+            vout: 0,
+            amount: 0.0001,
+            satoshis: 10_000,
+            confirmation_height: None,
+            verification: UtxoVerification::Unverified,
+        }
+    }
+
+    fn wallet(backend: &Rc<MockBackend>) -> Wallet {
+        let mut wallet = Wallet::from_secret(&[1; 32], Network::Mainnet).unwrap();
+        wallet.set_backend(backend.clone());
+        wallet
+    }
+
+    fn ignored(txids: &[String]) -> HashSet<String> {
+        txids.iter().cloned().collect()
+    }
+
+    #[test]
+    fn timeout() {
+        let wallet = wallet(&Rc::new(MockBackend::new(vec![], vec![])));
+        let mut watcher = wallet.watch_address(&wallet.address(), HashSet::new());
+        watcher.set_timeout(Some(Duration::from_millis(20)));
+        assert!(matches!(watcher.run(), Err(WatchError::Timeout)));
+    }
+
+    #[test]
+    fn ignores_already_existing() {
+        let old_txid = txid(1);
+        let new_txid = txid(2);
+        let backend = Rc::new(MockBackend::new(vec![utxo(&old_txid)], vec![]));
+        let wallet = wallet(&backend);
+        let mut watcher = wallet.watch_address(&wallet.address(), ignored(&[old_txid]));
+        watcher.set_timeout(Some(Duration::from_millis(20)));
+        assert!(matches!(watcher.run(), Err(WatchError::Timeout)));
+
+        backend.events.borrow_mut().push_back(Event::Receive(new_txid.clone()));
+        watcher.set_timeout(Some(Duration::from_millis(500)));
+        assert_eq!(watcher.run().unwrap().txid, new_txid);
+    }
+
+    #[test]
+    fn mempool_then_confirmed() {
+        let log = RefCell::new(Vec::new());
+        let tx_id = txid(1);
+        let events = vec![Event::Receive(tx_id.clone()), Event::Confirm(tx_id.clone())];
+        let wallet = wallet(&Rc::new(MockBackend::new(vec![], events)));
+        let mut watcher = wallet.watch_address(&wallet.address(), HashSet::new());
+        watcher.set_timeout(Some(Duration::from_millis(500)));
+        watcher.set_wait_for_confirmation(true);
+        watcher.on_seen_in_mempool(|utxo| log.borrow_mut().push(("mempool", utxo.verification)));
+        watcher.on_confirmed(|utxo| log.borrow_mut().push(("confirmed", utxo.verification)));
+        assert_eq!(watcher.run().unwrap().txid, tx_id);
+        assert_eq!(*log.borrow(), vec![
+            ("mempool", UtxoVerification::Unconfirmed),
+            ("confirmed", UtxoVerification::Unverified),
+        ]);
+    }
+
+    #[test]
+    fn waits_on_unknown_block() {
+        let log = RefCell::new(Vec::new());
+        let tx_id = txid(1);
+        let events = vec![Event::Receive(tx_id.clone()), Event::Confirm(tx_id)];
+        let mut wallet = wallet(&Rc::new(MockBackend::new(vec![], events)));
+        let path = std::env::temp_dir()
+            .join(format!("slpagora-test-{}-watcher-headers.bin", std::process::id()));
+        wallet.set_header_chain(Rc::new(HeaderChain::open(Network::Mainnet, path).unwrap()));
+        let mut watcher = wallet.watch_address(&wallet.address(), HashSet::new());
+        watcher.set_timeout(Some(Duration::from_millis(50)));
+        watcher.set_wait_for_confirmation(true);
+        watcher.on_seen_in_mempool(|utxo| log.borrow_mut().push(("mempool", utxo.verification)));
+        watcher.on_confirmed(|utxo| log.borrow_mut().push(("confirmed", utxo.verification)));
+        assert!(matches!(watcher.run(), Err(WatchError::Timeout)));
+        assert_eq!(*log.borrow(), vec![("mempool", UtxoVerification::Unconfirmed)]);
+        assert_eq!(wallet.verify_tx(&txid(1)).unwrap().0, UtxoVerification::UnknownBlock);
+    }
+
+    #[test]
+    fn resumes_after_cancel() {
+        let log = &RefCell::new(Vec::new());
+        let tx_id = txid(1);
+        let backend = Rc::new(MockBackend::new(vec![], vec![Event::Receive(tx_id.clone())]));
+        let wallet = wallet(&backend);
+        let mut watcher = wallet.watch_address(&wallet.address(), HashSet::new());
+        watcher.set_timeout(Some(Duration::from_millis(500)));
+        watcher.set_wait_for_confirmation(true);
+        let cancel_handle = watcher.cancel_handle();
+        watcher.on_seen_in_mempool(move |_| {
+            log.borrow_mut().push("mempool");
+            cancel_handle.cancel();
+        });
+        watcher.on_confirmed(move |_| log.borrow_mut().push("confirmed"));
+        assert!(matches!(watcher.run(), Err(WatchError::Cancelled)));
+        assert_eq!(*log.borrow(), vec!["mempool"]);
+
+        backend.events.borrow_mut().push_back(Event::Confirm(tx_id.clone()));
+        assert_eq!(watcher.run().unwrap().txid, tx_id);
+        assert_eq!(*log.borrow(), vec!["mempool", "confirmed"]);
+    }
+}
@@ -1,6 +1,8 @@
 use crate::message::Message;
+use crate::message_error::MessageError;
+use crate::network::Network;
 use crate::serialize::{read_var_str, write_var_str};
-use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt, WriteBytesExt};
 use std::{io, io::{Write, Read}};
 
 
@@ -26,7 +28,7 @@ impl VersionMessage {
         b"version"
     }
 
-    pub fn message(&self) -> Message {
+    pub fn message(&self, network: Network) -> Message {
         let mut payload = Vec::new();
         payload.write_i32::<LittleEndian>(self.version).unwrap();
         payload.write_u64::<LittleEndian>(self.services).unwrap();
@@ -34,51 +36,56 @@ impl VersionMessage {
 
         payload.write_u64::<LittleEndian>(self.recv_services).unwrap();
         payload.write(&self.recv_addr).unwrap();
-        payload.write_u16::<LittleEndian>(self.recv_port).unwrap();
+        payload.write_u16::<BigEndian>(self.recv_port).unwrap();
 
         payload.write_u64::<LittleEndian>(self.send_services).unwrap();
         payload.write(&self.send_addr).unwrap();
-        payload.write_u16::<LittleEndian>(self.send_port).unwrap();
+        payload.write_u16::<BigEndian>(self.send_port).unwrap();
 
         payload.write_u64::<LittleEndian>(self.nonce).unwrap();
         write_var_str(&mut payload, &self.user_agent).unwrap();
         payload.write_i32::<LittleEndian>(self.start_height).unwrap();
         payload.write_u8(if self.relay {1} else {0}).unwrap();
 
-        Message::from_payload(Self::command(), payload)
+        Message::from_payload(network, Self::command(), payload)
     }
 
-    pub fn from_payload(payload: &[u8]) -> VersionMessage {
-        let mut cur = io::Cursor::new(payload);
-        let version = cur.read_i32::<LittleEndian>().unwrap();
-        let services = cur.read_u64::<LittleEndian>().unwrap();
-        let timestamp = cur.read_i64::<LittleEndian>().unwrap();
+    pub fn from_payload(payload: &[u8]) -> Result<VersionMessage, MessageError> {
+        Self::read_payload(&mut io::Cursor::new(payload))
+            .map_err(|_| MessageError::MalformedPayload("version".to_string()))
+    }
+
+    fn read_payload(cur: &mut io::Cursor<&[u8]>) -> io::Result<VersionMessage> {
+        let version = cur.read_i32::<LittleEndian>()?;
+        let services = cur.read_u64::<LittleEndian>()?;
+        let timestamp = cur.read_i64::<LittleEndian>()?;
 
-        let recv_services = cur.read_u64::<LittleEndian>().unwrap();
+        let recv_services = cur.read_u64::<LittleEndian>()?;
         let mut recv_addr = [0; 16];
-        cur.read(&mut recv_addr).unwrap();
-        let recv_port = cur.read_u16::<LittleEndian>().unwrap();
+        cur.read_exact(&mut recv_addr)?;
+        let recv_port = cur.read_u16::<BigEndian>()?;
 
-        let send_services = cur.read_u64::<LittleEndian>().unwrap();
+        let send_services = cur.read_u64::<LittleEndian>()?;
         let mut send_addr = [0; 16];
-        cur.read(&mut send_addr).unwrap();
-        let send_port = cur.read_u16::<LittleEndian>().unwrap();
+        cur.read_exact(&mut send_addr)?;
+        let send_port = cur.read_u16::<BigEndian>()?;
 
-        let nonce = cur.read_u64::<LittleEndian>().unwrap();
-        let user_agent = read_var_str(&mut cur).unwrap();
-        let start_height = cur.read_i32::<LittleEndian>().unwrap();
-        let relay = cur.read_u8().unwrap() > 0;
-        VersionMessage {
+        let nonce = cur.read_u64::<LittleEndian>()?;
+        let user_agent = read_var_str(cur)?;
+        let start_height = cur.read_i32::<LittleEndian>()?;
+        // peers of protocol versions before BIP37 don't send the relay flag
+        let relay = cur.read_u8().map(|relay| relay > 0).unwrap_or(true);
+        Ok(VersionMessage {
             version, services, timestamp, recv_services, recv_addr, recv_port, send_services,
             send_addr, send_port, nonce, user_agent, start_height, relay,
-        }
+        })
     }
 }
 
 pub struct VerackMessage;
 
 impl VerackMessage {
-    pub fn message(&self) -> Message {
-        Message::from_payload(b"verack", vec![])
+    pub fn message(&self, network: Network) -> Message {
+        Message::from_payload(network, b"verack", vec![])
     }
 }
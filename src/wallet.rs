@@ -1,103 +1,489 @@
-use crate::address::Address;
-use serde::{Serialize, Deserialize};
+use crate::address::{Address, AddressType};
+use crate::hash::hash160;
 use crate::incomplete_tx::{IncompleteTx, Utxo};
 use crate::tx::{Tx, TxOutpoint, tx_hex_to_hash};
-use crate::outputs::{P2PKHOutput};
-use std::collections::HashSet;
+use crate::outputs::{P2PKHOutput, P2PKHUncompressedOutput};
+use crate::incomplete_tx::Output;
+use crate::wif::{to_wif, WifKey, WifError};
+use crate::network::Network;
+use crate::bip32::{ExtendedPrivKey, HARDENED};
+use crate::script_type::ScriptType;
+use crate::backend::{Backend, BackendError, RestBackend, UtxoEntry, UtxoVerification};
+use crate::header_chain::{HeaderChain, HeaderChainError};
+use crate::tx_store::{StoredTrade, TxStore};
+use crate::tx_watcher::{TxWatcher, WatchError};
+use std::collections::{HashMap, HashSet};
+use std::cell::{Cell, Ref, RefCell};
+use std::rc::Rc;
+use std::time::Duration;
 
 
-pub struct Wallet {
+/// BIP44 coin types of the wallet's accounts, m/44'/coin_type'/0'.
+const BCH_COIN_TYPE: u32 = 145;
+const SLP_COIN_TYPE: u32 = 245;
+/// Number of consecutive unused addresses after which address discovery stops.
+const GAP_LIMIT: u32 = 20;
+
+const RECEIVE_CHAIN: u32 = 0;
+const CHANGE_CHAIN: u32 = 1;
+
+struct HdAccounts {
+    mnemonic: bip39::Mnemonic,
+    bch_account: ExtendedPrivKey,
+    slp_account: ExtendedPrivKey,
+}
+
+#[derive(Clone, Copy)]
+struct SingleKey {
     secret_key: secp256k1::SecretKey,
+    is_compressed: bool,
+}
+
+/// A key of the wallet together with its P2PKH address.
+struct WalletKey {
+    secret_key: secp256k1::SecretKey,
+    is_compressed: bool,
     address: Address,
 }
 
-#[derive(Deserialize, Serialize, Debug)]
-pub struct UtxoEntry {
-    pub txid: String,
-    pub vout: u32,
-    pub amount: f64,
-    pub satoshis: u64,
+pub struct Wallet {
+    /// None for wallets of a single imported WIF key.
+    hd: Option<HdAccounts>,
+    /// Key not derived from the HD accounts, either of a wallet created before HD support or an
+    /// imported WIF key.
+    single_key: Option<SingleKey>,
+    next_receive_idx: Cell<u32>,
+    next_change_idx: Cell<u32>,
+    network: Network,
+    backend: Rc<dyn Backend>,
+    /// Headers to verify the merkle proofs of UTXOs against, None to not verify them.
+    header_chain: Option<Rc<HeaderChain>>,
+    /// Local record of the wallet's outputs and transactions, None to not keep one.
+    tx_store: Option<RefCell<TxStore>>,
 }
 
-#[derive(Deserialize, Serialize, Debug)]
-struct UtxoResult {
-    utxos: Vec<UtxoEntry>,
+fn account_path(coin_type: u32) -> [u32; 3] {
+    [44 | HARDENED, coin_type | HARDENED, HARDENED]
 }
 
 impl Wallet {
-    pub fn from_secret(secret: &[u8]) -> Result<Wallet, secp256k1::Error> {
-        let secret_key = secp256k1::SecretKey::from_slice(&secret)?;
-        let curve = secp256k1::Secp256k1::new();
-        let pk = secp256k1::PublicKey::from_secret_key(&curve, &secret_key);
-        let addr = Address::from_pub_key("bitcoincash", &pk);
+    pub fn from_mnemonic(mnemonic: bip39::Mnemonic, network: Network)
+            -> Result<Wallet, secp256k1::Error> {
+        let master = ExtendedPrivKey::from_seed(&mnemonic.to_seed(""))?;
         Ok(Wallet {
-            secret_key,
-            address: addr,
+            hd: Some(HdAccounts {
+                bch_account: master.derive_path(&account_path(BCH_COIN_TYPE))?,
+                slp_account: master.derive_path(&account_path(SLP_COIN_TYPE))?,
+                mnemonic,
+            }),
+            single_key: None,
+            next_receive_idx: Cell::new(0),
+            next_change_idx: Cell::new(0),
+            network,
+            backend: Rc::new(RestBackend::new(network)),
+            header_chain: None,
+            tx_store: None,
         })
     }
 
-    pub fn address(&self) -> &Address {
-        &self.address
+    /// Opens a wallet created before HD support from its raw 32 byte secret key. The secret key
+    /// itself is used as the entropy of the wallet's 24 word mnemonic, i.e. the mnemonic is just
+    /// another encoding of the legacy key. The key is also kept as is so old funds stay
+    /// spendable, as its address isn't one derived from the mnemonic, see `legacy_key_wif`.
+    pub fn from_secret(secret: &[u8], network: Network) -> Result<Wallet, secp256k1::Error> {
+        let secret_key = secp256k1::SecretKey::from_slice(secret)?;
+        let mnemonic = bip39::Mnemonic::from_entropy(secret)
+            .map_err(|_| secp256k1::Error::InvalidSecretKey)?;
+        let mut wallet = Wallet::from_mnemonic(mnemonic, network)?;
+        wallet.single_key = Some(SingleKey { secret_key, is_compressed: true });
+        Ok(wallet)
+    }
+
+    /// Creates a wallet of a single WIF private key, which has to belong to `network`.
+    pub fn from_wif(wif_key: &WifKey, network: Network) -> Result<Wallet, WifError> {
+        if !wif_key.is_for_network(network) {
+            return Err(WifError::WrongNetwork(wif_key.network));
+        }
+        Ok(Wallet {
+            hd: None,
+            single_key: Some(SingleKey {
+                secret_key: wif_key.secret_key,
+                is_compressed: wif_key.is_compressed,
+            }),
+            next_receive_idx: Cell::new(0),
+            next_change_idx: Cell::new(0),
+            network,
+            backend: Rc::new(RestBackend::new(network)),
+            header_chain: None,
+            tx_store: None,
+        })
+    }
+
+    /// The wallet's mnemonic phrase, None for wallets of an imported WIF key.
+    pub fn mnemonic(&self) -> Option<&bip39::Mnemonic> {
+        self.hd.as_ref().map(|hd| &hd.mnemonic)
+    }
+
+    /// The key of a wallet created before HD support in WIF together with its address, None for
+    /// other wallets. It isn't derived from the mnemonic, so it has to be backed up separately
+    /// or its funds swept into an HD address.
+    pub fn legacy_key_wif(&self) -> Option<(Address, String)> {
+        self.hd.as_ref()?;
+        let single_key = self.single_key?;
+        let key = self.wallet_key(single_key.secret_key, single_key.is_compressed);
+        Some((key.address, to_wif(&key.secret_key, self.network, key.is_compressed)))
+    }
+
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    /// The wallet's backend, which can be cloned to share it with other wallets.
+    pub fn backend(&self) -> &Rc<dyn Backend> {
+        &self.backend
+    }
+
+    /// Replaces the default rest.bitcoin.com backend, e.g. by another indexer or a node.
+    pub fn set_backend(&mut self, backend: Rc<dyn Backend>) {
+        self.backend = backend;
     }
 
-    pub fn get_utxos(&self, address: &Address) -> Vec<UtxoEntry> {
-        let result: UtxoResult = reqwest::get(
-            &format!("https://rest.bitcoin.com/v2/address/utxo/{}", address.cash_addr())
-        ).unwrap().json().unwrap();
-        result.utxos
+    pub fn header_chain(&self) -> Option<&Rc<HeaderChain>> {
+        self.header_chain.as_ref()
     }
 
-    pub fn get_balance(&self) -> u64 {
-        self.get_utxos(&self.address).iter().map(|utxo| utxo.satoshis).sum()
+    /// Enables verifying UTXOs with merkle proofs against the chain, see `verify_tx`.
+    pub fn set_header_chain(&mut self, header_chain: Rc<HeaderChain>) {
+        self.header_chain = Some(header_chain);
     }
 
-    pub fn wait_for_transaction(&self, address: &Address, already_existing: &HashSet<String>)
-            -> UtxoEntry {
-        loop {
-            let utxos = self.get_utxos(address);
-            let mut remaining = utxos.into_iter()
-                .filter(|utxo| !already_existing.contains(&utxo.txid))
-                .collect::<Vec<_>>();
-            if remaining.len() > 0 {
-                return remaining.remove(0)
+    pub fn tx_store(&self) -> Option<Ref<'_, TxStore>> {
+        self.tx_store.as_ref().map(RefCell::borrow)
+    }
+
+    /// Enables recording UTXOs, sent transactions and trades in the store. Outputs spent by
+    /// recorded transactions aren't used again until the backend knows they're spent.
+    pub fn set_tx_store(&mut self, tx_store: TxStore) {
+        self.tx_store = Some(RefCell::new(tx_store));
+    }
+
+    /// Updates the store, if there is one. It's only a record, so failing to write it doesn't
+    /// fail the wallet's operations, e.g. a transaction is broadcast either way; it's reported
+    /// instead.
+    fn update_tx_store(&self, update: impl FnOnce(&mut TxStore)) {
+        if let Some(tx_store) = &self.tx_store {
+            let mut tx_store = tx_store.borrow_mut();
+            update(&mut tx_store);
+            if let Err(err) = tx_store.save() {
+                println!("Couldn't save the local transaction store ({}), the latest transactions \
+                          and trades might be missing from it.", err);
+            }
+        }
+    }
+
+    /// Records the trade contract in the store, replacing earlier records of it.
+    pub fn record_trade(&self, trade: StoredTrade) {
+        self.update_tx_store(|tx_store| tx_store.record_trade(trade));
+    }
+
+    /// Fetches the UTXOs of the addresses, leaving out the ones spent by our pending
+    /// transactions, and records them in the store.
+    fn fetch_utxos(&self, addresses: &[Address]) -> Result<Vec<Vec<UtxoEntry>>, BackendError> {
+        let mut utxos = self.backend.get_utxos(addresses)?;
+        self.update_tx_store(|tx_store| tx_store.update_utxos(addresses, &utxos));
+        if let Some(tx_store) = self.tx_store() {
+            for address_utxos in utxos.iter_mut() {
+                address_utxos.retain(|utxo| !tx_store.is_pending_spend(&utxo.txid, utxo.vout));
             }
-            std::thread::sleep(std::time::Duration::new(1, 0));
         }
+        Ok(utxos)
+    }
+
+    fn derive_key(&self, account: &ExtendedPrivKey, chain: u32, idx: u32) -> secp256k1::SecretKey {
+        // derivation only fails with negligible probability
+        *account.derive_path(&[chain, idx]).unwrap().secret_key()
+    }
+
+    fn key_address(&self, key: &secp256k1::SecretKey, is_compressed: bool, prefix: &str)
+            -> Address {
+        let curve = secp256k1::Secp256k1::signing_only();
+        let pub_key = secp256k1::PublicKey::from_secret_key(&curve, key);
+        if is_compressed {
+            Address::from_pub_key(prefix, &pub_key)
+        } else {
+            Address::from_bytes_prefix(prefix, AddressType::P2PKH,
+                                       hash160(&pub_key.serialize_uncompressed()))
+        }
+    }
+
+    fn wallet_key(&self, secret_key: secp256k1::SecretKey, is_compressed: bool) -> WalletKey {
+        WalletKey {
+            address: self.key_address(&secret_key, is_compressed, self.network.bch_prefix()),
+            secret_key,
+            is_compressed,
+        }
+    }
+
+    fn hd_key(&self, chain: u32, idx: u32) -> Option<WalletKey> {
+        let hd = self.hd.as_ref()?;
+        Some(self.wallet_key(self.derive_key(&hd.bch_account, chain, idx), true))
     }
 
-    pub fn init_transaction(&self) -> (IncompleteTx, u64) {
+    fn single_key_address(&self, prefix: &str) -> Address {
+        let single_key = self.single_key.expect("wallets without HD accounts have a single key");
+        self.key_address(&single_key.secret_key, single_key.is_compressed, prefix)
+    }
+
+    /// The current unused receive address, m/44'/145'/0'/0/i.
+    pub fn address(&self) -> Address {
+        self.hd_key(RECEIVE_CHAIN, self.next_receive_idx.get())
+            .map(|key| key.address)
+            .unwrap_or_else(|| self.single_key_address(self.network.bch_prefix()))
+    }
+
+    /// Returns an unused receive address and marks it as used, so the next call returns another.
+    pub fn fresh_receive_address(&self) -> Address {
+        let address = self.address();
+        self.next_receive_idx.set(self.next_receive_idx.get() + 1);
+        address
+    }
+
+    /// The current unused change address, m/44'/145'/0'/1/i. It's marked as used once a
+    /// transaction paying to it is sent via `send_tx`.
+    pub fn change_address(&self) -> Address {
+        self.hd_key(CHANGE_CHAIN, self.next_change_idx.get())
+            .map(|key| key.address)
+            .unwrap_or_else(|| self.single_key_address(self.network.bch_prefix()))
+    }
+
+    /// The address to receive tokens at, m/44'/245'/0'/0/0.
+    pub fn slp_address(&self) -> Address {
+        match &self.hd {
+            Some(hd) => {
+                let key = self.derive_key(&hd.slp_account, RECEIVE_CHAIN, 0);
+                self.key_address(&key, true, self.network.slp_prefix())
+            },
+            None => self.single_key_address(self.network.slp_prefix()),
+        }
+    }
+
+    /// All keys which might hold the wallet's BCH: the single key, all used receive and change
+    /// addresses and the current receive address.
+    fn keys(&self) -> Vec<WalletKey> {
+        let mut keys = self.single_key.iter()
+            .map(|single_key| self.wallet_key(single_key.secret_key, single_key.is_compressed))
+            .collect::<Vec<_>>();
+        keys.extend((0..=self.next_receive_idx.get())
+            .filter_map(|idx| self.hd_key(RECEIVE_CHAIN, idx)));
+        keys.extend((0..self.next_change_idx.get())
+            .filter_map(|idx| self.hd_key(CHANGE_CHAIN, idx)));
+        keys
+    }
+
+    /// The key of one of the wallet's compressed P2PKH addresses, e.g. a trade's cancel address.
+    /// Receive addresses handed out without being funded aren't discovered on startup, so it also
+    /// searches up to `GAP_LIMIT` receive addresses after the current one.
+    pub fn secret_key_for(&self, address: &Address) -> Option<secp256k1::SecretKey> {
+        let next_receive_idx = self.next_receive_idx.get();
+        self.keys().into_iter()
+            .chain((next_receive_idx + 1..=next_receive_idx + GAP_LIMIT)
+                .filter_map(|idx| self.hd_key(RECEIVE_CHAIN, idx)))
+            .find(|key| key.is_compressed && key.address.bytes() == address.bytes())
+            .map(|key| key.secret_key)
+    }
+
+    /// The wallet's keys in WIF together with their addresses.
+    pub fn export_wif(&self) -> Vec<(Address, String)> {
+        self.keys().into_iter()
+            .map(|key| (key.address, to_wif(&key.secret_key, self.network, key.is_compressed)))
+            .collect()
+    }
+
+    /// Scans the receive and change chains for used addresses until `GAP_LIMIT` consecutive
+    /// unused addresses are found, and continues after the last used ones.
+    pub fn discover_addresses(&self) -> Result<(), BackendError> {
+        if self.hd.is_none() {
+            return Ok(());
+        }
+        for (chain, next_idx) in [(RECEIVE_CHAIN, &self.next_receive_idx),
+                                  (CHANGE_CHAIN, &self.next_change_idx)].iter() {
+            let mut n_used = 0;
+            let mut idx = 0;
+            while idx < n_used + GAP_LIMIT {
+                let addresses = (idx..n_used + GAP_LIMIT)
+                    .filter_map(|idx| self.hd_key(*chain, idx))
+                    .map(|key| key.address)
+                    .collect::<Vec<_>>();
+                let used = self.backend.are_addresses_used(&addresses)?;
+                for (offset, is_used) in used.into_iter().enumerate() {
+                    if is_used {
+                        n_used = idx + offset as u32 + 1;
+                    }
+                }
+                idx += addresses.len() as u32;
+            }
+            next_idx.set(n_used);
+        }
+        Ok(())
+    }
+
+    /// Returns the UTXOs of the address, verified against the header chain if there is one.
+    pub fn get_utxos(&self, address: &Address) -> Result<Vec<UtxoEntry>, BackendError> {
+        let mut utxos = self.fetch_utxos(std::slice::from_ref(address))?
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+        self.verify_utxos(&mut utxos)?;
+        Ok(utxos)
+    }
+
+    pub fn get_balance(&self) -> Result<u64, BackendError> {
+        let addresses = self.keys().into_iter().map(|key| key.address).collect::<Vec<_>>();
+        Ok(self.fetch_utxos(&addresses)?.iter().flatten().map(|utxo| utxo.satoshis).sum())
+    }
+
+    /// The part of the balance whose transactions are proven to be in a block of the header
+    /// chain.
+    pub fn get_verified_balance(&self) -> Result<u64, BackendError> {
+        let addresses = self.keys().into_iter().map(|key| key.address).collect::<Vec<_>>();
+        let mut utxos = self.fetch_utxos(&addresses)?
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+        self.verify_utxos(&mut utxos)?;
+        Ok(utxos.iter()
+            .filter(|utxo| utxo.verification == UtxoVerification::Verified)
+            .map(|utxo| utxo.satoshis)
+            .sum())
+    }
+
+    /// Checks the backend's merkle proof of the transaction against the header chain. Returns
+    /// the result and, if it's verified, the height of the transaction's block. Without a
+    /// header chain, it only tells whether the transaction is confirmed.
+    pub fn verify_tx(&self, tx_id: &str) -> Result<(UtxoVerification, Option<u32>), BackendError> {
+        let proof = match self.backend.get_tx_proof(tx_id)? {
+            Some(proof) => proof,
+            None => return Ok((UtxoVerification::Unconfirmed, None)),
+        };
+        let header_chain = match &self.header_chain {
+            Some(header_chain) => header_chain,
+            None => return Ok((UtxoVerification::Unverified, None)),
+        };
+        match header_chain.verify_tx_inclusion(&tx_hex_to_hash(tx_id), &proof) {
+            Ok(height) => Ok((UtxoVerification::Verified, Some(height))),
+            Err(HeaderChainError::UnknownBlock) => Ok((UtxoVerification::UnknownBlock, None)),
+            Err(_) => Ok((UtxoVerification::Invalid, None)),
+        }
+    }
+
+    /// Sets the verification status and confirmation height of the UTXOs.
+    fn verify_utxos(&self, utxos: &mut [UtxoEntry]) -> Result<(), BackendError> {
+        if self.header_chain.is_none() {
+            return Ok(());
+        }
+        let mut results = HashMap::new();
+        for utxo in utxos {
+            if !results.contains_key(&utxo.txid) {
+                results.insert(utxo.txid.clone(), self.verify_tx(&utxo.txid)?);
+            }
+            let (verification, confirmation_height) = results[&utxo.txid];
+            utxo.verification = verification;
+            utxo.confirmation_height = confirmation_height;
+        }
+        Ok(())
+    }
+
+    /// Watcher for a new transaction paying to the address, ignoring UTXOs of the txids in
+    /// `already_existing`.
+    pub fn watch_address(&self, address: &Address, already_existing: HashSet<String>)
+            -> TxWatcher<'_> {
+        TxWatcher::new(self, address.clone(), already_existing)
+    }
+
+    /// Waits for a new UTXO at the address, at most for `timeout` if given.
+    pub fn wait_for_transaction(&self,
+                                address: &Address,
+                                already_existing: &HashSet<String>,
+                                timeout: Option<Duration>) -> Result<UtxoEntry, WatchError> {
+        let mut watcher = self.watch_address(address, already_existing.clone());
+        watcher.set_timeout(timeout);
+        watcher.run()
+    }
+
+    pub fn init_transaction(&self) -> Result<(IncompleteTx, u64), BackendError> {
         let mut tx_build = IncompleteTx::new_simple();
         let mut balance = 0;
-        self.get_utxos(&self.address).iter().for_each(|utxo| {
-            balance += utxo.satoshis;
-            tx_build.add_utxo(Utxo {
-                key: self.secret_key.clone(),
-                output: Box::new(P2PKHOutput {
-                    address: self.address.clone(),
-                    value: utxo.satoshis,
-                }),
-                outpoint: TxOutpoint {
-                    tx_hash: tx_hex_to_hash(&utxo.txid),
-                    output_idx: utxo.vout,
-                },
-                sequence: 0xffff_ffff,
-            });
-        });
-        (tx_build, balance)
+        let keys = self.keys();
+        let addresses = keys.iter().map(|key| key.address.clone()).collect::<Vec<_>>();
+        let current_receive_address = self.address();
+        for (key, utxos) in keys.into_iter().zip(self.fetch_utxos(&addresses)?) {
+            if key.address.bytes() == current_receive_address.bytes() && !utxos.is_empty() &&
+                    self.hd.is_some() {
+                // the current receive address got funded, hand out a new one from now on
+                self.next_receive_idx.set(self.next_receive_idx.get() + 1);
+            }
+            for utxo in utxos {
+                balance += utxo.satoshis;
+                let output: Box<dyn Output> = if key.is_compressed {
+                    Box::new(P2PKHOutput { address: key.address.clone(), value: utxo.satoshis })
+                } else {
+                    Box::new(P2PKHUncompressedOutput {
+                        address: key.address.clone(),
+                        value: utxo.satoshis,
+                    })
+                };
+                tx_build.add_utxo(Utxo {
+                    key: key.secret_key,
+                    output,
+                    outpoint: TxOutpoint {
+                        tx_hash: tx_hex_to_hash(&utxo.txid),
+                        output_idx: utxo.vout,
+                    },
+                    sequence: 0xffff_ffff,
+                });
+            }
+        }
+        Ok((tx_build, balance))
     }
 
-    pub fn send_tx(&self, tx: &Tx) -> Result<String, Box<std::error::Error>> {
-        let mut tx_ser = Vec::new();
-        tx.write_to_stream(&mut tx_ser)?;
-        Ok(reqwest::get(
-            &format!(
-                "https://rest.bitcoin.com/v2/rawtransactions/sendRawTransaction/{}",
-                hex::encode(&tx_ser),
-            ),
-        )?.text()?)
+    pub fn send_tx(&self, tx: &Tx) -> Result<String, BackendError> {
+        let tx_id = self.backend.broadcast(tx)?;
+        self.update_tx_store(|tx_store| tx_store.record_tx(tx));
+        let change_address = self.change_address();
+        let pays_to_change = tx.outputs().iter().any(|output| {
+            match ScriptType::classify(&output.script, self.network) {
+                ScriptType::P2PKH { pub_key_hash } => &pub_key_hash[..] == change_address.bytes(),
+                _ => false,
+            }
+        });
+        if pays_to_change && self.hd.is_some() {
+            self.next_change_idx.set(self.next_change_idx.get() + 1);
+        }
+        Ok(tx_id)
     }
 
     pub fn dust_amount(&self) -> u64 {
         546
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_secret_uses_secret_as_entropy() {
+        let secret = [0x80; 32];
+        let wallet = Wallet::from_secret(&secret, Network::Mainnet).unwrap();
+        let mnemonic = wallet.mnemonic().unwrap();
+        // BIP39 vector of the entropy 0x80 * 32
+        assert_eq!(mnemonic.to_string(),
+                   "letter advice cage absurd amount doctor acoustic avoid letter advice cage \
+                    absurd amount doctor acoustic avoid letter advice cage absurd amount doctor \
+                    acoustic bless");
+        assert_eq!(mnemonic.to_entropy(), secret.to_vec());
+        let (_, wif) = wallet.legacy_key_wif().unwrap();
+        let wif_key = crate::wif::from_wif(&wif).unwrap();
+        assert_eq!(wif_key.secret_key[..], secret[..]);
+    }
+}
@@ -0,0 +1,233 @@
+use std::io::{self, Read, Write};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use aes_gcm::Aes256Gcm;
+use aes_gcm::aead::{Aead, NewAead, Payload};
+
+
+/// Encrypted wallet files start with these bytes, followed by the format version.
+const WALLET_FILE_MAGIC: &[u8] = b"SLPAGORA";
+const WALLET_FILE_VERSION: u8 = 1;
+const SALT_SIZE: usize = 16;
+const NONCE_SIZE: usize = 12;
+
+/// scrypt parameters for new files, N = 2^15, r = 8, p = 1.
+const SCRYPT_LOG_N: u8 = 15;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+/// Highest scrypt parameters accepted from files, so a tampered file can't make decryption
+/// take forever or exhaust memory (128 * r * 2^log_n bytes, i.e. 2 GiB at most).
+const MAX_SCRYPT_LOG_N: u8 = 20;
+const MAX_SCRYPT_R: u32 = 16;
+const MAX_SCRYPT_P: u32 = 4;
+
+#[derive(Debug)]
+pub enum WalletFileError {
+    NotEncrypted,
+    UnsupportedVersion(u8),
+    InvalidKdfParams,
+    WrongPassphrase,
+    IoError(io::Error),
+}
+
+impl From<io::Error> for WalletFileError {
+    fn from(err: io::Error) -> Self {
+        WalletFileError::IoError(err)
+    }
+}
+
+impl std::fmt::Display for WalletFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            WalletFileError::WrongPassphrase =>
+                write!(f, "Wrong passphrase or corrupted wallet file"),
+            err => write!(f, "{:?}", err),
+        }
+    }
+}
+
+impl std::error::Error for WalletFileError {
+
+}
+
+/// Wallet files written before encryption was supported contain the plain wallet data.
+pub fn is_encrypted(file_bytes: &[u8]) -> bool {
+    file_bytes.starts_with(WALLET_FILE_MAGIC)
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], log_n: u8, r: u32, p: u32)
+        -> Result<[u8; 32], WalletFileError> {
+    let params = scrypt::ScryptParams::new(log_n, r, p)
+        .map_err(|_| WalletFileError::InvalidKdfParams)?;
+    let mut key = [0; 32];
+    scrypt::scrypt(passphrase.as_bytes(), salt, &params, &mut key)
+        .map_err(|_| WalletFileError::InvalidKdfParams)?;
+    Ok(key)
+}
+
+/// Encrypts the wallet data with AES-256-GCM, using a key derived from the passphrase with
+/// scrypt. The header (magic, version, KDF parameters, salt and nonce) is authenticated as well.
+pub fn encrypt(wallet_data: &[u8], passphrase: &str) -> Result<Vec<u8>, WalletFileError> {
+    encrypt_with_params(wallet_data, passphrase, SCRYPT_LOG_N, SCRYPT_R, SCRYPT_P)
+}
+
+fn encrypt_with_params(wallet_data: &[u8], passphrase: &str, log_n: u8, r: u32, p: u32)
+        -> Result<Vec<u8>, WalletFileError> {
+    use rand::RngCore;
+    let mut rng = rand::rngs::OsRng::new().unwrap();
+    let mut salt = [0; SALT_SIZE];
+    let mut nonce = [0; NONCE_SIZE];
+    rng.fill_bytes(&mut salt);
+    rng.fill_bytes(&mut nonce);
+    let mut file_bytes = Vec::new();
+    file_bytes.write_all(WALLET_FILE_MAGIC)?;
+    file_bytes.write_u8(WALLET_FILE_VERSION)?;
+    file_bytes.write_u8(log_n)?;
+    file_bytes.write_u32::<BigEndian>(r)?;
+    file_bytes.write_u32::<BigEndian>(p)?;
+    file_bytes.write_all(&salt)?;
+    file_bytes.write_all(&nonce)?;
+    let key = derive_key(passphrase, &salt, log_n, r, p)?;
+    let cipher = Aes256Gcm::new(&key.into());
+    let ciphertext = cipher.encrypt(
+        &nonce.into(),
+        Payload { msg: wallet_data, aad: &file_bytes },
+    ).expect("wallet data is small enough for AES-GCM");
+    file_bytes.extend_from_slice(&ciphertext);
+    Ok(file_bytes)
+}
+
+pub fn decrypt(file_bytes: &[u8], passphrase: &str) -> Result<Vec<u8>, WalletFileError> {
+    if !is_encrypted(file_bytes) {
+        return Err(WalletFileError::NotEncrypted);
+    }
+    let mut cur = io::Cursor::new(&file_bytes[WALLET_FILE_MAGIC.len()..]);
+    let version = cur.read_u8()?;
+    if version != WALLET_FILE_VERSION {
+        return Err(WalletFileError::UnsupportedVersion(version));
+    }
+    let log_n = cur.read_u8()?;
+    let r = cur.read_u32::<BigEndian>()?;
+    let p = cur.read_u32::<BigEndian>()?;
+    if log_n > MAX_SCRYPT_LOG_N || r > MAX_SCRYPT_R || p > MAX_SCRYPT_P {
+        return Err(WalletFileError::InvalidKdfParams);
+    }
+    let mut salt = [0; SALT_SIZE];
+    let mut nonce = [0; NONCE_SIZE];
+    cur.read_exact(&mut salt)?;
+    cur.read_exact(&mut nonce)?;
+    let header_size = WALLET_FILE_MAGIC.len() + cur.position() as usize;
+    let (header, ciphertext) = file_bytes.split_at(header_size);
+    let key = derive_key(passphrase, &salt, log_n, r, p)?;
+    let cipher = Aes256Gcm::new(&key.into());
+    cipher.decrypt(&nonce.into(), Payload { msg: ciphertext, aad: header })
+        .map_err(|_| WalletFileError::WrongPassphrase)
+}
+
+/// Replaces the file at `path` by writing to a temporary file first, so a crash can't leave a
+/// half written wallet behind. On Unix, the file is only readable by its owner.
+pub fn write_atomic(path: &std::path::Path, file_bytes: &[u8]) -> io::Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(&tmp_path)?;
+    file.write_all(file_bytes)?;
+    file.sync_all()?;
+    std::fs::rename(tmp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WALLET_DATA: &[u8] = b"some wallet data";
+    /// Offsets of the KDF parameters, salt and nonce in the header.
+    const LOG_N_IDX: usize = 9;
+    const R_IDX: usize = 10;
+    const P_IDX: usize = 14;
+    const SALT_IDX: usize = 18;
+    const NONCE_IDX: usize = SALT_IDX + SALT_SIZE;
+    const HEADER_SIZE: usize = NONCE_IDX + NONCE_SIZE;
+
+    /// Encrypts with a low scrypt cost, so tests run fast.
+    fn encrypt_fast(passphrase: &str) -> Vec<u8> {
+        encrypt_with_params(WALLET_DATA, passphrase, 4, 8, 1).unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let file_bytes = encrypt_fast("correct horse");
+        assert!(is_encrypted(&file_bytes));
+        assert_eq!(file_bytes[LOG_N_IDX], 4);
+        assert_eq!(file_bytes.len(), HEADER_SIZE + WALLET_DATA.len() + 16);
+        assert!(!file_bytes.windows(WALLET_DATA.len()).any(|window| window == WALLET_DATA));
+        assert_eq!(decrypt(&file_bytes, "correct horse").unwrap(), WALLET_DATA);
+        // salt and nonce are random
+        assert_ne!(encrypt_fast("correct horse"), file_bytes);
+    }
+
+    #[test]
+    fn wrong_passphrase() {
+        let file_bytes = encrypt_fast("correct horse");
+        assert!(matches!(decrypt(&file_bytes, "correct horse "),
+                         Err(WalletFileError::WrongPassphrase)));
+        assert!(matches!(decrypt(&file_bytes, ""), Err(WalletFileError::WrongPassphrase)));
+    }
+
+    #[test]
+    fn rejects_tampering() {
+        let file_bytes = encrypt_fast("correct horse");
+        // the whole header is authenticated, e.g. valid but different KDF parameters
+        for idx in &[LOG_N_IDX, R_IDX + 3, SALT_IDX, NONCE_IDX, NONCE_IDX + NONCE_SIZE - 1,
+                     HEADER_SIZE, file_bytes.len() - 1] {
+            let mut tampered = file_bytes.clone();
+            tampered[*idx] ^= 1;
+            assert!(matches!(decrypt(&tampered, "correct horse"),
+                             Err(WalletFileError::WrongPassphrase)), "{}", idx);
+        }
+        let mut tampered = file_bytes.clone();
+        tampered[WALLET_FILE_MAGIC.len()] = 2;
+        assert!(matches!(decrypt(&tampered, "correct horse"),
+                         Err(WalletFileError::UnsupportedVersion(2))));
+        assert!(matches!(decrypt(&file_bytes[..HEADER_SIZE - 1], "correct horse"),
+                         Err(WalletFileError::IoError(_))));
+        assert!(matches!(decrypt(&file_bytes[1..], "correct horse"),
+                         Err(WalletFileError::NotEncrypted)));
+    }
+
+    #[test]
+    fn rejects_excessive_kdf_params() {
+        let file_bytes = encrypt_fast("correct horse");
+        let mut log_n = file_bytes.clone();
+        log_n[LOG_N_IDX] = MAX_SCRYPT_LOG_N + 1;
+        let mut r = file_bytes.clone();
+        r[R_IDX..P_IDX].copy_from_slice(&(MAX_SCRYPT_R + 1).to_be_bytes());
+        let mut p = file_bytes.clone();
+        p[P_IDX..SALT_IDX].copy_from_slice(&(MAX_SCRYPT_P + 1).to_be_bytes());
+        for tampered in &[log_n, r, p] {
+            assert!(matches!(decrypt(tampered, "correct horse"),
+                             Err(WalletFileError::InvalidKdfParams)));
+        }
+    }
+
+    #[test]
+    fn write_atomic_replaces_file() {
+        let path = std::env::temp_dir()
+            .join(format!("slpagora-test-{}.wallet", std::process::id()));
+        write_atomic(&path, b"old").unwrap();
+        write_atomic(&path, b"new").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"new");
+        assert!(!path.with_extension("tmp").exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,133 @@
+use crate::address::{to_base58check, from_base58check, AddressError};
+use crate::network::Network;
+
+
+#[derive(Clone, Debug)]
+pub enum WifError {
+    InvalidBase58Check(AddressError),
+    InvalidLength(usize),
+    InvalidVersion(u8),
+    InvalidCompressionFlag(u8),
+    InvalidSecretKey,
+    /// The key's version byte belongs to another network than the expected one.
+    WrongNetwork(Network),
+}
+
+impl std::fmt::Display for WifError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for WifError {
+
+}
+
+/// A private key decoded from WIF. Testnet and regtest share the same version byte, so keys of
+/// both decode as testnet keys.
+#[derive(Clone, Copy, Debug)]
+pub struct WifKey {
+    pub secret_key: secp256k1::SecretKey,
+    pub network: Network,
+    pub is_compressed: bool,
+}
+
+pub fn to_wif(secret_key: &secp256k1::SecretKey, network: Network, is_compressed: bool) -> String {
+    let mut payload = vec![network.wif_version()];
+    payload.extend_from_slice(&secret_key[..]);
+    if is_compressed {
+        payload.push(0x01);
+    }
+    to_base58check(&payload)
+}
+
+pub fn from_wif(wif: &str) -> Result<WifKey, WifError> {
+    let payload = from_base58check(wif).map_err(WifError::InvalidBase58Check)?;
+    let is_compressed = match payload.len() {
+        33 => false,
+        34 if payload[33] == 0x01 => true,
+        34 => return Err(WifError::InvalidCompressionFlag(payload[33])),
+        len => return Err(WifError::InvalidLength(len)),
+    };
+    let network = match payload[0] {
+        version if version == Network::Mainnet.wif_version() => Network::Mainnet,
+        version if version == Network::Testnet.wif_version() => Network::Testnet,
+        version => return Err(WifError::InvalidVersion(version)),
+    };
+    let secret_key = secp256k1::SecretKey::from_slice(&payload[1..33])
+        .map_err(|_| WifError::InvalidSecretKey)?;
+    Ok(WifKey { secret_key, network, is_compressed })
+}
+
+impl WifKey {
+    /// Whether the key can be used on `network`, treating testnet and regtest as the same.
+    pub fn is_for_network(&self, network: Network) -> bool {
+        self.network.wif_version() == network.wif_version()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET_KEY: &str = "0c28fca386c7a227600b2fe50b7cae11ec86d3bf1fbe471be89827e19d72aa1d";
+    /// WIFs of SECRET_KEY: network, whether compressed, WIF.
+    const VECTORS: [(Network, bool, &str); 4] = [
+        (Network::Mainnet, false, "5HueCGU8rMjxEXxiPuD5BDku4MkFqeZyd4dZ1jvhTVqvbTLvyTJ"),
+        (Network::Mainnet, true, "KwdMAjGmerYanjeui5SHS7JkmpZvVipYvB2LJGU1ZxJwYvP98617"),
+        (Network::Testnet, false, "91gGn1HgSap6CbU12F6z3pJri26xzp7Ay1VW6NHCoEayNXwRpu2"),
+        (Network::Testnet, true, "cMzLdeGd5vEqxB8B6VFQoRopQ3sLAAvEzDAoQgvX54xwofSWj1fx"),
+    ];
+
+    fn payload(version: u8, suffix: &[u8]) -> String {
+        let mut payload = vec![version];
+        payload.extend_from_slice(&hex::decode(SECRET_KEY).unwrap());
+        payload.extend_from_slice(suffix);
+        to_base58check(&payload)
+    }
+
+    #[test]
+    fn known_wifs() {
+        let secret_key = secp256k1::SecretKey::from_slice(&hex::decode(SECRET_KEY).unwrap())
+            .unwrap();
+        for (network, is_compressed, wif) in VECTORS.iter() {
+            assert_eq!(to_wif(&secret_key, *network, *is_compressed), *wif);
+            let key = from_wif(wif).unwrap();
+            assert_eq!(key.secret_key, secret_key);
+            assert_eq!(key.network.wif_version(), network.wif_version());
+            assert_eq!(key.is_compressed, *is_compressed);
+        }
+        // regtest keys are testnet keys
+        assert_eq!(to_wif(&secret_key, Network::Regtest, true), VECTORS[3].2);
+        assert!(from_wif(VECTORS[3].2).unwrap().is_for_network(Network::Regtest));
+        assert!(!from_wif(VECTORS[3].2).unwrap().is_for_network(Network::Mainnet));
+    }
+
+    #[test]
+    fn rejects_bad_checksum() {
+        assert!(matches!(from_wif("5HueCGU8rMjxEXxiPuD5BDku4MkFqeZyd4dZ1jvhTVqvbTLvyTK"),
+                         Err(WifError::InvalidBase58Check(AddressError::InvalidChecksum))));
+        assert!(matches!(from_wif("KwdMAjGmerYanjeui5SHS7JkmpZvVipYvB2LJGU1ZxJwYvP98618"),
+                         Err(WifError::InvalidBase58Check(AddressError::InvalidChecksum))));
+    }
+
+    #[test]
+    fn rejects_wrong_version() {
+        assert!(matches!(from_wif(&payload(0x00, &[])), Err(WifError::InvalidVersion(0x00))));
+        assert!(matches!(from_wif(&payload(0x6f, &[0x01])), Err(WifError::InvalidVersion(0x6f))));
+    }
+
+    #[test]
+    fn rejects_wrong_length() {
+        let mut truncated = from_base58check(&payload(0x80, &[])).unwrap();
+        truncated.pop();
+        assert!(matches!(from_wif(&to_base58check(&truncated)), Err(WifError::InvalidLength(32))));
+        assert!(matches!(from_wif(&payload(0x80, &[0x01, 0x00])),
+                         Err(WifError::InvalidLength(35))));
+        assert!(matches!(from_wif(&payload(0x80, &[0x02])),
+                         Err(WifError::InvalidCompressionFlag(0x02))));
+        let mut zero_key = vec![0x80];
+        zero_key.extend_from_slice(&[0; 32]);
+        assert!(matches!(from_wif(&to_base58check(&zero_key)), Err(WifError::InvalidSecretKey)));
+    }
+}